@@ -0,0 +1,280 @@
+//! Arbitrary output variables: images of what each pixel's camera rays first hit, alongside the
+//! rendered colour, for compositing and denoising.
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use crate::{Colour, Point3, Vec3};
+
+/// What a single camera sample saw at its first hit, or didn't.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AovSample {
+    /// The first hit, if the ray hit anything.
+    pub hit: Option<AovHit>,
+    /// The hit material's albedo, or the background seen when the ray missed.
+    pub albedo: Colour,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AovHit {
+    /// Distance from the camera along its viewing direction (not along the ray).
+    pub depth: f64,
+    pub normal: Vec3,
+    pub position: Point3,
+    pub object_id: u32,
+    pub material_id: u32,
+}
+
+/// AOV samples accumulated for one pixel.
+///
+/// Unlike colours these aren't spread by the reconstruction filter, so that edges stay sharp and
+/// IDs don't bleed into neighbouring pixels. Depth, normal and position are averaged over the
+/// samples that hit something; the IDs are those of the pixel's first sample.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AovPixel {
+    pub samples: usize,
+    pub hits: usize,
+    pub depth: f64,
+    pub normal: Vec3,
+    pub position: Point3,
+    pub albedo: Colour,
+    pub object_id: Option<u32>,
+    pub material_id: Option<u32>,
+}
+
+impl AovPixel {
+    /// Add a sample; `first` says whether it's the pixel's first sample, which sets the IDs.
+    pub fn add_sample(&mut self, sample: &AovSample, first: bool) {
+        self.samples += 1;
+        self.albedo += sample.albedo;
+        if let Some(hit) = sample.hit {
+            self.hits += 1;
+            self.depth += hit.depth;
+            self.normal += hit.normal;
+            self.position += hit.position;
+            if first {
+                self.object_id = Some(hit.object_id);
+                self.material_id = Some(hit.material_id);
+            }
+        }
+    }
+
+    /// Combine with the same pixel accumulated elsewhere.
+    pub fn merge(&mut self, other: &AovPixel) {
+        self.samples += other.samples;
+        self.hits += other.hits;
+        self.depth += other.depth;
+        self.normal += other.normal;
+        self.position += other.position;
+        self.albedo += other.albedo;
+        self.object_id = self.object_id.or(other.object_id);
+        self.material_id = self.material_id.or(other.material_id);
+    }
+
+    /// The value of one of the AOVs, with unused channels zero. Depth is infinite where nothing
+    /// was hit, and IDs are -1.
+    pub fn value(&self, aov: Aov) -> Vec3 {
+        let hit_mean = |sum: Vec3| match self.hits {
+            0 => Vec3::default(),
+            n => sum / n as f64,
+        };
+        let id = |id: Option<u32>| Vec3::new(id.map_or(-1.0, f64::from), 0.0, 0.0);
+        match aov {
+            Aov::Depth => match self.hits {
+                0 => Vec3::new(f64::INFINITY, 0.0, 0.0),
+                n => Vec3::new(self.depth / n as f64, 0.0, 0.0),
+            },
+            Aov::Normal => {
+                let normal = hit_mean(self.normal);
+                match normal.length() {
+                    0.0 => normal,
+                    length => normal / length,
+                }
+            }
+            Aov::Position => hit_mean(self.position),
+            Aov::Albedo => match self.samples {
+                0 => Colour::default(),
+                n => self.albedo / n as f64,
+            },
+            Aov::ObjectId => id(self.object_id),
+            Aov::MaterialId => id(self.material_id),
+        }
+    }
+}
+
+/// One of the output variables that can be written as an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aov {
+    Depth,
+    Normal,
+    Albedo,
+    Position,
+    ObjectId,
+    MaterialId,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 6] = [
+        Aov::Depth,
+        Aov::Normal,
+        Aov::Albedo,
+        Aov::Position,
+        Aov::ObjectId,
+        Aov::MaterialId,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::Position => "position",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+        }
+    }
+
+    /// 1 for scalars, 3 for vectors and colours.
+    pub fn channels(&self) -> usize {
+        match self {
+            Aov::Depth | Aov::ObjectId | Aov::MaterialId => 1,
+            Aov::Normal | Aov::Albedo | Aov::Position => 3,
+        }
+    }
+}
+
+impl fmt::Display for Aov {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Aov {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Aov::ALL
+            .into_iter()
+            .find(|aov| aov.name() == s)
+            .ok_or_else(|| format!("unknown AOV: {s}"))
+    }
+}
+
+/// Write a portable float map, a minimal uncompressed format for floating point images that
+/// most compositors and image tools read. `values` are in row order from the top of the image;
+/// only the first `channels` (1 or 3) of each are written.
+pub fn write_pfm(
+    out: &mut dyn Write,
+    width: usize,
+    height: usize,
+    channels: usize,
+    values: &[Vec3],
+) -> io::Result<()> {
+    assert!(channels == 1 || channels == 3);
+    assert_eq!(values.len(), width * height);
+    let kind = if channels == 1 { "Pf" } else { "PF" };
+    // A negative scale means little endian.
+    write!(out, "{kind}\n{width} {height}\n-1.0\n")?;
+    // Rows run from the bottom of the image up.
+    for row in values.chunks(width).rev() {
+        for value in row {
+            for channel in [value.x, value.y, value.z].iter().take(channels) {
+                out.write_all(&(*channel as f32).to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn hit(depth: f64, id: u32) -> AovSample {
+        AovSample {
+            hit: Some(AovHit {
+                depth,
+                normal: Vec3::new(0.0, 1.0, 0.0),
+                position: Point3::new(0.0, 0.0, -depth),
+                object_id: id,
+                material_id: id + 10,
+            }),
+            albedo: Colour::new(0.5, 0.5, 0.5),
+        }
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_pixel_averages_hits_and_keeps_first_ids() {
+        let miss = AovSample {
+            hit: None,
+            albedo: Colour::new(1.0, 1.0, 1.0),
+        };
+        let mut pixel = AovPixel::default();
+        pixel.add_sample(&hit(2.0, 1), true);
+        pixel.add_sample(&miss, false);
+        pixel.add_sample(&hit(4.0, 2), false);
+
+        assert_eq!(pixel.value(Aov::Depth).x, 3.0);
+        assert_eq!(pixel.value(Aov::Normal), Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(pixel.value(Aov::Position), Point3::new(0.0, 0.0, -3.0));
+        assert_eq!(pixel.value(Aov::Albedo), Colour::new(2.0, 2.0, 2.0) / 3.0);
+        assert_eq!(pixel.value(Aov::ObjectId).x, 1.0);
+        assert_eq!(pixel.value(Aov::MaterialId).x, 11.0);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_empty_pixel_has_no_depth_or_ids() {
+        let pixel = AovPixel::default();
+        assert_eq!(pixel.value(Aov::Depth).x, f64::INFINITY);
+        assert_eq!(pixel.value(Aov::ObjectId).x, -1.0);
+        assert_eq!(pixel.value(Aov::Normal), Vec3::default());
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_merge_matches_single_pixel() {
+        let mut whole = AovPixel::default();
+        let (mut a, mut b) = (AovPixel::default(), AovPixel::default());
+        for (index, sample) in [hit(1.0, 3), hit(2.0, 4), hit(6.0, 5)].iter().enumerate() {
+            whole.add_sample(sample, index == 0);
+            let part = if index < 2 { &mut a } else { &mut b };
+            part.add_sample(sample, index == 0);
+        }
+        b.merge(&a);
+        assert_eq!(b, whole);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_aov_names_round_trip() {
+        for aov in Aov::ALL {
+            assert_eq!(aov.name().parse::<Aov>(), Ok(aov));
+        }
+        assert!("beauty".parse::<Aov>().is_err());
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_write_pfm() {
+        let values = [
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(3.0, 0.0, 0.0),
+            Vec3::new(4.0, 0.0, 0.0),
+        ];
+        let mut out = Vec::new();
+        write_pfm(&mut out, 2, 2, 1, &values).unwrap();
+
+        let header = b"Pf\n2 2\n-1.0\n";
+        assert_eq!(&out[..header.len()], header);
+        let floats: Vec<f32> = out[header.len()..]
+            .chunks(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        // Bottom row first.
+        assert_eq!(floats, vec![3.0, 4.0, 1.0, 2.0]);
+    }
+}
@@ -1,25 +1,440 @@
+use std::borrow::Cow;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::thread;
+use std::time::{Duration, Instant};
+
 use raytraceweekend::Vec3;
-use raytraceweekend::camera::Camera;
+use raytraceweekend::aov::Aov;
+use raytraceweekend::budget::{Budget, CancelToken, StopReason};
+use raytraceweekend::camera::{AdaptiveSampling, Camera};
+use raytraceweekend::checkpoint::Checkpoint;
+use raytraceweekend::denoise::Denoiser;
+use raytraceweekend::distributed;
+use raytraceweekend::film::Film;
+use raytraceweekend::filter;
 use raytraceweekend::hit::HittableList;
+use raytraceweekend::integrator;
+use raytraceweekend::progress::{IndicatifProgress, LogProgress, NoProgress, ProgressObserver};
+use raytraceweekend::sampler::SamplerKind;
+use raytraceweekend::scene;
 use raytraceweekend::sphere::Sphere;
 
-fn main() {
-    env_logger::init();
+const ASPECT_RATIO: f64 = 16.0 / 9.0;
+
+/// Samples per pixel in each pass, unless overridden with --progressive.
+const DEFAULT_PASS_SAMPLES: usize = 16;
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: rt [--scene FILE] [--width N] [--threads N]\n\
+         \x20         [--sampler independent|stratified|halton|sobol|pmj] [--samples N]\n\
+         \x20         [--filter box|tent|gaussian|mitchell|lanczos]\n\
+         \x20         [--integrator path|spectral|normals|uv|front-face|depth|ao|cost]\n\
+         \x20         [--seed N] [--debug-pixel X,Y[,SAMPLE]]\n\
+         \x20         [--adaptive THRESHOLD --max-samples N] [--heatmap FILE]\n\
+         \x20         [--aovs PREFIX] [--denoise]\n\
+         \x20         [--time-budget SECONDS] [--noise-target RELATIVE_ERROR]\n\
+         \x20         [--progress bar|log|none]\n\
+         \x20         [--output FILE [--progressive SAMPLES_PER_PASS]]\n\
+         \x20         [--checkpoint FILE [--checkpoint-interval SECONDS]] [--resume FILE]\n\
+         \x20         [--coordinator LISTEN_ADDRESS [--tile-timeout SECONDS] | --worker ADDRESS]"
+    );
+    std::process::exit(2);
+}
+
+fn parse<T: std::str::FromStr>(flag: &str, value: Option<String>) -> T {
+    match value.map(|v| v.parse()) {
+        Some(Ok(value)) => value,
+        _ => {
+            eprintln!("{flag}: missing or invalid value");
+            usage();
+        }
+    }
+}
+
+/// The film to write out as an image. Images are denoised as they're written, while checkpoints,
+/// AOVs and heatmaps stay as rendered.
+fn finish(film: &Film, denoise: bool) -> Cow<'_, Film> {
+    if denoise {
+        Cow::Owned(Denoiser::default().denoise(film))
+    } else {
+        Cow::Borrowed(film)
+    }
+}
+
+/// Write the image to `path` by way of a temporary file, so that anything watching the output
+/// never sees a partially written image.
+fn write_image(film: &Film, path: &str) -> io::Result<()> {
+    let partial = format!("{path}.partial");
+    let mut out = BufWriter::new(File::create(&partial)?);
+    film.write_ppm(&mut out)?;
+    out.flush()?;
+    drop(out);
+    fs::rename(partial, path)
+}
+
+/// Everything set on the command line.
+struct Options {
+    scene: Option<PathBuf>,
+    image_width: usize,
+    samples_per_pixel: usize,
+    sampler: SamplerKind,
+    adaptive_threshold: Option<f64>,
+    max_samples: Option<usize>,
+    heatmap: Option<String>,
+    aovs: Option<String>,
+    denoise: bool,
+    filter_name: String,
+    integrator_name: String,
+    seed: u64,
+    debug_pixel: Option<String>,
+    progressive: Option<usize>,
+    output: Option<String>,
+    checkpoint: Option<PathBuf>,
+    checkpoint_interval: u64,
+    resume: Option<PathBuf>,
+    coordinator: Option<String>,
+    worker: Option<String>,
+    tile_timeout: u64,
+    time_budget: Option<u64>,
+    noise_target: Option<f64>,
+    progress: String,
+    threads: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            scene: None,
+            image_width: 3840,
+            samples_per_pixel: 100,
+            sampler: SamplerKind::Sobol,
+            adaptive_threshold: None,
+            max_samples: None,
+            heatmap: None,
+            aovs: None,
+            denoise: false,
+            filter_name: String::from("box"),
+            integrator_name: String::from("path"),
+            seed: 0,
+            debug_pixel: None,
+            progressive: None,
+            output: None,
+            checkpoint: None,
+            checkpoint_interval: 600,
+            resume: None,
+            coordinator: None,
+            worker: None,
+            tile_timeout: 600,
+            time_budget: None,
+            noise_target: None,
+            progress: String::from("bar"),
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+}
 
-    // Image
-    let aspect_ratio: f64 = 16.0 / 9.0;
-    let image_width: usize = 3840;
-    let samples_per_pixel: usize = 100;
+impl Options {
+    fn parse(args: impl IntoIterator<Item = String>) -> Self {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--scene" => options.scene = Some(parse(&arg, args.next())),
+                "--width" => options.image_width = parse(&arg, args.next()),
+                "--threads" => options.threads = parse(&arg, args.next()),
+                "--sampler" => options.sampler = parse(&arg, args.next()),
+                "--samples" => options.samples_per_pixel = parse(&arg, args.next()),
+                "--adaptive" => options.adaptive_threshold = Some(parse(&arg, args.next())),
+                "--max-samples" => options.max_samples = Some(parse(&arg, args.next())),
+                "--filter" => options.filter_name = parse(&arg, args.next()),
+                "--integrator" => options.integrator_name = parse(&arg, args.next()),
+                "--seed" => options.seed = parse(&arg, args.next()),
+                "--debug-pixel" => options.debug_pixel = Some(parse(&arg, args.next())),
+                "--progressive" => options.progressive = Some(parse(&arg, args.next())),
+                "--output" => options.output = Some(parse(&arg, args.next())),
+                "--checkpoint" => options.checkpoint = Some(parse(&arg, args.next())),
+                "--checkpoint-interval" => options.checkpoint_interval = parse(&arg, args.next()),
+                "--resume" => options.resume = Some(parse(&arg, args.next())),
+                "--coordinator" => options.coordinator = Some(parse(&arg, args.next())),
+                "--worker" => options.worker = Some(parse(&arg, args.next())),
+                "--tile-timeout" => options.tile_timeout = parse(&arg, args.next()),
+                "--time-budget" => options.time_budget = Some(parse(&arg, args.next())),
+                "--noise-target" => options.noise_target = Some(parse(&arg, args.next())),
+                "--progress" => options.progress = parse(&arg, args.next()),
+                "--heatmap" => options.heatmap = Some(parse(&arg, args.next())),
+                "--aovs" => options.aovs = Some(parse(&arg, args.next())),
+                "--denoise" => options.denoise = true,
+                _ => usage(),
+            }
+        }
+        options
+    }
+
+    /// The camera to render with. Everything that affects the image is set here, so that a
+    /// coordinator and its workers, run with the same options, build cameras with the same
+    /// fingerprint.
+    fn camera(&self) -> Camera {
+        let filter = match filter::from_name(&self.filter_name) {
+            Ok(filter) => filter,
+            Err(err) => {
+                eprintln!("{err}");
+                usage();
+            }
+        };
+
+        let integrator = match integrator::from_name(&self.integrator_name) {
+            Ok(integrator) => integrator,
+            Err(err) => {
+                eprintln!("{err}");
+                usage();
+            }
+        };
+
+        let mut camera = Camera::new(ASPECT_RATIO, self.image_width, self.samples_per_pixel)
+            .with_sampler(self.sampler)
+            .with_filter(filter)
+            .with_integrator(integrator)
+            .with_seed(self.seed)
+            .with_threads(self.threads);
+        // The denoiser is guided by the AOVs.
+        if self.aovs.is_some() || self.denoise {
+            camera = camera.with_aovs();
+        }
+        if let Some(threshold) = self.adaptive_threshold {
+            camera = camera.with_adaptive_sampling(AdaptiveSampling {
+                threshold,
+                max_samples: self.max_samples.unwrap_or(self.samples_per_pixel * 8),
+            });
+        }
+        // Render in passes, so that stopping early (Ctrl-C or a budget) still leaves an evenly
+        // sampled image, and so that checkpoints can be taken between them.
+        let pass_samples = self
+            .progressive
+            .unwrap_or(DEFAULT_PASS_SAMPLES.min(self.samples_per_pixel));
+        camera.with_pass_samples(pass_samples)
+    }
+}
+
+fn main() -> io::Result<()> {
+    env_logger::init();
+    let options = Options::parse(std::env::args().skip(1));
 
     // World
     log::info!("Initialising the world");
+    let setup_start = Instant::now();
+
+    let world: HittableList = match &options.scene {
+        Some(path) => match scene::load(path) {
+            Ok(scene) => scene.world,
+            Err(err) => {
+                eprintln!("{}: {err}", path.display());
+                std::process::exit(1);
+            }
+        },
+        None => {
+            let mut world = HittableList::new();
+            world.add(Box::new(Sphere::new(Vec3::new(0.0, 0.0, -1.0), 0.5)));
+            world.add(Box::new(Sphere::new(Vec3::new(0.0, -100.5, -1.0), 100.0)));
+            world
+        }
+    };
+
+    let progress: Arc<dyn ProgressObserver> = match options.progress.as_str() {
+        "bar" => Arc::new(IndicatifProgress::new()),
+        "log" => Arc::new(LogProgress::new(Duration::from_secs(10))),
+        "none" => Arc::new(NoProgress),
+        other => {
+            eprintln!("unknown progress reporter: {other}");
+            usage();
+        }
+    };
 
-    let mut world: HittableList = HittableList::new();
-    world.add(Box::new(Sphere::new(Vec3::new(0.0, 0.0, -1.0), 0.5)));
-    world.add(Box::new(Sphere::new(Vec3::new(0.0, -100.5, -1.0), 100.0)));
+    let camera = options.camera().with_progress(progress);
+    let setup_time = setup_start.elapsed();
+    if let Some(spec) = &options.debug_pixel {
+        let values: Vec<usize> = match spec.split(',').map(str::parse).collect() {
+            Ok(values) => values,
+            Err(_) => {
+                eprintln!("--debug-pixel: expected X,Y or X,Y,SAMPLE");
+                usage();
+            }
+        };
+        let (i, j, sample) = match values[..] {
+            [i, j] => (i, j, 0),
+            [i, j, sample] => (i, j, sample),
+            _ => {
+                eprintln!("--debug-pixel: expected X,Y or X,Y,SAMPLE");
+                usage();
+            }
+        };
+        println!(
+            "{}",
+            camera.trace_pixel_sample(&world, i, j, sample).to_json()
+        );
+        return Ok(());
+    }
+    if let Some(address) = &options.worker {
+        // Each connection renders one tile at a time, so open one per thread.
+        log::info!("Working for {address} on {} threads", options.threads);
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..options.threads)
+                .map(|_| scope.spawn(|| distributed::work(&camera, &world, address.as_str())))
+                .collect();
+            workers
+                .into_iter()
+                .try_for_each(|worker| worker.join().unwrap())
+        })?;
+        log::info!("Done");
+        return Ok(());
+    }
+    if options.coordinator.is_some()
+        && (options.progressive.is_some()
+            || options.checkpoint.is_some()
+            || options.resume.is_some()
+            || options.noise_target.is_some())
+    {
+        eprintln!(
+            "--coordinator can't be combined with --progressive, --checkpoint, --resume or \
+             --noise-target"
+        );
+        usage();
+    }
+    if options.progressive.is_some() && options.output.is_none() {
+        eprintln!("--progressive needs --output to write each pass to");
+        usage();
+    }
+    let fingerprint = camera.fingerprint();
+    let checkpoint_interval = Duration::from_secs(options.checkpoint_interval);
+    let mut last_checkpoint = Instant::now();
+    let on_pass = |film: &Film, samples: usize| {
+        if options.progressive.is_some()
+            && let Some(path) = &options.output
+        {
+            log::info!("Writing {path} after {samples} samples per pixel");
+            if let Err(err) = write_image(&finish(film, options.denoise), path) {
+                log::error!("Failed to write {path}: {err}");
+            }
+        }
+        if let Some(path) = &options.checkpoint
+            && last_checkpoint.elapsed() >= checkpoint_interval
+        {
+            log::info!(
+                "Checkpointing to {} after {samples} samples per pixel",
+                path.display()
+            );
+            match Checkpoint::save(path, film, samples, fingerprint) {
+                Ok(()) => last_checkpoint = Instant::now(),
+                Err(err) => log::error!("Failed to write checkpoint {}: {err}", path.display()),
+            }
+        }
+    };
 
-    let camera: Camera = Camera::new(aspect_ratio, image_width, samples_per_pixel);
-    camera.render(&world);
+    // Ctrl-C stops the render and writes out what we have so far; a second one exits at once.
+    let interrupted = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register_conditional_shutdown(
+        signal_hook::consts::SIGINT,
+        1,
+        interrupted.clone(),
+    )?;
+    signal_hook::flag::register(signal_hook::consts::SIGINT, interrupted.clone())?;
+    let budget = Budget {
+        time: options.time_budget.map(Duration::from_secs),
+        noise: options.noise_target,
+        cancel: Some(CancelToken::from(interrupted)),
+    };
+
+    // Remote tiles aren't counted, so a coordinator has no statistics to report.
+    let (film, stop, stats) = match &options.coordinator {
+        Some(address) => distributed::coordinate(
+            &camera,
+            TcpListener::bind(address)?,
+            Duration::from_secs(options.tile_timeout),
+            &budget,
+        )
+        .map(|(film, stop)| (film, stop, None))?,
+        None => {
+            let result = match &options.resume {
+                Some(path) => {
+                    log::info!("Resuming from {}", path.display());
+                    camera.resume(&world, Checkpoint::load(path)?, &budget, on_pass)?
+                }
+                None => camera.render_within(&world, &budget, on_pass),
+            };
+            // Otherwise whatever was rendered since the last checkpoint is lost.
+            if result.stop != StopReason::Completed
+                && let Some(path) = &options.checkpoint
+            {
+                log::info!(
+                    "Checkpointing to {} after {} samples per pixel",
+                    path.display(),
+                    result.samples
+                );
+                if let Err(err) =
+                    Checkpoint::save(path, result.resumable(), result.samples, fingerprint)
+                {
+                    log::error!("Failed to write checkpoint {}: {err}", path.display());
+                }
+            }
+            (result.film, result.stop, Some(result.stats))
+        }
+    };
+    if !matches!(stop, StopReason::Completed | StopReason::Converged) {
+        log::info!("Render stopped early: {stop:?}");
+    }
+
+    let output_start = Instant::now();
+    match &options.output {
+        Some(path) => write_image(&finish(&film, options.denoise), path)?,
+        None => {
+            finish(&film, options.denoise).write_ppm(&mut BufWriter::new(io::stdout().lock()))?
+        }
+    }
+    if let Some(path) = options.heatmap {
+        film.write_sample_heatmap(&mut BufWriter::new(File::create(path)?))?;
+    }
+    if let Some(prefix) = &options.aovs {
+        for aov in Aov::ALL {
+            let path = format!("{prefix}.{aov}.pfm");
+            log::info!("Writing {path}");
+            film.write_aov(aov, &mut BufWriter::new(File::create(path)?))?;
+        }
+    }
+
+    // To stderr, as the image may be going to stdout.
+    if let Some(stats) = stats {
+        eprintln!("Setup time:       {setup_time:.3?}");
+        eprintln!("{stats}");
+        eprintln!("Output time:      {:.3?}", output_start.elapsed());
+    }
 
     log::info!("Done");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn options(args: &str) -> Options {
+        Options::parse(args.split_whitespace().map(String::from))
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case("")]
+    #[case("--samples 16")]
+    #[case("--samples 32")]
+    #[case("--samples 40 --sampler pmj --adaptive 0.05")]
+    fn test_coordinator_and_worker_cameras_match(#[case] args: &str) {
+        let coordinator = options(&format!("--coordinator 127.0.0.1:0 {args}")).camera();
+        let worker = options(&format!("--worker 127.0.0.1:0 --threads 3 {args}")).camera();
+        assert_eq!(coordinator.fingerprint(), worker.fingerprint());
+    }
 }
@@ -0,0 +1,129 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::film::Film;
+use crate::stats::RenderStats;
+
+/// A flag that asks a render in progress to stop. Clones share the same flag, so one can be
+/// handed to a signal handler or another thread while the render holds the other.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl From<Arc<AtomicBool>> for CancelToken {
+    /// Wrap an existing flag, e.g. one registered with `signal_hook::flag`.
+    fn from(flag: Arc<AtomicBool>) -> Self {
+        Self(flag)
+    }
+}
+
+/// Limits on how long a render may run, beyond the camera's sample counts.
+///
+/// Time and cancellation are checked between pixels, so a render stops promptly even part way
+/// through a pass. The noise target is checked between passes, against the mean of the pixels'
+/// relative errors (see `Pixel::relative_error`).
+#[derive(Debug, Clone, Default)]
+pub struct Budget {
+    pub time: Option<Duration>,
+    pub noise: Option<f64>,
+    pub cancel: Option<CancelToken>,
+}
+
+impl Budget {
+    /// Why the render should stop now, if it should, given when it has to finish by.
+    pub(crate) fn interrupted(&self, deadline: Option<Instant>) -> Option<StopReason> {
+        if self.cancel.as_ref().is_some_and(|c| c.is_cancelled()) {
+            return Some(StopReason::Cancelled);
+        }
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            return Some(StopReason::TimeBudget);
+        }
+        None
+    }
+}
+
+/// Why a render finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// Every pass was rendered.
+    Completed,
+    /// Adaptive sampling found every pixel converged.
+    Converged,
+    /// The image reached the budget's noise target.
+    NoiseTarget,
+    /// The time budget ran out.
+    TimeBudget,
+    /// The budget's cancel token was triggered.
+    Cancelled,
+}
+
+/// The outcome of a render with a `Budget`.
+#[derive(Debug)]
+pub struct RenderResult {
+    /// The image so far. If the render was interrupted part way through a pass some pixels will
+    /// have more samples than others, but each is normalised by its own sample weights.
+    pub film: Film,
+    /// If the render was interrupted part way through a pass, the film as it was before that
+    /// pass. See `resumable`.
+    pub last_pass: Option<Film>,
+    /// Samples per pixel taken by the last pass that ran to completion.
+    pub samples: usize,
+    pub stop: StopReason,
+    /// Statistics for this run only; a resumed render doesn't include the earlier runs'.
+    pub stats: RenderStats,
+}
+
+impl RenderResult {
+    /// The film holding exactly `samples` samples per pixel, to checkpoint and resume from.
+    pub fn resumable(&self) -> &Film {
+        self.last_pass.as_ref().unwrap_or(&self.film)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_cancel_token_is_shared_between_clones() {
+        let token = CancelToken::new();
+        let clone = token.clone();
+        assert!(!clone.is_cancelled());
+        token.cancel();
+        assert!(clone.is_cancelled());
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_budget_interrupted() {
+        let token = CancelToken::new();
+        let budget = Budget {
+            cancel: Some(token.clone()),
+            ..Budget::default()
+        };
+        let past = Instant::now() - Duration::from_secs(1);
+        let future = Instant::now() + Duration::from_secs(60);
+
+        assert_eq!(budget.interrupted(None), None);
+        assert_eq!(budget.interrupted(Some(future)), None);
+        assert_eq!(budget.interrupted(Some(past)), Some(StopReason::TimeBudget));
+        token.cancel();
+        assert_eq!(budget.interrupted(Some(past)), Some(StopReason::Cancelled));
+    }
+}
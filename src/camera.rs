@@ -1,19 +1,57 @@
-use indicatif::{ProgressIterator, ProgressStyle};
+use std::collections::BTreeMap;
+use std::io;
+use std::mem;
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, mpsc};
+use std::thread;
+use std::time::Instant;
 
-use crate::hit::{Hittable, HittableList};
+use crate::aov::{AovHit, AovSample};
+use crate::budget::{Budget, RenderResult, StopReason};
+use crate::checkpoint::Checkpoint;
+use crate::film::{Bounds, Film};
+use crate::filter::{BoxFilter, Filter};
+use crate::hit::{HitRecord, HittableList};
+use crate::integrator::{Integrator, PathIntegrator};
+use crate::path_debug::PathDump;
+use crate::progress::{NoProgress, ProgressObserver, ProgressUpdate};
 use crate::ray::Ray;
-use crate::{Colour, Point3, Vec3, random_f64, random_on_hemisphere, unit_vector};
+use crate::sampler::{Sampler, SamplerKind, SamplerSetup, hash};
+use crate::stats::RenderStats;
+use crate::{Point3, Vec3, dot};
 
 #[derive(Debug)]
 pub struct Camera {
     image_width: usize, // rendered image width in pixel count
     image_height: usize,
     centre: Point3,
+    forward: Vec3,
     pixel00_loc: Point3,
     pixel_delta_u: Vec3,
     pixel_delta_v: Vec3,
     samples_per_pixel: usize,
-    pixel_samples_scale: f64,
+    sampler: SamplerKind,
+    seed: u64,
+    adaptive: Option<AdaptiveSampling>,
+    filter: Arc<dyn Filter>,
+    threads: usize,
+    pass_samples: Option<usize>,
+    progress: Arc<dyn ProgressObserver>,
+    aovs: bool,
+    integrator: Arc<dyn Integrator>,
+}
+
+/// Width and height of the square tiles the image is split into for rendering.
+const TILE_SIZE: usize = 32;
+
+/// Settings for adaptive sampling. Pixels are sampled in passes (of `samples_per_pixel`, unless
+/// rendering progressively), and stop once the 95% confidence interval of their mean luminance is
+/// within `threshold` of the mean, or once they reach `max_samples`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptiveSampling {
+    pub threshold: f64,
+    pub max_samples: usize,
 }
 
 impl Default for Camera {
@@ -57,69 +95,551 @@ impl Camera {
             centre - Vec3::new(0.0, 0.0, focal_length) - viewport_u / 2.0 - viewport_v / 2.0;
         let pixel00_loc = viewport_upper_left + 0.5 * (pixel_delta_u + pixel_delta_v);
         log::debug!("pixel00_loc: {pixel00_loc:?}");
-        let pixel_samples_scale = 1.0 / samples_per_pixel as f64;
 
         Self {
             image_width,
             image_height,
             centre,
+            forward: Vec3::new(0.0, 0.0, -1.0),
             pixel00_loc,
             pixel_delta_u,
             pixel_delta_v,
             samples_per_pixel,
-            pixel_samples_scale,
+            sampler: SamplerKind::Independent,
+            seed: 0,
+            adaptive: None,
+            filter: Arc::new(BoxFilter::default()),
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            pass_samples: None,
+            progress: Arc::new(NoProgress),
+            aovs: false,
+            integrator: Arc::new(PathIntegrator::default()),
         }
     }
 
-    fn ray_colour(r: &Ray, world: &HittableList, depth: isize) -> Colour {
-            if depth <= 0 {
-        return Colour::new(0.0, 0.0, 0.0);
+    /// Choose how pixel and scattering samples are generated.
+    pub fn with_sampler(mut self, sampler: SamplerKind) -> Self {
+        self.sampler = sampler;
+        self
+    }
+
+    /// Seed for the sampler; the same seed, scene and settings render the same image.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Keep sampling noisy pixels beyond `samples_per_pixel`, see `AdaptiveSampling`.
+    pub fn with_adaptive_sampling(mut self, adaptive: AdaptiveSampling) -> Self {
+        self.adaptive = Some(adaptive);
+        self
+    }
+
+    /// The filter used to reconstruct pixels from their samples. Defaults to a box filter.
+    pub fn with_filter(mut self, filter: Arc<dyn Filter>) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Number of threads to render tiles on. Defaults to the available parallelism.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Render progressively, in passes of `pass_samples` samples per pixel over the whole image,
+    /// rather than finishing each pixel before moving on. See `render_progressive`.
+    pub fn with_pass_samples(mut self, pass_samples: usize) -> Self {
+        self.pass_samples = Some(pass_samples.max(1));
+        self
+    }
+
+    /// Where to report progress while rendering. Defaults to `NoProgress`.
+    pub fn with_progress(mut self, progress: Arc<dyn ProgressObserver>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    pub fn progress(&self) -> &dyn ProgressObserver {
+        self.progress.as_ref()
+    }
+
+    /// How to compute the colour seen along each camera ray. Defaults to `PathIntegrator`; the
+    /// others in `integrator` are for diagnosing scenes.
+    pub fn with_integrator(mut self, integrator: Arc<dyn Integrator>) -> Self {
+        self.integrator = integrator;
+        self
+    }
+
+    /// Also collect AOVs from each camera ray's first hit into the film; see `Film::write_aov`.
+    pub fn with_aovs(mut self) -> Self {
+        self.aovs = true;
+        self
     }
-        // Find the first object that intersects the ray, and return those details
-        if let Some(hit_record) = world.hit(r, &(0.001..f64::INFINITY)) {
-            let direction = random_on_hemisphere(&hit_record.normal);
-            return 0.5 * Camera::ray_colour(&Ray::new(hit_record.p, direction), world, depth - 1);
-            // return 0.5 * (hit_record.normal + Colour::new(1.0, 1.0, 1.0));
+
+    pub fn render(&self, world: &HittableList) -> Film {
+        self.render_progressive(world, |_, _| {})
+    }
+
+    /// Render the image in passes, calling `on_pass` with the accumulated film and the number of
+    /// samples per pixel taken so far after each one.
+    ///
+    /// Without `with_pass_samples` each pass is `samples_per_pixel` samples, so a plain render is
+    /// a single pass and an adaptive one carries on in passes until every pixel has converged.
+    pub fn render_progressive(
+        &self,
+        world: &HittableList,
+        on_pass: impl FnMut(&Film, usize),
+    ) -> Film {
+        self.render_within(world, &Budget::default(), on_pass).film
+    }
+
+    /// As `render_progressive`, but stopping early if the `budget` runs out. Whatever has been
+    /// rendered by then is returned rather than lost.
+    pub fn render_within(
+        &self,
+        world: &HittableList,
+        budget: &Budget,
+        on_pass: impl FnMut(&Film, usize),
+    ) -> RenderResult {
+        self.render_from(world, self.new_film(), 0, budget, on_pass)
+    }
+
+    /// Continue a progressive render from a checkpoint written by an earlier run with the same
+    /// camera settings and scene. The result matches what the uninterrupted render would produce.
+    ///
+    /// Only the camera settings are checked, by `fingerprint`; resuming with another scene
+    /// blends the two.
+    pub fn resume(
+        &self,
+        world: &HittableList,
+        checkpoint: Checkpoint,
+        budget: &Budget,
+        on_pass: impl FnMut(&Film, usize),
+    ) -> io::Result<RenderResult> {
+        if checkpoint.fingerprint != self.fingerprint() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "checkpoint was written with different camera settings",
+            ));
+        }
+        // A corrupt checkpoint could still carry the right fingerprint.
+        let film = &checkpoint.film;
+        if (film.width, film.height) != (self.image_width, self.image_height)
+            || film.bounds() != self.image_bounds()
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "checkpoint film doesn't cover the image",
+            ));
         }
-        // No objects were found, so.. continue to the horizon.
-        let unit_direction = unit_vector(&r.direction);
-        let a = 0.5 * (unit_direction.y + 1.0);
-        (1.0 - a) * Colour::new(1.0, 1.0, 1.0) + a * Colour::new(0.5, 0.7, 1.0)
+        Ok(self.render_from(world, checkpoint.film, checkpoint.samples, budget, on_pass))
+    }
+
+    /// A hash of every camera setting that affects the rendered image, used to check that a
+    /// checkpoint or distributed worker belongs to this camera. The scene isn't included.
+    pub fn fingerprint(&self) -> u64 {
+        let settings = format!(
+            "{} {} {:?} {:?} {:?} {:?} {} {:?} {} {:?} {:?} {} {:?}",
+            self.image_width,
+            self.image_height,
+            self.centre,
+            self.pixel00_loc,
+            self.pixel_delta_u,
+            self.pixel_delta_v,
+            self.samples_per_pixel,
+            self.sampler,
+            self.seed,
+            self.adaptive,
+            self.filter,
+            self.aovs,
+            self.integrator,
+        );
+        let pass_samples = self.pass_samples.unwrap_or(self.samples_per_pixel) as u64;
+        let words: Vec<u64> = settings.bytes().map(u64::from).collect();
+        hash(&[hash(&words), pass_samples])
     }
 
-    pub fn render(&self, world: &HittableList) {
+    /// Render the passes after the first `samples_done` samples per pixel into `film`.
+    fn render_from(
+        &self,
+        world: &HittableList,
+        mut film: Film,
+        samples_done: usize,
+        budget: &Budget,
+        mut on_pass: impl FnMut(&Film, usize),
+    ) -> RenderResult {
         log::info!("Rendering image");
-        // Set a recursion limit
-        let max_depth = 50;
+        let deadline = budget.time.map(|time| Instant::now() + time);
 
-        // Define the progress bar style
-        let style = ProgressStyle::with_template(
-            "[Elapsed: {elapsed_precise}| ETA: {eta_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
-        )
-        .unwrap();
-
-        // Render the image in PPM format
-        println!("P3\n{} {}\n255\n", self.image_width, self.image_height);
-        for j in (0..self.image_height).progress_with_style(style) {
-            for i in 0..self.image_width {
-                let mut pixel_colour: Colour = Colour::new(0.0, 0.0, 0.0);
-                for _sample in 0..self.samples_per_pixel {
-                    let r: Ray = self.get_ray(i, j);
-                    pixel_colour += Camera::ray_colour(&r, world, max_depth);
+        let (total_samples, pass_samples) = self.pass_layout();
+        let passes = total_samples.div_ceil(pass_samples);
+        let first_pass = samples_done.div_ceil(pass_samples);
+
+        let tiles = self.tiles();
+        let start = Instant::now();
+        let mut progress =
+            ProgressUpdate::new(tiles.len() * (passes - first_pass.min(passes)), passes);
+        let mut active = vec![true; self.image_width * self.image_height];
+        let mut samples_done = samples_done;
+        let mut stop = StopReason::Completed;
+        let mut stats = RenderStats::default();
+        let mut last_pass = None;
+        let sampler = self.sampler_setup();
+
+        for pass in first_pass..passes {
+            let analysis = Instant::now();
+            if let Some(adaptive) = self.adaptive {
+                for j in 0..self.image_height {
+                    for i in 0..self.image_width {
+                        active[j * self.image_width + i] =
+                            film.pixel(i, j).relative_error() > adaptive.threshold;
+                    }
+                }
+                if !active.contains(&true) {
+                    stats.phases.analysis += analysis.elapsed();
+                    log::info!("All pixels converged after {samples_done} samples");
+                    stop = StopReason::Converged;
+                    break;
                 }
-                println!(
-                    "{}",
-                    (self.pixel_samples_scale * pixel_colour).write_colour()
-                );
+            }
+
+            stats.phases.analysis += analysis.elapsed();
+
+            let samples = pass * pass_samples..((pass + 1) * pass_samples).min(total_samples);
+            progress.pass = pass + 1;
+            let interrupted = || budget.interrupted(deadline);
+            // A pass cut short can't be resumed from, so each pass is rendered into a film of its
+            // own and only added to `film` once it's complete.
+            let mut pass_film = self.new_film();
+            if let Some(reason) = self.render_pass(
+                world,
+                &mut pass_film,
+                &sampler,
+                &tiles,
+                &active,
+                samples.clone(),
+                &interrupted,
+                start,
+                &mut progress,
+                &mut stats,
+            ) {
+                log::info!("Stopped part way through pass {}: {reason:?}", pass + 1);
+                stop = reason;
+                // Show what the pass got through, but resume from the passes before it.
+                let mut partial = film.clone();
+                partial.merge(&pass_film);
+                last_pass = Some(mem::replace(&mut film, partial));
+                break;
+            }
+            let merge = Instant::now();
+            film.merge(&pass_film);
+            stats.phases.merge += merge.elapsed();
+            samples_done = samples.end;
+            let callback = Instant::now();
+            on_pass(&film, samples_done);
+            stats.phases.callbacks += callback.elapsed();
+
+            let analysis = Instant::now();
+            let reached_noise = budget
+                .noise
+                .is_some_and(|noise| film.mean_relative_error() <= noise);
+            stats.phases.analysis += analysis.elapsed();
+            if reached_noise {
+                log::info!("Reached noise target after {samples_done} samples");
+                stop = StopReason::NoiseTarget;
+                break;
             }
         }
+        self.progress.finish(&progress);
         log::info!("Done");
+        RenderResult {
+            film,
+            last_pass,
+            samples: samples_done,
+            stop,
+            stats,
+        }
+    }
+
+    /// Take the given range of samples for every active pixel, accumulating them into `film`.
+    /// Stops early, returning why, if `interrupted` says so; the samples taken before then are
+    /// still accumulated.
+    #[allow(clippy::too_many_arguments)]
+    fn render_pass(
+        &self,
+        world: &HittableList,
+        film: &mut Film,
+        sampler: &SamplerSetup,
+        tiles: &[Bounds],
+        active: &[bool],
+        samples: Range<usize>,
+        interrupted: &(dyn Fn() -> Option<StopReason> + Sync),
+        start: Instant,
+        progress: &mut ProgressUpdate,
+        stats: &mut RenderStats,
+    ) -> Option<StopReason> {
+        let pass_start = Instant::now();
+        let next_tile = AtomicUsize::new(0);
+        let (sender, receiver) = mpsc::channel();
+
+        thread::scope(|scope| {
+            for _ in 0..self.threads {
+                let sender = sender.clone();
+                let (next_tile, samples) = (&next_tile, samples.clone());
+                scope.spawn(move || {
+                    let mut sampler = sampler.build();
+                    loop {
+                        let index = next_tile.fetch_add(1, Ordering::Relaxed);
+                        let Some(&tile) = tiles.get(index) else {
+                            break;
+                        };
+                        let (tile, stop) = self.render_tile_pass(
+                            world,
+                            sampler.as_mut(),
+                            tile,
+                            active,
+                            &samples,
+                            interrupted,
+                        );
+                        if sender.send((index, tile)).is_err() || stop.is_some() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(sender);
+
+            // Tiles overlap where their filter footprints meet, so merge them in a fixed order to
+            // keep the result independent of thread scheduling.
+            let mut pending = BTreeMap::new();
+            let mut next_merge = 0;
+            for (index, (tile_film, tile_stats)) in receiver {
+                let merge = Instant::now();
+                stats.merge(&tile_stats);
+                progress.tile_done(start, tile_stats.camera_rays, tile_stats.rays);
+                self.progress.update(progress);
+                pending.insert(index, tile_film);
+                while let Some(tile_film) = pending.remove(&next_merge) {
+                    film.merge(&tile_film);
+                    next_merge += 1;
+                }
+                stats.phases.merge += merge.elapsed();
+            }
+            // If we were interrupted there may be gaps; keep what came after them too.
+            let merge = Instant::now();
+            for tile_film in pending.values() {
+                film.merge(tile_film);
+            }
+            stats.phases.merge += merge.elapsed();
+        });
+        stats.phases.render += pass_start.elapsed();
+        interrupted()
+    }
+
+    /// Render every pass of a single tile on the calling thread, e.g. on a distributed worker.
+    /// Merging the tiles of `tiles()` in order gives the same image as `render`, up to rounding
+    /// where the filter spreads samples across tiles. The `sampler`, from `sampler_setup`, can be
+    /// shared by every tile of a render.
+    pub fn render_tile(&self, world: &HittableList, sampler: &SamplerSetup, tile: Bounds) -> Film {
+        let (total_samples, pass_samples) = self.pass_layout();
+        let mut sampler = sampler.build();
+        let mut film = self.tile_film(tile);
+        for start in (0..total_samples).step_by(pass_samples) {
+            let samples = start..(start + pass_samples).min(total_samples);
+            // Each pass is summed on its own and then added, as `render_from` does.
+            let mut pass_film = self.tile_film(tile);
+            for j in tile.y0..tile.y1 {
+                for i in tile.x0..tile.x1 {
+                    let active = match self.adaptive {
+                        Some(adaptive) => film.pixel(i, j).relative_error() > adaptive.threshold,
+                        None => true,
+                    };
+                    if active {
+                        self.render_pixel(
+                            world,
+                            sampler.as_mut(),
+                            &mut pass_film,
+                            i,
+                            j,
+                            &samples,
+                            &mut RenderStats::default(),
+                        );
+                    }
+                }
+            }
+            film.merge(&pass_film);
+        }
+        film
+    }
+
+    /// Builds the samplers for rendering with this camera.
+    pub fn sampler_setup(&self) -> SamplerSetup {
+        self.sampler.setup(self.samples_per_pixel, self.seed)
+    }
+
+    /// An empty film covering the whole image.
+    pub fn new_film(&self) -> Film {
+        let film = Film::new(self.image_width, self.image_height);
+        if self.aovs { film.with_aovs() } else { film }
+    }
+
+    /// An empty film for rendering `tile` into.
+    fn tile_film(&self, tile: Bounds) -> Film {
+        let film = Film::for_tile(
+            self.image_width,
+            self.image_height,
+            tile,
+            self.filter.as_ref(),
+        );
+        if self.aovs { film.with_aovs() } else { film }
+    }
+
+    /// The whole image, as bounds.
+    pub fn image_bounds(&self) -> Bounds {
+        Bounds {
+            x0: 0,
+            y0: 0,
+            x1: self.image_width,
+            y1: self.image_height,
+        }
+    }
+
+    /// The part of the image covered by the film `render_tile` returns for `tile`.
+    pub fn tile_film_bounds(&self, tile: Bounds) -> Bounds {
+        Film::tile_bounds(
+            self.image_width,
+            self.image_height,
+            tile,
+            self.filter.as_ref(),
+        )
+    }
+
+    /// The tiles the image is split into for rendering, in merge order.
+    pub fn tiles(&self) -> Vec<Bounds> {
+        Bounds::tiles(self.image_width, self.image_height, TILE_SIZE)
+    }
+
+    /// Total samples per pixel to take (at most, if adaptive), and how many to take per pass.
+    fn pass_layout(&self) -> (usize, usize) {
+        let total_samples = match self.adaptive {
+            Some(adaptive) => adaptive.max_samples.max(self.samples_per_pixel),
+            None => self.samples_per_pixel,
+        };
+        let pass_samples = self.pass_samples.unwrap_or(self.samples_per_pixel).max(1);
+        (total_samples, pass_samples)
+    }
+
+    fn render_tile_pass(
+        &self,
+        world: &HittableList,
+        sampler: &mut dyn Sampler,
+        tile: Bounds,
+        active: &[bool],
+        samples: &Range<usize>,
+        interrupted: &dyn Fn() -> Option<StopReason>,
+    ) -> ((Film, RenderStats), Option<StopReason>) {
+        let mut film = self.tile_film(tile);
+        let mut stats = RenderStats::default();
+        for j in tile.y0..tile.y1 {
+            for i in tile.x0..tile.x1 {
+                if let Some(reason) = interrupted() {
+                    return ((film, stats), Some(reason));
+                }
+                if active[j * self.image_width + i] {
+                    self.render_pixel(world, sampler, &mut film, i, j, samples, &mut stats);
+                }
+            }
+        }
+        ((film, stats), None)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn render_pixel(
+        &self,
+        world: &HittableList,
+        sampler: &mut dyn Sampler,
+        film: &mut Film,
+        i: usize,
+        j: usize,
+        samples: &Range<usize>,
+        stats: &mut RenderStats,
+    ) {
+        for sample_index in samples.clone() {
+            sampler.start_pixel_sample(i, j, sample_index);
+            let offset = Camera::sample_square(sampler);
+            let r: Ray = self.get_ray(i, j, offset);
+            let rays_before = stats.rays;
+            let (colour, first_hit) = if self.aovs {
+                let (colour, first_hit) = self.integrator.li_first_hit(&r, world, sampler, stats);
+                (colour, Some(first_hit))
+            } else {
+                (self.integrator.li(&r, world, sampler, stats), None)
+            };
+            stats.camera_rays += 1;
+            stats.max_path_depth = stats.max_path_depth.max(stats.rays - rays_before);
+            let position = (i as f64 + 0.5 + offset.x, j as f64 + 0.5 + offset.y);
+            film.add_sample(i, j, position, colour, self.filter.as_ref());
+            if let Some(first_hit) = first_hit {
+                let sample = self.aov_sample(&r, world, first_hit);
+                film.add_aov_sample(i, j, &sample, sample_index == 0);
+            }
+        }
+    }
+
+    /// Trace sample `sample_index` of pixel (i, j) exactly as rendering would, recording every
+    /// vertex of its path.
+    pub fn trace_pixel_sample(
+        &self,
+        world: &HittableList,
+        i: usize,
+        j: usize,
+        sample_index: usize,
+    ) -> PathDump {
+        let mut sampler = self.sampler.build(self.samples_per_pixel, self.seed);
+        sampler.start_pixel_sample(i, j, sample_index);
+        let offset = Camera::sample_square(sampler.as_mut());
+        let r = self.get_ray(i, j, offset);
+        let mut vertices = Vec::new();
+        let colour = self.integrator.li_path(
+            &r,
+            world,
+            sampler.as_mut(),
+            &mut RenderStats::default(),
+            &mut vertices,
+        );
+        PathDump {
+            pixel: (i, j),
+            sample_index,
+            seed: self.seed,
+            camera_ray: (r.origin, r.direction),
+            colour,
+            vertices,
+        }
     }
 
-    fn get_ray(&self, i: usize, j: usize) -> Ray {
-        // Construct a camera ray originating from the origin and directed at randomly sampled
+    fn aov_sample(&self, r: &Ray, world: &HittableList, first_hit: Option<HitRecord>) -> AovSample {
+        match first_hit {
+            Some(rec) => AovSample {
+                hit: Some(AovHit {
+                    depth: dot(&(rec.p - self.centre), &self.forward),
+                    normal: rec.normal,
+                    position: rec.p,
+                    object_id: rec.object_id,
+                    material_id: rec.material_id,
+                }),
+                albedo: rec.material.albedo(&rec),
+            },
+            None => AovSample {
+                hit: None,
+                albedo: world.background.radiance(&r.direction),
+            },
+        }
+    }
+
+    fn get_ray(&self, i: usize, j: usize, offset: Vec3) -> Ray {
+        // Construct a camera ray originating from the origin and directed at the sampled
         // point around the pixel location i, j.
-        let offset = Camera::sample_square();
         let pixel_sample = self.pixel00_loc
             + ((i as f64 + offset.x) * self.pixel_delta_u)
             + ((j as f64 + offset.y) * self.pixel_delta_v);
@@ -129,7 +649,363 @@ impl Camera {
         Ray::new(ray_origin, ray_direction)
     }
 
-    fn sample_square() -> Vec3 {
-        return Vec3::new(random_f64() - 0.5, random_f64() - 0.5, 0.0);
+    fn sample_square(sampler: &mut dyn Sampler) -> Vec3 {
+        // Returns the vector to a point in the [-.5,-.5]-[+.5,+.5] unit square.
+        let (x, y) = sampler.get_2d();
+        Vec3::new(x - 0.5, y - 0.5, 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Colour;
+    use crate::aov::Aov;
+    use crate::budget::CancelToken;
+    use crate::integrator::NormalIntegrator;
+    use crate::sphere::Sphere;
+    use rstest::rstest;
+    use std::time::Duration;
+
+    fn test_world() -> HittableList {
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5)));
+        world.add(Box::new(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0)));
+        world
+    }
+
+    fn pixels(film: &Film) -> Vec<Colour> {
+        (0..film.height)
+            .flat_map(|j| (0..film.width).map(move |i| (i, j)))
+            .map(|(i, j)| film.pixel(i, j).colour())
+            .collect()
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_progressive_matches_single_pass() {
+        let world = test_world();
+        let camera = Camera::new(2.0, 24, 8).with_sampler(SamplerKind::Sobol);
+        let single = camera.render(&world);
+
+        let mut passes = Vec::new();
+        let progressive = Camera::new(2.0, 24, 8)
+            .with_sampler(SamplerKind::Sobol)
+            .with_pass_samples(3)
+            .render_progressive(&world, |_, samples| passes.push(samples));
+
+        assert_eq!(passes, vec![3, 6, 8]);
+        // Same samples, just summed in a different order.
+        for (a, b) in pixels(&single).iter().zip(pixels(&progressive)) {
+            assert!((*a - b).length() < 1e-9, "{a:?} != {b:?}");
+        }
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_resume_matches_uninterrupted_render() {
+        let world = test_world();
+        let camera = Camera::new(2.0, 24, 9)
+            .with_sampler(SamplerKind::Halton)
+            .with_pass_samples(3);
+        let uninterrupted = camera.render(&world);
+
+        // Stop after the first pass, then carry on from its checkpoint.
+        let mut bytes = Vec::new();
+        camera.render_progressive(&world, |film, samples| {
+            if samples == 3 {
+                Checkpoint::write(&mut bytes, film, samples, camera.fingerprint()).unwrap();
+            }
+        });
+        let checkpoint = Checkpoint::read(&mut bytes.as_slice()).unwrap();
+        let resumed = camera
+            .resume(&world, checkpoint, &Budget::default(), |_, _| {})
+            .unwrap();
+
+        assert_eq!(resumed.stop, StopReason::Completed);
+        assert_eq!(pixels(&uninterrupted), pixels(&resumed.film));
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_resume_rejects_other_settings() {
+        let world = test_world();
+        let camera = Camera::new(2.0, 8, 4);
+        let checkpoint = Checkpoint {
+            film: Film::new(8, 4),
+            samples: 0,
+            fingerprint: camera.fingerprint(),
+        };
+        let other = Camera::new(2.0, 8, 4).with_seed(1);
+        assert!(
+            other
+                .resume(&world, checkpoint, &Budget::default(), |_, _| {})
+                .is_err()
+        );
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case(Film::new(16, 4))]
+    #[case(Film::for_tile(8, 4, Bounds { x0: 0, y0: 0, x1: 2, y1: 2 }, &BoxFilter::default()))]
+    fn test_resume_rejects_films_of_other_sizes(#[case] film: Film) {
+        let world = test_world();
+        let camera = Camera::new(2.0, 8, 4);
+        let checkpoint = Checkpoint {
+            film,
+            samples: 0,
+            fingerprint: camera.fingerprint(),
+        };
+        let err = camera
+            .resume(&world, checkpoint, &Budget::default(), |_, _| {})
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_cancelled_render_keeps_completed_passes() {
+        let world = test_world();
+        let camera = Camera::new(2.0, 24, 8).with_pass_samples(2);
+        let cancel = CancelToken::new();
+        let budget = Budget {
+            cancel: Some(cancel.clone()),
+            ..Budget::default()
+        };
+        let result = camera.render_within(&world, &budget, |_, samples| {
+            if samples == 4 {
+                cancel.cancel();
+            }
+        });
+
+        assert_eq!(result.stop, StopReason::Cancelled);
+        assert_eq!(result.samples, 4);
+        // Nothing was started after the cancel, so every pixel is normalised over 4 samples.
+        assert!((0..12).all(|j| (0..24).all(|i| result.film.pixel(i, j).samples == 4)));
+    }
+
+    /// Cancels the render once it has rendered `after` tiles.
+    #[derive(Debug)]
+    struct CancelAfter {
+        after: usize,
+        cancel: CancelToken,
+    }
+
+    impl ProgressObserver for CancelAfter {
+        fn update(&self, progress: &ProgressUpdate) {
+            if progress.tiles_done >= self.after {
+                self.cancel.cancel();
+            }
+        }
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_render_cancelled_part_way_through_a_pass_can_be_resumed() {
+        let world = test_world();
+        // Two tiles a pass, on one thread so that the cancel lands part way through pass 2.
+        let camera = Camera::new(2.0, 48, 6)
+            .with_sampler(SamplerKind::Halton)
+            .with_pass_samples(2)
+            .with_threads(1);
+        let uninterrupted = camera.render(&world);
+
+        let cancel = CancelToken::new();
+        let cancelling = Camera::new(2.0, 48, 6)
+            .with_sampler(SamplerKind::Halton)
+            .with_pass_samples(2)
+            .with_threads(1)
+            .with_progress(Arc::new(CancelAfter {
+                after: 3,
+                cancel: cancel.clone(),
+            }));
+        let budget = Budget {
+            cancel: Some(cancel),
+            ..Budget::default()
+        };
+        let result = cancelling.render_within(&world, &budget, |_, _| {});
+        assert_eq!(result.stop, StopReason::Cancelled);
+        assert_eq!(result.samples, 2);
+        assert!(result.film.pixel(0, 0).samples > 2);
+        assert!(result.resumable().pixels().iter().all(|p| p.samples == 2));
+
+        let checkpoint = Checkpoint {
+            film: result.resumable().clone(),
+            samples: result.samples,
+            fingerprint: camera.fingerprint(),
+        };
+        let resumed = camera
+            .resume(&world, checkpoint, &Budget::default(), |_, _| {})
+            .unwrap();
+        assert_eq!(pixels(&uninterrupted), pixels(&resumed.film));
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_time_budget_returns_partial_image() {
+        let world = test_world();
+        let camera = Camera::new(2.0, 24, 1_000_000);
+        let budget = Budget {
+            time: Some(Duration::from_millis(50)),
+            ..Budget::default()
+        };
+        let result = camera.render_within(&world, &budget, |_, _| {});
+
+        assert_eq!(result.stop, StopReason::TimeBudget);
+        assert_eq!(result.samples, 0);
+        let first = result.film.pixel(0, 0);
+        assert!(first.samples > 0);
+        assert!(first.colour().length() > 0.0);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_noise_target_stops_render() {
+        let world = test_world();
+        let camera = Camera::new(2.0, 24, 4096).with_pass_samples(16);
+        let budget = Budget {
+            noise: Some(0.5),
+            ..Budget::default()
+        };
+        let result = camera.render_within(&world, &budget, |_, _| {});
+
+        assert_eq!(result.stop, StopReason::NoiseTarget);
+        assert!(result.samples < 4096);
+        assert!(result.film.mean_relative_error() <= 0.5);
+    }
+
+    /// Keeps every update it's given.
+    #[derive(Debug, Default)]
+    struct RecordProgress {
+        updates: std::sync::Mutex<Vec<ProgressUpdate>>,
+        finished: std::sync::Mutex<Option<ProgressUpdate>>,
+    }
+
+    impl ProgressObserver for RecordProgress {
+        fn update(&self, progress: &ProgressUpdate) {
+            self.updates.lock().unwrap().push(*progress);
+        }
+
+        fn finish(&self, progress: &ProgressUpdate) {
+            *self.finished.lock().unwrap() = Some(*progress);
+        }
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_progress_observer_sees_every_tile() {
+        let world = test_world();
+        let observer = Arc::new(RecordProgress::default());
+        let camera = Camera::new(2.0, 80, 6)
+            .with_pass_samples(3)
+            .with_progress(observer.clone());
+        camera.render(&world);
+
+        let tiles = camera.tiles().len();
+        let updates = observer.updates.lock().unwrap();
+        assert_eq!(updates.len(), tiles * 2);
+        assert_eq!(updates[0].pass, 1);
+        assert!(
+            updates
+                .windows(2)
+                .all(|w| w[0].tiles_done < w[1].tiles_done)
+        );
+
+        let finished = observer.finished.lock().unwrap().unwrap();
+        assert_eq!(finished.tiles_done, tiles * 2);
+        assert_eq!(finished.tiles_total, tiles * 2);
+        assert_eq!(finished.pass, 2);
+        assert_eq!(finished.samples, (80 * 40 * 6) as u64);
+        assert!(finished.rays >= finished.samples);
+        assert_eq!(finished.eta, Some(Duration::ZERO));
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_render_collects_stats() {
+        let world = test_world();
+        let camera = Camera::new(2.0, 40, 8).with_pass_samples(4);
+        let stats = camera
+            .render_within(&world, &Budget::default(), |_, _| {})
+            .stats;
+
+        assert_eq!(stats.camera_rays, 40 * 20 * 8);
+        assert!(stats.rays > stats.camera_rays);
+        // Both spheres are tested by every ray.
+        assert_eq!(stats.primitive_tests, 2 * stats.rays);
+        assert!(stats.max_path_depth > 1 && stats.max_path_depth <= 50);
+        assert!(stats.mean_path_depth() <= stats.max_path_depth as f64);
+        assert!(stats.phases.render > Duration::ZERO);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_aovs_describe_first_hit() {
+        let world = test_world();
+        let camera = Camera::new(2.0, 40, 4).with_sampler(SamplerKind::Sobol);
+        let with_aovs = Camera::new(2.0, 40, 4)
+            .with_sampler(SamplerKind::Sobol)
+            .with_aovs();
+        let plain = camera.render(&world);
+        let film = with_aovs.render(&world);
+        // Collecting AOVs doesn't change the image.
+        assert_eq!(pixels(&film), pixels(&plain));
+        assert!(!plain.has_aovs());
+
+        // The middle of the image looks straight at the front of the small sphere.
+        let centre = film.aov_pixel(20, 10).unwrap();
+        assert!((centre.value(Aov::Depth).x - 0.5).abs() < 0.02);
+        assert!(centre.value(Aov::Normal).z > 0.99);
+        assert_eq!(centre.value(Aov::ObjectId).x, 0.0);
+        assert_eq!(centre.value(Aov::Albedo), Colour::new(0.5, 0.5, 0.5));
+
+        // The bottom row sees the ground, and the top row sees nothing.
+        assert_eq!(film.aov_pixel(0, 19).unwrap().value(Aov::ObjectId).x, 1.0);
+        let sky = film.aov_pixel(0, 0).unwrap();
+        assert_eq!(sky.value(Aov::Depth).x, f64::INFINITY);
+        assert_eq!(sky.value(Aov::ObjectId).x, -1.0);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case(Arc::new(PathIntegrator::default()), 0)]
+    #[case(Arc::new(NormalIntegrator), 1)]
+    fn test_aov_rays_are_counted(
+        #[case] integrator: Arc<dyn Integrator>,
+        #[case] extra_rays_per_sample: u64,
+    ) {
+        let world = test_world();
+        let camera = Camera::new(2.0, 40, 4).with_integrator(integrator);
+        let plain = camera.render_within(&world, &Budget::default(), |_, _| {});
+        let with_aovs = camera
+            .with_aovs()
+            .render_within(&world, &Budget::default(), |_, _| {});
+        // The path integrator hands over the hit it found; others trace it again.
+        let extra = extra_rays_per_sample * plain.stats.camera_rays;
+        assert_eq!(with_aovs.stats.rays, plain.stats.rays + extra);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_traced_sample_matches_render() {
+        let world = test_world();
+        let camera = Camera::new(2.0, 40, 1)
+            .with_sampler(SamplerKind::Sobol)
+            .with_seed(9);
+        let film = camera.render(&world);
+
+        for (i, j) in [(20, 10), (3, 18), (0, 0)] {
+            let dump = camera.trace_pixel_sample(&world, i, j, 0);
+            assert_eq!(dump.colour, film.pixel(i, j).colour());
+            let last = dump.vertices.last().unwrap();
+            assert!(last.surface.is_none(), "paths here always escape");
+            for vertex in &dump.vertices[..dump.vertices.len() - 1] {
+                let scatter = vertex.surface.as_ref().unwrap().scatter.as_ref().unwrap();
+                assert!(scatter.pdf.unwrap() > 0.0);
+            }
+        }
+        let json = camera.trace_pixel_sample(&world, 20, 10, 0).to_json();
+        assert!(json.contains("\"pixel\": [20, 10]"));
+        assert!(json.contains("\"event\": \"escaped\""));
     }
 }
@@ -0,0 +1,319 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::aov::AovPixel;
+use crate::film::{Bounds, Film, Pixel};
+use crate::{Colour, Vec3};
+
+/// Identifies a checkpoint file, and the version of its layout.
+const MAGIC: &[u8; 8] = b"RTWCKPT2";
+
+/// The most pixels a film read back may have: far more than any image rendered here, but few
+/// enough that a corrupt size can't overflow.
+const MAX_FILM_PIXELS: usize = 1 << 30;
+
+/// The state of a progressive render between passes: the accumulated film and how many samples
+/// per pixel it holds. Samplers are seeded per pixel sample, so this is all that's needed to carry
+/// on exactly where the render left off.
+#[derive(Debug)]
+pub struct Checkpoint {
+    pub film: Film,
+    pub samples: usize,
+    /// `Camera::fingerprint` of the camera that rendered the film.
+    pub fingerprint: u64,
+}
+
+impl Checkpoint {
+    pub fn write(
+        out: &mut dyn Write,
+        film: &Film,
+        samples: usize,
+        fingerprint: u64,
+    ) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        out.write_all(&fingerprint.to_le_bytes())?;
+        out.write_all(&(samples as u64).to_le_bytes())?;
+        write_film(out, film)
+    }
+
+    pub fn read(input: &mut dyn Read) -> io::Result<Checkpoint> {
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a render checkpoint",
+            ));
+        }
+        let fingerprint = read_u64(input)?;
+        let samples = read_u64(input)? as usize;
+        Ok(Checkpoint {
+            film: read_film(input)?,
+            samples,
+            fingerprint,
+        })
+    }
+
+    /// Write a checkpoint to `path`. The file is replaced atomically, so a crash part way through
+    /// leaves the previous checkpoint intact.
+    pub fn save(path: &Path, film: &Film, samples: usize, fingerprint: u64) -> io::Result<()> {
+        let partial = path.with_extension("partial");
+        let mut out = BufWriter::new(File::create(&partial)?);
+        Checkpoint::write(&mut out, film, samples, fingerprint)?;
+        out.into_inner()?.sync_all()?;
+        fs::rename(partial, path)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Checkpoint> {
+        Checkpoint::read(&mut BufReader::new(File::open(path)?))
+    }
+}
+
+/// Write a film's raw pixels, including its bounds within the image.
+pub(crate) fn write_film(out: &mut dyn Write, film: &Film) -> io::Result<()> {
+    let bounds = film.bounds();
+    for value in [
+        film.width,
+        film.height,
+        bounds.x0,
+        bounds.y0,
+        bounds.x1,
+        bounds.y1,
+    ] {
+        out.write_all(&(value as u64).to_le_bytes())?;
+    }
+    for pixel in film.pixels() {
+        for value in [
+            pixel.sum.x,
+            pixel.sum.y,
+            pixel.sum.z,
+            pixel.weight,
+            pixel.luminance_mean,
+            pixel.luminance_m2,
+        ] {
+            out.write_all(&value.to_le_bytes())?;
+        }
+        out.write_all(&(pixel.samples as u64).to_le_bytes())?;
+    }
+
+    out.write_all(&[film.has_aovs() as u8])?;
+    for pixel in film.aov_pixels().unwrap_or_default() {
+        for value in [pixel.samples, pixel.hits] {
+            out.write_all(&(value as u64).to_le_bytes())?;
+        }
+        for vector in [
+            Vec3::new(pixel.depth, 0.0, 0.0),
+            pixel.normal,
+            pixel.position,
+            pixel.albedo,
+        ] {
+            for value in [vector.x, vector.y, vector.z] {
+                out.write_all(&value.to_le_bytes())?;
+            }
+        }
+        for id in [pixel.object_id, pixel.material_id] {
+            out.write_all(&id.map_or(u64::MAX, u64::from).to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn read_film(input: &mut dyn Read) -> io::Result<Film> {
+    let (width, height, bounds) = read_film_bounds(input)?;
+    read_film_pixels(input, width, height, bounds)
+}
+
+/// Read the start of a film written by `write_film`: the image's width and height, and the part
+/// of it the film covers. Nothing depending on them has been allocated yet, so callers expecting
+/// a particular film can check it cheaply.
+pub(crate) fn read_film_bounds(input: &mut dyn Read) -> io::Result<(usize, usize, Bounds)> {
+    let width = read_u64(input)? as usize;
+    let height = read_u64(input)? as usize;
+    let bounds = Bounds {
+        x0: read_u64(input)? as usize,
+        y0: read_u64(input)? as usize,
+        x1: read_u64(input)? as usize,
+        y1: read_u64(input)? as usize,
+    };
+    if width
+        .checked_mul(height)
+        .is_none_or(|pixels| pixels > MAX_FILM_PIXELS)
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "film is too large",
+        ));
+    }
+    if bounds.x0 > bounds.x1 || bounds.y0 > bounds.y1 || bounds.x1 > width || bounds.y1 > height {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "film bounds are outside the image",
+        ));
+    }
+
+    Ok((width, height, bounds))
+}
+
+/// Read the rest of a film after `read_film_bounds`.
+pub(crate) fn read_film_pixels(
+    input: &mut dyn Read,
+    width: usize,
+    height: usize,
+    bounds: Bounds,
+) -> io::Result<Film> {
+    // Grown as pixels arrive rather than reserved up front, so a stream that stops short of
+    // the size it claims never costs more memory than it sent.
+    let mut pixels = Vec::new();
+    for _ in 0..bounds.area() {
+        let sum = Colour::new(read_f64(input)?, read_f64(input)?, read_f64(input)?);
+        let weight = read_f64(input)?;
+        let luminance_mean = read_f64(input)?;
+        let luminance_m2 = read_f64(input)?;
+        let samples = read_u64(input)? as usize;
+        pixels.push(Pixel {
+            sum,
+            weight,
+            samples,
+            luminance_mean,
+            luminance_m2,
+        });
+    }
+
+    let mut has_aovs = [0; 1];
+    input.read_exact(&mut has_aovs)?;
+    let aovs = match has_aovs[0] {
+        0 => None,
+        _ => {
+            let mut aovs = Vec::new();
+            for _ in 0..bounds.area() {
+                aovs.push(read_aov_pixel(input)?);
+            }
+            Some(aovs)
+        }
+    };
+    Ok(Film::from_pixels(width, height, bounds, pixels, aovs))
+}
+
+fn read_aov_pixel(input: &mut dyn Read) -> io::Result<AovPixel> {
+    let samples = read_u64(input)? as usize;
+    let hits = read_u64(input)? as usize;
+    let mut vectors = [Vec3::default(); 4];
+    for vector in &mut vectors {
+        *vector = Vec3::new(read_f64(input)?, read_f64(input)?, read_f64(input)?);
+    }
+    let mut read_id = || -> io::Result<Option<u32>> {
+        Ok(match read_u64(input)? {
+            u64::MAX => None,
+            id => Some(id as u32),
+        })
+    };
+    let [depth, normal, position, albedo] = vectors;
+    Ok(AovPixel {
+        samples,
+        hits,
+        depth: depth.x,
+        normal,
+        position,
+        albedo,
+        object_id: read_id()?,
+        material_id: read_id()?,
+    })
+}
+
+pub(crate) fn read_u64(input: &mut dyn Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f64(input: &mut dyn Read) -> io::Result<f64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(f64::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aov::{AovHit, AovSample};
+    use crate::filter::TentFilter;
+    use rstest::rstest;
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_checkpoint_round_trip() {
+        let filter = TentFilter { radius: 1.0 };
+        let mut film = Film::new(3, 2);
+        film.add_sample(1, 0, (1.3, 0.8), Colour::new(0.2, 0.4, 0.6), &filter);
+        film.add_sample(2, 1, (2.9, 1.1), Colour::new(1.0, 0.0, 0.5), &filter);
+
+        let mut bytes = Vec::new();
+        Checkpoint::write(&mut bytes, &film, 42, 0x1234).unwrap();
+        let checkpoint = Checkpoint::read(&mut bytes.as_slice()).unwrap();
+
+        assert_eq!(checkpoint.samples, 42);
+        assert_eq!(checkpoint.fingerprint, 0x1234);
+        assert_eq!(checkpoint.film.bounds(), film.bounds());
+        assert_eq!(checkpoint.film.pixels(), film.pixels());
+        assert_eq!(checkpoint.film.aov_pixels(), None);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_checkpoint_round_trip_with_aovs() {
+        let mut film = Film::new(2, 1).with_aovs();
+        let hit = AovSample {
+            hit: Some(AovHit {
+                depth: 2.5,
+                normal: Vec3::new(0.0, 0.0, 1.0),
+                position: Vec3::new(0.1, 0.2, -2.5),
+                object_id: 3,
+                material_id: 1,
+            }),
+            albedo: Colour::new(0.5, 0.25, 0.125),
+        };
+        film.add_aov_sample(1, 0, &hit, true);
+
+        let mut bytes = Vec::new();
+        Checkpoint::write(&mut bytes, &film, 1, 0).unwrap();
+        let checkpoint = Checkpoint::read(&mut bytes.as_slice()).unwrap();
+
+        assert_eq!(checkpoint.film.aov_pixels(), film.aov_pixels());
+        assert_eq!(checkpoint.film.aov_pixel(0, 0).unwrap().object_id, None);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_checkpoint_rejects_other_files() {
+        let bytes = b"P3\n2 1\n255\n".to_vec();
+        let err = Checkpoint::read(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case([u64::MAX, u64::MAX, 0, 0, 1, 1], "film is too large")]
+    #[case([1 << 40, 1 << 40, 0, 0, 1, 1], "film is too large")]
+    #[case([4, 4, 0, 0, 5, 1], "film bounds are outside the image")]
+    #[case([4, 4, 3, 0, 2, 1], "film bounds are outside the image")]
+    fn test_checkpoint_rejects_bad_sizes(#[case] fields: [u64; 6], #[case] want: &str) {
+        let mut bytes = MAGIC.to_vec();
+        for value in [0, 1].into_iter().chain(fields) {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        let err = Checkpoint::read(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), want);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_checkpoint_truncated() {
+        let mut bytes = Vec::new();
+        Checkpoint::write(&mut bytes, &Film::new(2, 2), 1, 0).unwrap();
+        bytes.truncate(bytes.len() - 4);
+        let err = Checkpoint::read(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,328 @@
+//! An edge-avoiding à-trous wavelet denoiser, after the spatial filter in SVGF (Schied et al.,
+//! "Spatiotemporal Variance-Guided Filtering", 2017).
+//!
+//! Each iteration blurs with a 5x5 B3 spline kernel whose taps are spread 2^i pixels apart, so a
+//! few iterations cover a wide footprint cheaply. Taps are weighted down where the normal, depth
+//! or luminance differ from the centre pixel's, which keeps edges sharp while noise is smoothed
+//! out. Texture is kept by filtering the lighting alone: colours are divided by their albedo
+//! before filtering and multiplied back afterwards.
+use crate::aov::{Aov, AovPixel};
+use crate::film::{Film, Pixel};
+use crate::{Colour, Vec3, dot};
+
+/// Settings for `Denoiser::denoise`. Larger sigmas blur more across differences in that feature.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Denoiser {
+    /// Number of à-trous iterations; the footprint doubles with each.
+    pub iterations: usize,
+    /// Luminance differences are measured in standard deviations of the centre pixel's noise.
+    pub sigma_luminance: f64,
+    /// Exponent on the cosine between normals, so larger is stricter.
+    pub sigma_normal: f64,
+    /// Depth differences are measured relative to the centre pixel's depth, per pixel stepped.
+    pub sigma_depth: f64,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self {
+            iterations: 5,
+            sigma_luminance: 4.0,
+            sigma_normal: 128.0,
+            sigma_depth: 0.05,
+        }
+    }
+}
+
+/// 1D weights of the B3 spline kernel.
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// Albedo channels below this aren't divided out, to avoid amplifying noise in dark surfaces.
+const MIN_ALBEDO: f64 = 0.01;
+
+/// Keeps the luminance weight finite for noiseless pixels.
+const EPSILON: f64 = 1e-10;
+
+/// The per-pixel features that guide the filter.
+#[derive(Debug, Clone, Copy)]
+struct Guide {
+    normal: Vec3,
+    /// Infinite where the pixel saw only background.
+    depth: f64,
+    albedo: Colour,
+}
+
+impl Guide {
+    fn new(aov: Option<&AovPixel>) -> Self {
+        match aov {
+            Some(aov) => Self {
+                normal: aov.value(Aov::Normal),
+                depth: aov.value(Aov::Depth).x,
+                albedo: aov.value(Aov::Albedo),
+            },
+            // Without AOVs only the luminance guides the filter.
+            None => Self {
+                normal: Vec3::default(),
+                depth: f64::INFINITY,
+                albedo: Colour::new(1.0, 1.0, 1.0),
+            },
+        }
+    }
+
+    fn demodulate(&self, colour: Colour) -> Colour {
+        let divide = |c: f64, a: f64| if a < MIN_ALBEDO { c } else { c / a };
+        Colour::new(
+            divide(colour.x, self.albedo.x),
+            divide(colour.y, self.albedo.y),
+            divide(colour.z, self.albedo.z),
+        )
+    }
+
+    fn remodulate(&self, colour: Colour) -> Colour {
+        let multiply = |c: f64, a: f64| if a < MIN_ALBEDO { c } else { c * a };
+        Colour::new(
+            multiply(colour.x, self.albedo.x),
+            multiply(colour.y, self.albedo.y),
+            multiply(colour.z, self.albedo.z),
+        )
+    }
+
+    /// Weight for filtering with `other`, based on geometry alone.
+    fn geometry_weight(&self, other: &Guide, step: usize, denoiser: &Denoiser) -> f64 {
+        let normal = if self.normal == Vec3::default() || other.normal == Vec3::default() {
+            // Background, or no AOVs; only match like with like.
+            if self.normal == other.normal {
+                1.0
+            } else {
+                0.0
+            }
+        } else {
+            dot(&self.normal, &other.normal)
+                .max(0.0)
+                .powf(denoiser.sigma_normal)
+        };
+
+        let depth = match (self.depth.is_finite(), other.depth.is_finite()) {
+            (true, true) => {
+                let scale = denoiser.sigma_depth * self.depth.abs() * step as f64 + EPSILON;
+                (-(self.depth - other.depth).abs() / scale).exp()
+            }
+            (false, false) => 1.0,
+            _ => 0.0,
+        };
+        normal * depth
+    }
+}
+
+impl Denoiser {
+    /// Denoise a whole-image film, returning a film of the same size whose pixels hold the
+    /// filtered colours. Sample counts, statistics and AOVs are carried over unchanged.
+    ///
+    /// The film should have AOVs (see `Camera::with_aovs`); without them the filter can only
+    /// tell edges apart by their luminance, and blurs more.
+    pub fn denoise(&self, film: &Film) -> Film {
+        let bounds = film.bounds();
+        let (width, height) = (bounds.width(), bounds.height());
+        let guides: Vec<Guide> = (0..bounds.area())
+            .map(|index| Guide::new(film.aov_pixels().map(|aovs| &aovs[index])))
+            .collect();
+
+        let mut colours: Vec<Colour> = film
+            .pixels()
+            .iter()
+            .zip(&guides)
+            .map(|(pixel, guide)| guide.demodulate(pixel.colour()))
+            .collect();
+        let mut variances = self.initial_variances(film.pixels(), &guides, &colours, width);
+
+        for iteration in 0..self.iterations {
+            let step = 1 << iteration;
+            let mut next_colours = vec![Colour::default(); colours.len()];
+            let mut next_variances = vec![0.0; variances.len()];
+            for y in 0..height {
+                for x in 0..width {
+                    let p = y * width + x;
+                    let luminance = colours[p].luminance();
+                    let sigma = self.sigma_luminance * variances[p].sqrt() + EPSILON;
+
+                    let (mut colour, mut variance, mut total) = (Colour::default(), 0.0, 0.0);
+                    for (dy, ky) in KERNEL.iter().enumerate() {
+                        for (dx, kx) in KERNEL.iter().enumerate() {
+                            let qx = x as isize + (dx as isize - 2) * step as isize;
+                            let qy = y as isize + (dy as isize - 2) * step as isize;
+                            if qx < 0 || qy < 0 || qx >= width as isize || qy >= height as isize {
+                                continue;
+                            }
+                            let q = qy as usize * width + qx as usize;
+                            let luminance_weight =
+                                (-(luminance - colours[q].luminance()).abs() / sigma).exp();
+                            let weight = kx
+                                * ky
+                                * luminance_weight
+                                * guides[p].geometry_weight(&guides[q], step, self);
+                            colour += weight * colours[q];
+                            variance += weight * weight * variances[q];
+                            total += weight;
+                        }
+                    }
+                    // The centre tap always has a positive weight, so total > 0.
+                    next_colours[p] = colour / total;
+                    next_variances[p] = variance / (total * total);
+                }
+            }
+            colours = next_colours;
+            variances = next_variances;
+        }
+
+        let pixels = film
+            .pixels()
+            .iter()
+            .zip(&guides)
+            .zip(&colours)
+            .map(|((pixel, guide), colour)| Pixel {
+                sum: guide.remodulate(*colour),
+                weight: 1.0,
+                ..*pixel
+            })
+            .collect();
+        Film::from_pixels(
+            film.width,
+            film.height,
+            bounds,
+            pixels,
+            film.aov_pixels().map(<[AovPixel]>::to_vec),
+        )
+    }
+
+    /// The variance of each pixel's (demodulated) mean luminance. Pixels with too few samples to
+    /// estimate it from fall back on the spread of luminance around them instead.
+    fn initial_variances(
+        &self,
+        pixels: &[Pixel],
+        guides: &[Guide],
+        colours: &[Colour],
+        width: usize,
+    ) -> Vec<f64> {
+        let height = pixels.len() / width.max(1);
+        (0..pixels.len())
+            .map(|p| {
+                let pixel = &pixels[p];
+                if pixel.samples >= 4 {
+                    let albedo = guides[p].albedo.luminance().max(MIN_ALBEDO);
+                    return pixel.variance() / pixel.samples as f64 / (albedo * albedo);
+                }
+                let (x, y) = (p % width, p / width);
+                let (mut sum, mut sum_squares, mut n) = (0.0, 0.0, 0.0);
+                for qy in y.saturating_sub(2)..(y + 3).min(height) {
+                    for qx in x.saturating_sub(2)..(x + 3).min(width) {
+                        let luminance = colours[qy * width + qx].luminance();
+                        sum += luminance;
+                        sum_squares += luminance * luminance;
+                        n += 1.0;
+                    }
+                }
+                let mean = sum / n;
+                (sum_squares / n - mean * mean).max(0.0)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aov::{AovHit, AovSample};
+    use crate::filter::BoxFilter;
+    use rand::rngs::SmallRng;
+    use rand::{Rng, SeedableRng};
+    use rstest::rstest;
+
+    const SIZE: usize = 24;
+
+    /// A film of a wall facing the camera, whose left and right halves face different ways and
+    /// have different brightness, rendered with noisy samples.
+    fn noisy_film(noise: f64, samples: usize) -> Film {
+        let mut rng = SmallRng::seed_from_u64(7);
+        let mut film = Film::new(SIZE, SIZE).with_aovs();
+        for j in 0..SIZE {
+            for i in 0..SIZE {
+                let (brightness, normal) = truth(i);
+                for sample in 0..samples {
+                    let value = brightness * (1.0 + noise * (rng.random::<f64>() - 0.5));
+                    let position = (i as f64 + 0.5, j as f64 + 0.5);
+                    let colour = Colour::new(value, value, value);
+                    film.add_sample(i, j, position, colour, &BoxFilter::default());
+                    let aov = AovSample {
+                        hit: Some(AovHit {
+                            depth: 2.0,
+                            normal,
+                            ..AovHit::default()
+                        }),
+                        albedo: Colour::new(1.0, 1.0, 1.0),
+                    };
+                    film.add_aov_sample(i, j, &aov, sample == 0);
+                }
+            }
+        }
+        film
+    }
+
+    fn truth(i: usize) -> (f64, Vec3) {
+        if i < SIZE / 2 {
+            (0.2, Vec3::new(0.0, 0.0, 1.0))
+        } else {
+            (0.8, Vec3::new(1.0, 0.0, 0.0))
+        }
+    }
+
+    fn error(film: &Film) -> f64 {
+        let mut total = 0.0;
+        for j in 0..SIZE {
+            for i in 0..SIZE {
+                total += (film.pixel(i, j).colour().x - truth(i).0).abs();
+            }
+        }
+        total / (SIZE * SIZE) as f64
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case(1)]
+    #[case(8)]
+    fn test_denoise_reduces_error(#[case] samples: usize) {
+        let film = noisy_film(1.0, samples);
+        let denoised = Denoiser::default().denoise(&film);
+        assert!(
+            error(&denoised) < error(&film) / 2.0,
+            "{} vs {}",
+            error(&denoised),
+            error(&film)
+        );
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_denoise_keeps_edges() {
+        let denoised = Denoiser::default().denoise(&noisy_film(1.0, 8));
+        for j in 0..SIZE {
+            let left = denoised.pixel(SIZE / 2 - 1, j).colour().x;
+            let right = denoised.pixel(SIZE / 2, j).colour().x;
+            assert!((left - 0.2).abs() < 0.1, "left {left}");
+            assert!((right - 0.8).abs() < 0.1, "right {right}");
+        }
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_denoise_leaves_clean_image_alone() {
+        let film = noisy_film(0.0, 4);
+        let denoised = Denoiser::default().denoise(&film);
+        for j in 0..SIZE {
+            for i in 0..SIZE {
+                let (a, b) = (film.pixel(i, j).colour(), denoised.pixel(i, j).colour());
+                assert!((a - b).length() < 1e-9, "{a:?} != {b:?}");
+            }
+        }
+        assert_eq!(denoised.pixel(3, 3).samples, 4);
+    }
+}
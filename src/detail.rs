@@ -0,0 +1,242 @@
+//! Fine surface detail, such as scratches, weave or pores, faked by tilting the shading normal
+//! rather than modelled in the geometry.
+use std::sync::Arc;
+
+use crate::hit::HitRecord;
+use crate::material::{Evaluation, Material, Scatter};
+use crate::medium::Medium;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::texture::Texture;
+use crate::{Colour, Vec3, dot, unit_vector};
+
+/// Step in surface coordinates over which bump maps are differentiated.
+const BUMP_DELTA: f64 = 5e-4;
+
+/// How a surface's normal is tilted.
+#[derive(Debug, Clone)]
+pub enum SurfaceDetail {
+    /// A tangent-space normal map: each colour's channels, mapped from [0, 1] to [-1, 1], are
+    /// the normal along the u tangent, the v tangent and the surface normal. `strength` scales
+    /// the tilt, with 1 as the map has it and 0 flat.
+    NormalMap {
+        map: Arc<dyn Texture>,
+        strength: f64,
+    },
+    /// A height field, read from the texture's first channel and multiplied by `scale` in
+    /// scene units, which the surface seems to be displaced outwards by.
+    Bump {
+        height: Arc<dyn Texture>,
+        scale: f64,
+    },
+}
+
+impl SurfaceDetail {
+    /// The tilted normal at a hit, on the outside of the surface like `outward`.
+    fn outward_normal(&self, rec: &HitRecord, outward: &Vec3) -> Vec3 {
+        match self {
+            SurfaceDetail::NormalMap { map, strength } => {
+                let tangent = unit_vector(&(rec.dpdu - dot(&rec.dpdu, outward) * *outward));
+                let bitangent = outward.cross(tangent);
+                let m = 2.0 * map.at(rec) - Colour::new(1.0, 1.0, 1.0);
+                unit_vector(&(*strength * (m.x * tangent + m.y * bitangent) + m.z * *outward))
+            }
+            SurfaceDetail::Bump { height, scale } => {
+                let at = |du: f64, dv: f64| {
+                    let p = rec.p + du * rec.dpdu + dv * rec.dpdv;
+                    scale * height.value(rec.u + du, rec.v + dv, &p).x
+                };
+                let base = at(0.0, 0.0);
+                let dhdu = (at(BUMP_DELTA, 0.0) - base) / BUMP_DELTA;
+                let dhdv = (at(0.0, BUMP_DELTA) - base) / BUMP_DELTA;
+                // The tangents of the displaced surface, ignoring how the normal itself turns,
+                // which hardly matters for small bumps.
+                let dpdu = rec.dpdu + dhdu * *outward;
+                let dpdv = rec.dpdv + dhdv * *outward;
+                let normal = unit_vector(&dpdu.cross(dpdv));
+                if dot(&normal, outward) < 0.0 {
+                    -normal
+                } else {
+                    normal
+                }
+            }
+        }
+    }
+}
+
+/// Any material with detail added by tilting its shading normal.
+///
+/// Tilted normals can face away from the light arriving, or send light through the surface it
+/// reflects off. Where light arrives from behind the tilted normal the surface is shaded flat,
+/// and light scattered to the wrong side of the true surface is absorbed.
+#[derive(Debug, Clone)]
+pub struct Detailed {
+    pub base: Arc<dyn Material>,
+    pub detail: SurfaceDetail,
+}
+
+impl Detailed {
+    pub fn new(base: Arc<dyn Material>, detail: SurfaceDetail) -> Self {
+        Self { base, detail }
+    }
+
+    /// The hit as the base material should see it, with the shading normal tilted.
+    fn shading_record(&self, r_in: &Ray, rec: &HitRecord) -> HitRecord {
+        let outward = if rec.front_face {
+            rec.geometric_normal
+        } else {
+            -rec.geometric_normal
+        };
+        let tilted = self.detail.outward_normal(rec, &outward);
+        let normal = if rec.front_face { tilted } else { -tilted };
+        let normal = if dot(&r_in.direction, &normal) < 0.0 && normal.length() > 0.0 {
+            normal
+        } else {
+            rec.normal
+        };
+        HitRecord {
+            normal,
+            material: rec.material.clone(),
+            ..*rec
+        }
+    }
+}
+
+impl Material for Detailed {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        let shading = self.shading_record(r_in, rec);
+        let scatter = self.base.scatter(r_in, &shading, sampler)?;
+        let direction = scatter.ray.direction;
+        let geometric_side = dot(&direction, &rec.geometric_normal) > 0.0;
+        let shading_side = dot(&direction, &shading.normal) > 0.0;
+        (geometric_side == shading_side).then_some(scatter)
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Colour {
+        self.base.emitted(rec)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Colour {
+        self.base.albedo(rec)
+    }
+
+    fn medium(&self) -> Option<Medium> {
+        self.base.medium()
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Option<Evaluation> {
+        let shading = self.shading_record(r_in, rec);
+        let evaluation = self.base.eval(r_in, &shading, direction)?;
+        let geometric_side = dot(direction, &rec.geometric_normal) > 0.0;
+        let shading_side = dot(direction, &shading.normal) > 0.0;
+        Some(if geometric_side == shading_side {
+            evaluation
+        } else {
+            Evaluation {
+                f: Colour::default(),
+                pdf: 0.0,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Point3;
+    use crate::hit::Hittable;
+    use crate::material::Diffuse;
+    use crate::sampler::SamplerKind;
+    use crate::sphere::Sphere;
+    use crate::texture::SolidColour;
+    use rstest::rstest;
+
+    /// A texture whose value is a function of the surface coordinates.
+    #[derive(Debug)]
+    struct Ramp(fn(f64, f64) -> f64);
+
+    impl Texture for Ramp {
+        fn value(&self, u: f64, v: f64, _p: &Point3) -> Colour {
+            let value = (self.0)(u, v);
+            Colour::new(value, value, value)
+        }
+    }
+
+    fn hit_sphere(detail: SurfaceDetail) -> (Ray, HitRecord, Detailed) {
+        let r = Ray::new(Point3::new(3.0, 0.2, 0.1), Vec3::new(-1.0, 0.0, 0.0));
+        let rec = Sphere::new(Point3::default(), 1.0)
+            .hit(&r, &(0.001..f64::INFINITY))
+            .unwrap();
+        let material = Detailed::new(Arc::new(Diffuse::default()), detail);
+        (r, rec, material)
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_flat_details_keep_the_normal() {
+        let flat = Arc::new(SolidColour::new(Colour::new(0.5, 0.5, 1.0)));
+        for detail in [
+            SurfaceDetail::NormalMap {
+                map: flat.clone(),
+                strength: 1.0,
+            },
+            SurfaceDetail::Bump {
+                height: flat,
+                scale: 0.3,
+            },
+        ] {
+            let (r, rec, material) = hit_sphere(detail);
+            let shading = material.shading_record(&r, &rec);
+            assert!((shading.normal - rec.normal).length() < 1e-9);
+        }
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_normal_map_tilts_towards_tangent() {
+        let map = Arc::new(SolidColour::new(Colour::new(1.0, 0.5, 1.0)));
+        let (r, rec, material) = hit_sphere(SurfaceDetail::NormalMap { map, strength: 1.0 });
+        let shading = material.shading_record(&r, &rec);
+        let tangent = unit_vector(&rec.dpdu);
+        // Halfway between the normal and the tangent along u.
+        let want = unit_vector(&(rec.normal + tangent));
+        assert!(
+            (shading.normal - want).length() < 1e-3,
+            "{:?}",
+            shading.normal
+        );
+        assert!((shading.normal.length() - 1.0).abs() < 1e-9);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_bump_tilts_against_the_slope() {
+        // Rising along u, so the normal leans back along -u.
+        let (r, rec, material) = hit_sphere(SurfaceDetail::Bump {
+            height: Arc::new(Ramp(|u, _| u)),
+            scale: 0.1,
+        });
+        let shading = material.shading_record(&r, &rec);
+        assert!(dot(&shading.normal, &rec.dpdu) < -0.05);
+        assert!(dot(&shading.normal, &rec.dpdv).abs() < 1e-6);
+        assert!(dot(&shading.normal, &rec.normal) > 0.5);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_scatter_stays_on_the_lit_side() {
+        // A steep normal map sends many diffuse rays below the true surface.
+        let map = Arc::new(SolidColour::new(Colour::new(1.0, 0.5, 0.6)));
+        let (r, rec, material) = hit_sphere(SurfaceDetail::NormalMap { map, strength: 1.0 });
+        let mut sampler = SamplerKind::Independent.build(1, 0);
+        let (mut kept, n) = (0, 1000);
+        for sample_index in 0..n {
+            sampler.start_pixel_sample(0, 0, sample_index);
+            if let Some(scatter) = material.scatter(&r, &rec, sampler.as_mut()) {
+                assert!(dot(&scatter.ray.direction, &rec.geometric_normal) > 0.0);
+                kept += 1;
+            }
+        }
+        assert!(kept > n / 2 && kept < n, "{kept}");
+    }
+}
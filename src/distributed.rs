@@ -0,0 +1,567 @@
+//! Rendering across several processes. A coordinator listens for workers, hands each one tiles
+//! to render, and assembles the tiles they send back into the final film. Workers must be run
+//! with the same scene and camera settings as the coordinator. Only the camera settings are
+//! checked, with `Camera::fingerprint` when they connect: a worker given a different scene is
+//! accepted, and its tiles won't match the rest of the image.
+//!
+//! The protocol is a stream of messages, each a tag byte followed by little endian fields:
+//!
+//! - worker -> coordinator `HELLO`: fingerprint
+//! - coordinator -> worker `TILE`: tile index, x0, y0, x1, y1
+//! - worker -> coordinator `RESULT`: tile index, film
+//! - coordinator -> worker `DONE`: no fields; the worker should exit
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::budget::{Budget, StopReason};
+use crate::camera::Camera;
+use crate::checkpoint::{read_film_bounds, read_film_pixels, read_u64, write_film};
+use crate::film::{Bounds, Film};
+use crate::hit::HittableList;
+use crate::progress::{ProgressObserver, ProgressUpdate};
+
+const HELLO: u8 = 1;
+const TILE: u8 = 2;
+const RESULT: u8 = 3;
+const DONE: u8 = 4;
+
+/// How long the coordinator waits for a new connection to say `HELLO`. Workers send it as soon as
+/// they connect, so anything slower isn't a worker.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often the coordinator checks for new workers while tiles are outstanding.
+const ACCEPT_POLL: Duration = Duration::from_millis(50);
+
+fn read_tag(input: &mut dyn Read) -> io::Result<u8> {
+    let mut tag = [0; 1];
+    input.read_exact(&mut tag)?;
+    Ok(tag[0])
+}
+
+fn unexpected(tag: u8) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("unexpected message {tag}"),
+    )
+}
+
+/// Tiles still to be rendered, and the results of those that have been.
+struct Queue {
+    pending: VecDeque<usize>,
+    results: BTreeMap<usize, Film>,
+    /// Set once the budget runs out, after which no more tiles are handed out.
+    stopped: Option<StopReason>,
+}
+
+struct Shared<'a> {
+    camera: &'a Camera,
+    tiles: &'a [Bounds],
+    fingerprint: u64,
+    tile_timeout: Duration,
+    budget: &'a Budget,
+    deadline: Option<Instant>,
+    queue: Mutex<Queue>,
+    changed: Condvar,
+    observer: &'a dyn ProgressObserver,
+    start: Instant,
+    progress: Mutex<ProgressUpdate>,
+}
+
+impl Shared<'_> {
+    fn finished(&self, queue: &Queue) -> bool {
+        queue.results.len() == self.tiles.len() || queue.stopped.is_some()
+    }
+
+    /// Wait for a tile to hand out, or `None` once every tile has been rendered or the budget
+    /// has run out.
+    fn next_tile(&self) -> Option<usize> {
+        let mut queue = self.queue.lock().unwrap();
+        loop {
+            self.check_budget(&mut queue);
+            if queue.stopped.is_some() {
+                return None;
+            }
+            if let Some(index) = queue.pending.pop_front() {
+                return Some(index);
+            }
+            if self.finished(&queue) {
+                return None;
+            }
+            queue = self.changed.wait(queue).unwrap();
+        }
+    }
+
+    /// Stop handing out tiles if the budget has run out, waking any workers waiting for one.
+    fn check_budget(&self, queue: &mut Queue) {
+        if queue.stopped.is_none()
+            && let Some(reason) = self.budget.interrupted(self.deadline)
+        {
+            log::info!("Handing out no more tiles: {reason:?}");
+            queue.stopped = Some(reason);
+            self.changed.notify_all();
+        }
+    }
+
+    fn complete(&self, index: usize, film: Film) {
+        let mut queue = self.queue.lock().unwrap();
+        // Workers don't report their sample or ray counts back.
+        let mut progress = self.progress.lock().unwrap();
+        progress.tile_done(self.start, 0, 0);
+        self.observer.update(&progress);
+        queue.results.insert(index, film);
+        self.changed.notify_all();
+    }
+
+    fn retry(&self, index: usize) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.pending.push_front(index);
+        self.changed.notify_all();
+    }
+}
+
+/// Render `camera`'s image on whichever workers connect to `listener`, returning once every tile
+/// has come back. A worker that disconnects, misbehaves, or takes longer than `tile_timeout` over
+/// a tile is dropped and its tile given to another worker.
+///
+/// Once the budget's time runs out or it's cancelled no more tiles are handed out, and the image
+/// is returned with the tiles that came back, after waiting for those already handed out. Its
+/// noise target isn't used: tiles aren't rendered in passes.
+pub fn coordinate(
+    camera: &Camera,
+    listener: TcpListener,
+    tile_timeout: Duration,
+    budget: &Budget,
+) -> io::Result<(Film, StopReason)> {
+    let tiles = camera.tiles();
+    let shared = Shared {
+        camera,
+        tiles: &tiles,
+        fingerprint: camera.fingerprint(),
+        tile_timeout,
+        budget,
+        deadline: budget.time.map(|time| Instant::now() + time),
+        queue: Mutex::new(Queue {
+            pending: (0..tiles.len()).collect(),
+            results: BTreeMap::new(),
+            stopped: None,
+        }),
+        changed: Condvar::new(),
+        observer: camera.progress(),
+        start: Instant::now(),
+        progress: Mutex::new(ProgressUpdate::new(tiles.len(), 1)),
+    };
+    log::info!(
+        "Waiting for workers on {} to render {} tiles",
+        listener.local_addr()?,
+        tiles.len()
+    );
+
+    listener.set_nonblocking(true)?;
+    thread::scope(|scope| {
+        loop {
+            {
+                let mut queue = shared.queue.lock().unwrap();
+                shared.check_budget(&mut queue);
+                if shared.finished(&queue) {
+                    break;
+                }
+            }
+            match listener.accept() {
+                Ok((stream, address)) => {
+                    log::info!("Worker connected from {address}");
+                    let shared = &shared;
+                    scope.spawn(move || serve_worker(shared, stream, address));
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => thread::sleep(ACCEPT_POLL),
+                Err(err) => {
+                    // Typically transient (e.g. out of file descriptors), and the workers we
+                    // already have can carry on regardless.
+                    log::warn!("Failed to accept worker: {err}");
+                    thread::sleep(ACCEPT_POLL);
+                }
+            }
+        }
+    });
+    shared.observer.finish(&shared.progress.lock().unwrap());
+
+    // Merge in tile order, as a local render does.
+    let queue = shared.queue.into_inner().unwrap();
+    let mut film = camera.new_film();
+    for tile_film in queue.results.values() {
+        film.merge(tile_film);
+    }
+    Ok((film, queue.stopped.unwrap_or(StopReason::Completed)))
+}
+
+fn serve_worker(shared: &Shared, stream: TcpStream, address: SocketAddr) {
+    if let Err(err) = try_serve_worker(shared, stream) {
+        log::warn!("Dropping worker {address}: {err}");
+    }
+}
+
+fn try_serve_worker(shared: &Shared, stream: TcpStream) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    match read_tag(&mut reader)? {
+        HELLO => {
+            let fingerprint = read_u64(&mut reader)?;
+            if fingerprint != shared.fingerprint {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "worker has different camera settings",
+                ));
+            }
+        }
+        tag => return Err(unexpected(tag)),
+    }
+    writer
+        .get_ref()
+        .set_read_timeout(Some(shared.tile_timeout))?;
+
+    while let Some(index) = shared.next_tile() {
+        match render_remote(
+            &mut reader,
+            &mut writer,
+            shared.camera,
+            index,
+            shared.tiles[index],
+        ) {
+            Ok(film) => shared.complete(index, film),
+            Err(err) => {
+                shared.retry(index);
+                return Err(err);
+            }
+        }
+    }
+    writer.write_all(&[DONE])?;
+    writer.flush()
+}
+
+fn render_remote(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    camera: &Camera,
+    index: usize,
+    tile: Bounds,
+) -> io::Result<Film> {
+    writer.write_all(&[TILE])?;
+    for value in [index, tile.x0, tile.y0, tile.x1, tile.y1] {
+        writer.write_all(&(value as u64).to_le_bytes())?;
+    }
+    writer.flush()?;
+
+    match read_tag(reader)? {
+        RESULT => {
+            let result_index = read_u64(reader)? as usize;
+            // Checked before the pixels are read, so a worker can't make us allocate more than
+            // the film we asked for.
+            let (width, height, bounds) = read_film_bounds(reader)?;
+            let image = camera.image_bounds();
+            if result_index != index
+                || (width, height) != (image.x1, image.y1)
+                || bounds != camera.tile_film_bounds(tile)
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("worker returned the wrong film for tile {index}"),
+                ));
+            }
+            read_film_pixels(reader, width, height, bounds)
+        }
+        tag => Err(unexpected(tag)),
+    }
+}
+
+/// Connect to a coordinator and render the tiles it hands out until it says it's done.
+pub fn work(
+    camera: &Camera,
+    world: &HittableList,
+    coordinator: impl ToSocketAddrs,
+) -> io::Result<()> {
+    let stream = TcpStream::connect(coordinator)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    writer.write_all(&[HELLO])?;
+    writer.write_all(&camera.fingerprint().to_le_bytes())?;
+    writer.flush()?;
+
+    let sampler = camera.sampler_setup();
+    loop {
+        match read_tag(&mut reader)? {
+            TILE => {
+                let index = read_u64(&mut reader)?;
+                let tile = Bounds {
+                    x0: read_u64(&mut reader)? as usize,
+                    y0: read_u64(&mut reader)? as usize,
+                    x1: read_u64(&mut reader)? as usize,
+                    y1: read_u64(&mut reader)? as usize,
+                };
+                let image = camera.image_bounds();
+                if tile.x0 > tile.x1
+                    || tile.x1 > image.x1
+                    || tile.y0 > tile.y1
+                    || tile.y1 > image.y1
+                {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("tile {index} is outside the image: {tile:?}"),
+                    ));
+                }
+                log::debug!("Rendering tile {index}: {tile:?}");
+                let film = camera.render_tile(world, &sampler, tile);
+                writer.write_all(&[RESULT])?;
+                writer.write_all(&index.to_le_bytes())?;
+                write_film(&mut writer, &film)?;
+                writer.flush()?;
+            }
+            DONE => return Ok(()),
+            tag => return Err(unexpected(tag)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::budget::CancelToken;
+    use crate::camera::AdaptiveSampling;
+    use crate::sampler::SamplerKind;
+    use crate::sphere::Sphere;
+    use crate::{Colour, Point3};
+    use rstest::rstest;
+
+    fn test_world() -> HittableList {
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5)));
+        world.add(Box::new(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0)));
+        world
+    }
+
+    fn pixels(film: &Film) -> Vec<Colour> {
+        (0..film.height)
+            .flat_map(|j| (0..film.width).map(move |i| (i, j)))
+            .map(|(i, j)| film.pixel(i, j).colour())
+            .collect()
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case(Camera::new(2.0, 80, 4).with_sampler(SamplerKind::Sobol))]
+    #[case(Camera::new(2.0, 80, 4).with_sampler(SamplerKind::Pmj).with_pass_samples(2))]
+    #[case(
+        Camera::new(2.0, 80, 4)
+            .with_sampler(SamplerKind::Sobol)
+            .with_pass_samples(2)
+            .with_adaptive_sampling(AdaptiveSampling {
+                threshold: 0.1,
+                max_samples: 16,
+            })
+    )]
+    fn test_distributed_matches_local_render(#[case] camera: Camera) {
+        let world = test_world();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let film = thread::scope(|scope| {
+            let coordinator = scope.spawn(|| {
+                coordinate(
+                    &camera,
+                    listener,
+                    Duration::from_secs(30),
+                    &Budget::default(),
+                )
+            });
+
+            // A worker that takes a tile and then dies; its tile has to go to someone else.
+            let mut dead = TcpStream::connect(address).unwrap();
+            dead.write_all(&[HELLO]).unwrap();
+            dead.write_all(&camera.fingerprint().to_le_bytes()).unwrap();
+            assert_eq!(read_tag(&mut dead).unwrap(), TILE);
+            drop(dead);
+
+            for _ in 0..2 {
+                scope.spawn(|| work(&camera, &world, address).unwrap());
+            }
+            let (film, stop) = coordinator.join().unwrap().unwrap();
+            assert_eq!(stop, StopReason::Completed);
+            film
+        });
+
+        let local = camera.render(&world);
+        assert_eq!(pixels(&film), pixels(&local));
+        let samples = |film: &Film| film.pixels().iter().map(|p| p.samples).collect::<Vec<_>>();
+        assert_eq!(samples(&film), samples(&local));
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case([u64::MAX, u64::MAX, 0, 0, u64::MAX, u64::MAX])]
+    #[case([1 << 20, 1 << 20, 0, 0, 1 << 20, 1 << 20])]
+    #[case([32, 8, 0, 0, 16, 8])]
+    #[case([16, 8, 0, 0, 8, 8])]
+    fn test_worker_sending_the_wrong_film_is_dropped(#[case] header: [u64; 6]) {
+        let world = test_world();
+        let camera = Camera::new(2.0, 16, 2);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        thread::scope(|scope| {
+            let coordinator = scope.spawn(|| {
+                coordinate(
+                    &camera,
+                    listener,
+                    Duration::from_secs(30),
+                    &Budget::default(),
+                )
+            });
+            let mut liar = TcpStream::connect(address).unwrap();
+            liar.write_all(&[HELLO]).unwrap();
+            liar.write_all(&camera.fingerprint().to_le_bytes()).unwrap();
+            assert_eq!(read_tag(&mut liar).unwrap(), TILE);
+            let index = read_u64(&mut liar).unwrap();
+            let mut tile = [0; 32];
+            liar.read_exact(&mut tile).unwrap();
+            liar.write_all(&[RESULT]).unwrap();
+            liar.write_all(&index.to_le_bytes()).unwrap();
+            for value in header {
+                liar.write_all(&value.to_le_bytes()).unwrap();
+            }
+            // Dropped before it sends any pixels.
+            assert_eq!(liar.read(&mut [0; 1]).unwrap(), 0);
+
+            work(&camera, &world, address).unwrap();
+            coordinator.join().unwrap().unwrap();
+        });
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case([0, 0, 17, 8])]
+    #[case([0, 0, 16, 9])]
+    #[case([5, 0, 4, 8])]
+    #[case([0, 5, 16, 4])]
+    #[case([u64::MAX, 0, u64::MAX, 8])]
+    fn test_worker_rejects_tiles_outside_the_image(#[case] bounds: [u64; 4]) {
+        let world = test_world();
+        let camera = Camera::new(2.0, 16, 2);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        thread::scope(|scope| {
+            let worker = scope.spawn(|| work(&camera, &world, address));
+            let (mut stream, _) = listener.accept().unwrap();
+            assert_eq!(read_tag(&mut stream).unwrap(), HELLO);
+            read_u64(&mut stream).unwrap();
+            stream.write_all(&[TILE]).unwrap();
+            for value in [0].into_iter().chain(bounds) {
+                stream.write_all(&value.to_le_bytes()).unwrap();
+            }
+            let err = worker.join().unwrap().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        });
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_silent_connection_is_dropped_after_the_handshake_timeout() {
+        let world = test_world();
+        let camera = Camera::new(2.0, 16, 2);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let start = Instant::now();
+
+        thread::scope(|scope| {
+            let coordinator = scope.spawn(|| {
+                coordinate(
+                    &camera,
+                    listener,
+                    Duration::from_secs(60),
+                    &Budget::default(),
+                )
+            });
+            let _silent = TcpStream::connect(address).unwrap();
+            work(&camera, &world, address).unwrap();
+            coordinator.join().unwrap().unwrap();
+        });
+        assert!(start.elapsed() < HANDSHAKE_TIMEOUT + Duration::from_secs(5));
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_budget_stops_handing_out_tiles() {
+        let world = test_world();
+        let camera = Camera::new(2.0, 80, 4);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let cancel = CancelToken::new();
+        let budget = Budget {
+            cancel: Some(cancel.clone()),
+            ..Budget::default()
+        };
+
+        thread::scope(|scope| {
+            let coordinator =
+                scope.spawn(|| coordinate(&camera, listener, Duration::from_secs(30), &budget));
+            // Take one tile and render it, then cancel before asking for another.
+            let mut stream = TcpStream::connect(address).unwrap();
+            stream.write_all(&[HELLO]).unwrap();
+            stream
+                .write_all(&camera.fingerprint().to_le_bytes())
+                .unwrap();
+            assert_eq!(read_tag(&mut stream).unwrap(), TILE);
+            let index = read_u64(&mut stream).unwrap();
+            let mut bounds = [0; 4];
+            for value in &mut bounds {
+                *value = read_u64(&mut stream).unwrap() as usize;
+            }
+            let [x0, y0, x1, y1] = bounds;
+            let tile = Bounds { x0, y0, x1, y1 };
+            cancel.cancel();
+            stream.write_all(&[RESULT]).unwrap();
+            stream.write_all(&index.to_le_bytes()).unwrap();
+            write_film(
+                &mut stream,
+                &camera.render_tile(&world, &camera.sampler_setup(), tile),
+            )
+            .unwrap();
+            assert_eq!(read_tag(&mut stream).unwrap(), DONE);
+
+            let (film, stop) = coordinator.join().unwrap().unwrap();
+            assert_eq!(stop, StopReason::Cancelled);
+            assert!(film.pixel(tile.x0, tile.y0).samples > 0);
+            assert!(film.pixel(79, 39).samples == 0);
+        });
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_mismatched_worker_is_rejected() {
+        let world = test_world();
+        let camera = Camera::new(2.0, 16, 2);
+        let other = Camera::new(2.0, 16, 2).with_seed(3);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        thread::scope(|scope| {
+            let coordinator = scope.spawn(|| {
+                coordinate(
+                    &camera,
+                    listener,
+                    Duration::from_secs(30),
+                    &Budget::default(),
+                )
+            });
+            assert!(work(&other, &world, address).is_err());
+            work(&camera, &world, address).unwrap();
+            coordinator.join().unwrap().unwrap();
+        });
+    }
+}
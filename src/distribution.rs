@@ -0,0 +1,159 @@
+//! Piecewise-constant distributions, for sampling in proportion to a tabulated function such as
+//! the brightness of an image.
+
+/// A distribution over [0, 1] in proportion to a function that's constant over each of a
+/// number of equal bins.
+#[derive(Debug, Clone)]
+pub struct Distribution1D {
+    func: Vec<f64>,
+    /// Running integral of the function over the bins, from 0 up to 1.
+    cdf: Vec<f64>,
+    integral: f64,
+}
+
+impl Distribution1D {
+    /// Negative values count as zero. A function that's zero everywhere is sampled uniformly.
+    pub fn new(func: &[f64]) -> Self {
+        let n = func.len().max(1);
+        let mut func: Vec<f64> = func.iter().map(|f| f.max(0.0)).collect();
+        func.resize(n, 0.0);
+        let mut cdf = vec![0.0; n + 1];
+        for i in 0..n {
+            cdf[i + 1] = cdf[i] + func[i] / n as f64;
+        }
+        let integral = cdf[n];
+        if integral > 0.0 {
+            cdf.iter_mut().for_each(|c| *c /= integral);
+        } else {
+            for (i, c) in cdf.iter_mut().enumerate() {
+                *c = i as f64 / n as f64;
+            }
+        }
+        Self {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    /// The function's integral over [0, 1].
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    /// A sample in [0, 1) for a uniform `u`, with its density and the bin it fell in.
+    pub fn sample(&self, u: f64) -> (f64, f64, usize) {
+        let n = self.func.len();
+        // The last bin whose running integral starts at or before u.
+        let bin = (self.cdf.partition_point(|&c| c <= u).max(1) - 1).min(n - 1);
+        let width = self.cdf[bin + 1] - self.cdf[bin];
+        let offset = if width > 0.0 {
+            ((u - self.cdf[bin]) / width).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let x = ((bin as f64 + offset) / n as f64).min(1.0 - f64::EPSILON / 2.0);
+        (x, self.pdf_bin(bin), bin)
+    }
+
+    fn pdf_bin(&self, bin: usize) -> f64 {
+        if self.integral > 0.0 {
+            self.func[bin] / self.integral
+        } else {
+            1.0
+        }
+    }
+
+    /// The density of sampling `x` in [0, 1].
+    pub fn pdf(&self, x: f64) -> f64 {
+        let n = self.func.len();
+        self.pdf_bin(((x * n as f64) as usize).min(n - 1))
+    }
+}
+
+/// A distribution over [0, 1]² in proportion to a function that's constant over each cell of a
+/// grid, sampled as a row from the marginal distribution and then a column within that row.
+#[derive(Debug, Clone)]
+pub struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    /// `func` holds `height` rows of `width` values each. The first coordinate goes along the
+    /// rows, and the second down the columns.
+    pub fn new(func: &[f64], width: usize, height: usize) -> Self {
+        let rows: Vec<Distribution1D> = (0..height)
+            .map(|y| Distribution1D::new(&func[y * width..(y + 1) * width]))
+            .collect();
+        let marginal =
+            Distribution1D::new(&rows.iter().map(|row| row.integral()).collect::<Vec<_>>());
+        Self { rows, marginal }
+    }
+
+    pub fn integral(&self) -> f64 {
+        self.marginal.integral()
+    }
+
+    /// A point in [0, 1)² for uniform `u`, with its density.
+    pub fn sample(&self, u: (f64, f64)) -> ((f64, f64), f64) {
+        let (y, pdf_y, row) = self.marginal.sample(u.1);
+        let (x, pdf_x, _) = self.rows[row].sample(u.0);
+        ((x, y), pdf_x * pdf_y)
+    }
+
+    /// The density of sampling (x, y).
+    pub fn pdf(&self, x: f64, y: f64) -> f64 {
+        let n = self.rows.len();
+        let row = ((y * n as f64) as usize).min(n - 1);
+        self.marginal.pdf(y) * self.rows[row].pdf(x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_1d_samples_in_proportion() {
+        let distribution = Distribution1D::new(&[1.0, 0.0, 3.0, 0.0]);
+        assert_eq!(distribution.integral(), 1.0);
+        let n = 1000;
+        let mut counts = [0; 4];
+        for i in 0..n {
+            let (x, pdf, bin) = distribution.sample((i as f64 + 0.5) / n as f64);
+            assert_eq!(bin, (x * 4.0) as usize);
+            assert_eq!(pdf, distribution.pdf(x));
+            counts[bin] += 1;
+        }
+        assert_eq!(counts, [250, 0, 750, 0]);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_1d_zero_function_is_uniform() {
+        let distribution = Distribution1D::new(&[0.0, 0.0]);
+        assert_eq!(distribution.sample(0.75), (0.75, 1.0, 1));
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_2d_density_integrates_to_one() {
+        let func = [0.0, 1.0, 2.0, 3.0, 4.0, 5.0];
+        let distribution = Distribution2D::new(&func, 3, 2);
+        // One point in each cell, each a sixth of the area.
+        let mut total = 0.0;
+        for y in 0..2 {
+            for x in 0..3 {
+                let pdf = distribution.pdf((x as f64 + 0.5) / 3.0, (y as f64 + 0.5) / 2.0);
+                assert!((pdf - func[y * 3 + x] / 2.5).abs() < 1e-12);
+                total += pdf / 6.0;
+            }
+        }
+        assert!((total - 1.0).abs() < 1e-12);
+        let ((x, y), pdf) = distribution.sample((0.5, 0.9));
+        assert!(y >= 0.5 && (pdf - distribution.pdf(x, y)).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,235 @@
+//! What rays see when they escape the scene: light arriving from infinitely far away.
+use std::f64::consts::PI;
+use std::fmt::Debug;
+
+use crate::distribution::Distribution2D;
+use crate::image::Image;
+use crate::sampler::Sampler;
+use crate::sphere::Sphere;
+use crate::{Colour, Vec3, unit_vector};
+
+/// A direction to look for light in, chosen in proportion to how much arrives from it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LightSample {
+    /// Unit length, pointing towards the light.
+    pub direction: Vec3,
+    pub radiance: Colour,
+    /// Density per solid angle of having chosen `direction`.
+    pub pdf: f64,
+}
+
+/// Light arriving from every direction, from beyond everything in the scene.
+pub trait Background: Send + Sync + Debug {
+    /// The light seen looking along `direction`, which needn't be unit length.
+    fn radiance(&self, direction: &Vec3) -> Colour;
+
+    /// Choose a direction to look for light in. Backgrounds that can't choose well return
+    /// `None`, and are only seen by rays that happen to escape.
+    fn sample(&self, _sampler: &mut dyn Sampler) -> Option<LightSample> {
+        None
+    }
+
+    /// The density per solid angle of `sample` choosing `direction`.
+    fn pdf(&self, _direction: &Vec3) -> f64 {
+        0.0
+    }
+}
+
+/// A sky fading from white at the horizon up to blue.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Gradient;
+
+impl Background for Gradient {
+    fn radiance(&self, direction: &Vec3) -> Colour {
+        let unit_direction = unit_vector(direction);
+        let a = 0.5 * (unit_direction.y + 1.0);
+        (1.0 - a) * Colour::new(1.0, 1.0, 1.0) + a * Colour::new(0.5, 0.7, 1.0)
+    }
+}
+
+/// An equirectangular (latitude-longitude) image of the surroundings, such as an HDR photo of
+/// a real place, lighting the scene. Bright parts, like the sun, are sampled in proportion to
+/// their brightness.
+#[derive(Debug, Clone)]
+pub struct EnvironmentMap {
+    image: Image,
+    /// Turn about the Y axis, in degrees.
+    pub rotation: f64,
+    /// What the image's values are multiplied by.
+    pub intensity: f64,
+    /// Over (u, 1 - v), to match the image's rows from the top.
+    distribution: Distribution2D,
+}
+
+impl EnvironmentMap {
+    /// The image is wrapped around the scene as `Sphere::uv` maps a sphere, with its top row
+    /// straight up.
+    pub fn new(image: Image) -> Self {
+        // Bilinear lookups blend each pixel into its neighbours, so each is sampled as if it
+        // were as bright as the brightest around it, or light would be found where it's
+        // hardly ever sampled. Rows near the poles cover less of the sphere, so are chosen
+        // less often.
+        let (width, height) = (image.width, image.height);
+        let luminance = |i: usize, j: usize| image.pixels[j * width + i].luminance().max(0.0);
+        let mut func = Vec::with_capacity(width * height);
+        for j in 0..height {
+            let sin_theta = (PI * (j as f64 + 0.5) / height as f64).sin();
+            for i in 0..width {
+                let mut brightest = 0.0_f64;
+                for dj in [-1, 0, 1] {
+                    let Some(row) = j.checked_add_signed(dj).filter(|&row| row < height) else {
+                        continue;
+                    };
+                    for di in [width - 1, 0, 1] {
+                        brightest = brightest.max(luminance((i + di) % width, row));
+                    }
+                }
+                func.push(brightest * sin_theta);
+            }
+        }
+        let distribution = Distribution2D::new(&func, image.width, image.height);
+        Self {
+            image,
+            rotation: 0.0,
+            intensity: 1.0,
+            distribution,
+        }
+    }
+
+    pub fn with_rotation(mut self, degrees: f64) -> Self {
+        self.rotation = degrees;
+        self
+    }
+
+    pub fn with_intensity(mut self, intensity: f64) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    /// Turn a direction about Y by `angle` radians.
+    fn rotate(direction: &Vec3, angle: f64) -> Vec3 {
+        let (sin, cos) = angle.sin_cos();
+        Vec3::new(
+            cos * direction.x + sin * direction.z,
+            direction.y,
+            -sin * direction.x + cos * direction.z,
+        )
+    }
+
+    /// The image coordinates seen along a world direction.
+    fn uv(&self, direction: &Vec3) -> (f64, f64) {
+        let local = Self::rotate(&unit_vector(direction), -self.rotation.to_radians());
+        Sphere::uv(&local)
+    }
+
+    /// The world direction at image coordinates (u, v), and the sine of its angle from the
+    /// poles.
+    fn direction(&self, u: f64, v: f64) -> (Vec3, f64) {
+        let theta = PI * v;
+        let phi = 2.0 * PI * u - PI;
+        let (sin_theta, cos_theta) = theta.sin_cos();
+        let local = Vec3::new(sin_theta * phi.cos(), -cos_theta, -sin_theta * phi.sin());
+        (Self::rotate(&local, self.rotation.to_radians()), sin_theta)
+    }
+}
+
+impl Background for EnvironmentMap {
+    fn radiance(&self, direction: &Vec3) -> Colour {
+        let (u, v) = self.uv(direction);
+        self.intensity * self.image.bilinear(u, v)
+    }
+
+    fn sample(&self, sampler: &mut dyn Sampler) -> Option<LightSample> {
+        let u = sampler.get_2d();
+        if self.distribution.integral() <= 0.0 {
+            return None;
+        }
+        let ((u, row), pdf) = self.distribution.sample(u);
+        let (direction, sin_theta) = self.direction(u, 1.0 - row);
+        if pdf <= 0.0 || sin_theta <= 0.0 {
+            return None;
+        }
+        // From density over the image to density over the sphere of directions.
+        Some(LightSample {
+            direction,
+            radiance: self.radiance(&direction),
+            pdf: pdf / (2.0 * PI * PI * sin_theta),
+        })
+    }
+
+    fn pdf(&self, direction: &Vec3) -> f64 {
+        let (u, v) = self.uv(direction);
+        let sin_theta = (PI * v).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(u, 1.0 - v) / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::SamplerKind;
+    use crate::{dot, sample_uniform_sphere};
+    use rstest::rstest;
+
+    /// A dark map with one bright pixel, in cells 22.5 degrees across.
+    fn bright_spot() -> Image {
+        let (width, height) = (16, 8);
+        let mut pixels = vec![Colour::new(0.1, 0.1, 0.1); width * height];
+        pixels[2 * width + 11] = Colour::new(100.0, 80.0, 60.0);
+        Image {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case(0.0)]
+    #[case(70.0)]
+    fn test_directions_round_trip(#[case] rotation: f64) {
+        let map = EnvironmentMap::new(bright_spot()).with_rotation(rotation);
+        for (u, v) in [(0.1, 0.2), (0.5, 0.5), (0.9, 0.7)] {
+            let (direction, _) = map.direction(u, v);
+            let (u2, v2) = map.uv(&direction);
+            assert!((u - u2).abs() < 1e-9 && (v - v2).abs() < 1e-9);
+        }
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_sampling_matches_pdf_and_favours_the_bright_spot() {
+        let map = EnvironmentMap::new(bright_spot()).with_rotation(30.0);
+        let mut sampler = SamplerKind::Independent.build(1, 0);
+        let spot = map.direction(11.5 / 16.0, 1.0 - 2.5 / 8.0).0;
+        let n = 2000;
+        let mut near_spot = 0;
+        for sample_index in 0..n {
+            sampler.start_pixel_sample(0, 0, sample_index);
+            let sample = map.sample(sampler.as_mut()).unwrap();
+            assert!((sample.pdf - map.pdf(&sample.direction)).abs() < 1e-6 * sample.pdf);
+            assert_eq!(sample.radiance, map.radiance(&sample.direction));
+            near_spot += usize::from(dot(&sample.direction, &spot) > 0.7);
+        }
+        assert!(near_spot > n * 9 / 10, "{near_spot}");
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_pdf_integrates_to_one() {
+        let map = EnvironmentMap::new(bright_spot());
+        let n = 200;
+        let mut total = 0.0;
+        for i in 0..n {
+            for j in 0..n {
+                let u = ((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64);
+                total += map.pdf(&sample_uniform_sphere(u)) * 4.0 * PI;
+            }
+        }
+        let mean = total / (n * n) as f64;
+        assert!((mean - 1.0).abs() < 0.02, "{mean}");
+    }
+}
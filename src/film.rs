@@ -0,0 +1,511 @@
+use std::io::{self, Write};
+
+use crate::Colour;
+use crate::aov::{Aov, AovPixel, AovSample, write_pfm};
+use crate::filter::Filter;
+
+/// A rectangle of pixels, covering [x0, x1) x [y0, y1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bounds {
+    pub x0: usize,
+    pub y0: usize,
+    pub x1: usize,
+    pub y1: usize,
+}
+
+impl Bounds {
+    pub fn width(&self) -> usize {
+        self.x1 - self.x0
+    }
+
+    pub fn height(&self) -> usize {
+        self.y1 - self.y0
+    }
+
+    pub fn area(&self) -> usize {
+        self.width() * self.height()
+    }
+
+    pub fn contains(&self, i: usize, j: usize) -> bool {
+        (self.x0..self.x1).contains(&i) && (self.y0..self.y1).contains(&j)
+    }
+
+    /// Splits a width x height image into tiles of at most `size` x `size` pixels, in row order.
+    pub fn tiles(width: usize, height: usize, size: usize) -> Vec<Bounds> {
+        let size = size.max(1);
+        let mut tiles = Vec::new();
+        for y0 in (0..height).step_by(size) {
+            for x0 in (0..width).step_by(size) {
+                tiles.push(Bounds {
+                    x0,
+                    y0,
+                    x1: (x0 + size).min(width),
+                    y1: (y0 + size).min(height),
+                });
+            }
+        }
+        tiles
+    }
+}
+
+/// Accumulated samples for a single pixel.
+///
+/// The colour is a filter weighted sum of every sample splatted onto this pixel, while the sample
+/// count and luminance statistics only cover the samples generated for this pixel itself.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Pixel {
+    pub sum: Colour,
+    pub weight: f64,
+    pub samples: usize,
+    // Running mean and sum of squared differences of the sample luminance (Welford's method).
+    pub(crate) luminance_mean: f64,
+    pub(crate) luminance_m2: f64,
+}
+
+impl Pixel {
+    /// Record a sample generated for this pixel in its statistics.
+    pub fn add_sample(&mut self, colour: Colour) {
+        self.samples += 1;
+        let luminance = colour.luminance();
+        let delta = luminance - self.luminance_mean;
+        self.luminance_mean += delta / self.samples as f64;
+        self.luminance_m2 += delta * (luminance - self.luminance_mean);
+    }
+
+    /// Add a sample's contribution to this pixel's colour.
+    pub fn splat(&mut self, colour: Colour, weight: f64) {
+        self.sum += weight * colour;
+        self.weight += weight;
+    }
+
+    /// Combine with the same pixel accumulated elsewhere, e.g. in an overlapping tile.
+    pub fn merge(&mut self, other: &Pixel) {
+        self.sum += other.sum;
+        self.weight += other.weight;
+
+        // Chan et al.'s parallel form of Welford's update.
+        let samples = self.samples + other.samples;
+        if self.samples == 0 {
+            // Copied rather than scaled by n / n, so merging into an empty pixel is exact.
+            self.luminance_mean = other.luminance_mean;
+            self.luminance_m2 = other.luminance_m2;
+        } else if samples > 0 {
+            let delta = other.luminance_mean - self.luminance_mean;
+            let (n_a, n_b) = (self.samples as f64, other.samples as f64);
+            self.luminance_mean += delta * n_b / samples as f64;
+            self.luminance_m2 += other.luminance_m2 + delta * delta * n_a * n_b / samples as f64;
+        }
+        self.samples = samples;
+    }
+
+    /// Filter weighted mean of the samples taken so far, black if there are none.
+    pub fn colour(&self) -> Colour {
+        if self.weight == 0.0 {
+            return Colour::default();
+        }
+        self.sum / self.weight
+    }
+
+    /// Sample variance of the luminance.
+    pub fn variance(&self) -> f64 {
+        match self.samples {
+            0 | 1 => 0.0,
+            n => self.luminance_m2 / (n - 1) as f64,
+        }
+    }
+
+    /// Half width of the 95% confidence interval of the mean luminance, relative to that mean.
+    /// Very dark pixels are measured against a floor so that they can converge at all.
+    pub fn relative_error(&self) -> f64 {
+        if self.samples < 2 {
+            return f64::INFINITY;
+        }
+        let half_width = 1.96 * (self.variance() / self.samples as f64).sqrt();
+        half_width / self.luminance_mean.max(MIN_LUMINANCE)
+    }
+}
+
+/// Luminance below which a pixel's error is measured in absolute rather than relative terms.
+const MIN_LUMINANCE: f64 = 0.01;
+
+/// The framebuffer that samples are accumulated into while rendering.
+///
+/// A film either covers the whole image, or just a window of it (`bounds`) when used to render
+/// a single tile; pixels are always addressed in whole image coordinates.
+#[derive(Debug, Clone)]
+pub struct Film {
+    pub width: usize,
+    pub height: usize,
+    bounds: Bounds,
+    pixels: Vec<Pixel>,
+    aovs: Option<Vec<AovPixel>>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Self {
+        let bounds = Bounds {
+            x0: 0,
+            y0: 0,
+            x1: width,
+            y1: height,
+        };
+        Self {
+            width,
+            height,
+            bounds,
+            pixels: vec![Pixel::default(); bounds.area()],
+            aovs: None,
+        }
+    }
+
+    /// A film for rendering `tile` of a width x height image. It extends past the tile by the
+    /// filter radius so that samples near the tile's edge can splat onto its neighbours.
+    pub fn for_tile(width: usize, height: usize, tile: Bounds, filter: &dyn Filter) -> Self {
+        let bounds = Film::tile_bounds(width, height, tile, filter);
+        Self {
+            width,
+            height,
+            bounds,
+            pixels: vec![Pixel::default(); bounds.area()],
+            aovs: None,
+        }
+    }
+
+    /// The part of the image a `for_tile` film covers: `tile` and the margin around it.
+    pub fn tile_bounds(width: usize, height: usize, tile: Bounds, filter: &dyn Filter) -> Bounds {
+        let margin = (filter.radius() + 0.5).ceil() as usize;
+        Bounds {
+            x0: tile.x0.saturating_sub(margin),
+            y0: tile.y0.saturating_sub(margin),
+            x1: (tile.x1 + margin).min(width),
+            y1: (tile.y1 + margin).min(height),
+        }
+    }
+
+    /// Also accumulate AOVs (see `add_aov_sample`).
+    pub fn with_aovs(mut self) -> Self {
+        self.aovs = Some(vec![AovPixel::default(); self.bounds.area()]);
+        self
+    }
+
+    pub fn bounds(&self) -> Bounds {
+        self.bounds
+    }
+
+    /// Rebuild a film from its raw pixels, which must cover `bounds` in row order.
+    pub(crate) fn from_pixels(
+        width: usize,
+        height: usize,
+        bounds: Bounds,
+        pixels: Vec<Pixel>,
+        aovs: Option<Vec<AovPixel>>,
+    ) -> Self {
+        assert_eq!(pixels.len(), bounds.area());
+        assert!(aovs.as_ref().is_none_or(|a| a.len() == bounds.area()));
+        Self {
+            width,
+            height,
+            bounds,
+            pixels,
+            aovs,
+        }
+    }
+
+    pub(crate) fn pixels(&self) -> &[Pixel] {
+        &self.pixels
+    }
+
+    pub(crate) fn aov_pixels(&self) -> Option<&[AovPixel]> {
+        self.aovs.as_deref()
+    }
+
+    pub fn has_aovs(&self) -> bool {
+        self.aovs.is_some()
+    }
+
+    fn index(&self, i: usize, j: usize) -> usize {
+        (j - self.bounds.y0) * self.bounds.width() + (i - self.bounds.x0)
+    }
+
+    pub fn pixel(&self, i: usize, j: usize) -> &Pixel {
+        &self.pixels[self.index(i, j)]
+    }
+
+    /// The AOVs for pixel (i, j), if this film has them.
+    pub fn aov_pixel(&self, i: usize, j: usize) -> Option<&AovPixel> {
+        let index = self.index(i, j);
+        self.aovs.as_ref().map(|aovs| &aovs[index])
+    }
+
+    /// Record what a sample for pixel (i, j) first hit, if this film accumulates AOVs. `first`
+    /// says whether it was the pixel's first sample.
+    pub fn add_aov_sample(&mut self, i: usize, j: usize, sample: &AovSample, first: bool) {
+        let index = self.index(i, j);
+        if let Some(aovs) = &mut self.aovs {
+            aovs[index].add_sample(sample, first);
+        }
+    }
+
+    /// Add a sample generated for pixel (i, j), positioned at (x, y) in continuous image
+    /// coordinates, splatting it onto every pixel within the filter's radius.
+    pub fn add_sample(
+        &mut self,
+        i: usize,
+        j: usize,
+        (x, y): (f64, f64),
+        colour: Colour,
+        filter: &dyn Filter,
+    ) {
+        let index = self.index(i, j);
+        self.pixels[index].add_sample(colour);
+
+        // Pixel centres sit at half-integer coordinates.
+        let radius = filter.radius();
+        let x0 = (x - 0.5 - radius).ceil().max(self.bounds.x0 as f64) as usize;
+        let y0 = (y - 0.5 - radius).ceil().max(self.bounds.y0 as f64) as usize;
+        let x1 = ((x - 0.5 + radius).floor() + 1.0).clamp(0.0, self.bounds.x1 as f64) as usize;
+        let y1 = ((y - 0.5 + radius).floor() + 1.0).clamp(0.0, self.bounds.y1 as f64) as usize;
+        for py in y0..y1 {
+            for px in x0..x1 {
+                let weight = filter.evaluate(x - (px as f64 + 0.5), y - (py as f64 + 0.5));
+                if weight != 0.0 {
+                    let index = self.index(px, py);
+                    self.pixels[index].splat(colour, weight);
+                }
+            }
+        }
+    }
+
+    /// Mean of every pixel's `relative_error`; infinite until each pixel has enough samples.
+    pub fn mean_relative_error(&self) -> f64 {
+        let total: f64 = self.pixels.iter().map(Pixel::relative_error).sum();
+        total / self.pixels.len().max(1) as f64
+    }
+
+    /// Accumulate another film (typically a rendered tile) into this one.
+    pub fn merge(&mut self, other: &Film) {
+        let b = other.bounds;
+        for j in b.y0.max(self.bounds.y0)..b.y1.min(self.bounds.y1) {
+            for i in b.x0.max(self.bounds.x0)..b.x1.min(self.bounds.x1) {
+                let index = self.index(i, j);
+                self.pixels[index].merge(other.pixel(i, j));
+                if let (Some(aovs), Some(other)) = (&mut self.aovs, other.aov_pixel(i, j)) {
+                    aovs[index].merge(other);
+                }
+            }
+        }
+    }
+
+    /// Write the averaged image in PPM format.
+    pub fn write_ppm(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "P3\n{} {}\n255\n", self.width, self.height)?;
+        for pixel in &self.pixels {
+            writeln!(out, "{}", pixel.colour().write_colour())?;
+        }
+        Ok(())
+    }
+
+    /// Write one of the AOVs as a portable float map. Fails if the film doesn't have AOVs.
+    pub fn write_aov(&self, aov: Aov, out: &mut dyn Write) -> io::Result<()> {
+        let Some(aovs) = &self.aovs else {
+            return Err(io::Error::other("film has no AOVs"));
+        };
+        let values: Vec<_> = aovs.iter().map(|pixel| pixel.value(aov)).collect();
+        write_pfm(
+            out,
+            self.bounds.width(),
+            self.bounds.height(),
+            aov.channels(),
+            &values,
+        )
+    }
+
+    /// Write an image of how many samples each pixel received, from blue (fewest) to red (most).
+    pub fn write_sample_heatmap(&self, out: &mut dyn Write) -> io::Result<()> {
+        let max_samples = self
+            .pixels
+            .iter()
+            .map(|p| p.samples)
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        writeln!(out, "P3\n{} {}\n255\n", self.width, self.height)?;
+        for pixel in &self.pixels {
+            let t = pixel.samples as f64 / max_samples as f64;
+            writeln!(out, "{}", heatmap_colour(t).write_colour())?;
+        }
+        Ok(())
+    }
+}
+
+/// Maps t in [0, 1] onto a blue -> green -> red ramp.
+pub fn heatmap_colour(t: f64) -> Colour {
+    let t = t.clamp(0.0, 1.0);
+    if t < 0.5 {
+        let a = 2.0 * t;
+        Colour::new(0.0, a, 1.0 - a)
+    } else {
+        let a = 2.0 * (t - 0.5);
+        Colour::new(a, 1.0 - a, 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::{BoxFilter, TentFilter};
+    use rstest::rstest;
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_pixel_averages_samples() {
+        let mut pixel = Pixel::default();
+        assert_eq!(pixel.colour(), Colour::new(0.0, 0.0, 0.0));
+        pixel.splat(Colour::new(1.0, 0.0, 0.5), 1.0);
+        pixel.splat(Colour::new(0.0, 1.0, 0.5), 1.0);
+        assert_eq!(pixel.colour(), Colour::new(0.5, 0.5, 0.5));
+        pixel.splat(Colour::new(1.0, 1.0, 1.0), 2.0);
+        assert_eq!(pixel.colour(), Colour::new(0.75, 0.75, 0.75));
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_pixel_merge_matches_single_pixel() {
+        let values = [0.1, 0.9, 0.4, 0.3, 0.8];
+        let mut whole = Pixel::default();
+        let mut left = Pixel::default();
+        let mut right = Pixel::default();
+        for (k, &v) in values.iter().enumerate() {
+            let colour = Colour::new(v, v, v);
+            whole.add_sample(colour);
+            whole.splat(colour, 1.0);
+            let part = if k < 2 { &mut left } else { &mut right };
+            part.add_sample(colour);
+            part.splat(colour, 1.0);
+        }
+        left.merge(&right);
+        assert_eq!(left.samples, whole.samples);
+        assert!((left.variance() - whole.variance()).abs() < 1e-12);
+        assert!((left.colour().x - whole.colour().x).abs() < 1e-12);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_box_filter_only_touches_own_pixel() {
+        let filter = BoxFilter::default();
+        let mut film = Film::new(3, 3);
+        film.add_sample(1, 1, (1.2, 1.7), Colour::new(1.0, 1.0, 1.0), &filter);
+        for j in 0..3 {
+            for i in 0..3 {
+                let want = if (i, j) == (1, 1) { 1.0 } else { 0.0 };
+                assert_eq!(film.pixel(i, j).weight, want, "pixel ({i}, {j})");
+            }
+        }
+        assert_eq!(film.pixel(1, 1).samples, 1);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_tiles_merge_across_boundaries() {
+        // Splatting into two tiles and merging should match splatting into one film.
+        let filter = TentFilter { radius: 1.5 };
+        let samples = [
+            (1, 0, (1.9, 0.5), Colour::new(1.0, 0.0, 0.0)),
+            (2, 1, (2.1, 1.5), Colour::new(0.0, 1.0, 0.0)),
+            (3, 1, (3.5, 1.2), Colour::new(0.0, 0.0, 1.0)),
+        ];
+        let mut whole = Film::new(4, 2);
+        let mut merged = Film::new(4, 2);
+        let tiles = Bounds::tiles(4, 2, 2);
+        for tile in &tiles {
+            let mut tile_film = Film::for_tile(4, 2, *tile, &filter);
+            for &(i, j, position, colour) in &samples {
+                if tile.contains(i, j) {
+                    tile_film.add_sample(i, j, position, colour, &filter);
+                }
+            }
+            merged.merge(&tile_film);
+        }
+        for &(i, j, position, colour) in &samples {
+            whole.add_sample(i, j, position, colour, &filter);
+        }
+        for j in 0..2 {
+            for i in 0..4 {
+                assert_eq!(merged.pixel(i, j).colour(), whole.pixel(i, j).colour());
+                assert_eq!(merged.pixel(i, j).samples, whole.pixel(i, j).samples);
+            }
+        }
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_bounds_tiles_cover_image() {
+        let tiles = Bounds::tiles(5, 3, 2);
+        assert_eq!(tiles.len(), 6);
+        assert_eq!(tiles.iter().map(|t| t.area()).sum::<usize>(), 15);
+        assert_eq!(
+            tiles[5],
+            Bounds {
+                x0: 4,
+                y0: 2,
+                x1: 5,
+                y1: 3
+            }
+        );
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_pixel_variance() {
+        let mut pixel = Pixel::default();
+        for v in [1.0, 2.0, 3.0, 4.0] {
+            pixel.add_sample(Colour::new(v, v, v));
+        }
+        // Sample variance of 1, 2, 3, 4
+        assert!((pixel.variance() - 5.0 / 3.0).abs() < 1e-12);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case(&[0.5, 0.5, 0.5, 0.5], 0.0)]
+    #[case(&[0.0], f64::INFINITY)]
+    fn test_relative_error(#[case] samples: &[f64], #[case] want: f64) {
+        let mut pixel = Pixel::default();
+        for &v in samples {
+            pixel.add_sample(Colour::new(v, v, v));
+        }
+        assert_eq!(pixel.relative_error(), want);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_noisy_pixel_has_larger_error() {
+        let mut flat = Pixel::default();
+        let mut noisy = Pixel::default();
+        for k in 0..16 {
+            flat.add_sample(Colour::new(0.5, 0.5, 0.5));
+            let v = if k % 2 == 0 { 0.0 } else { 1.0 };
+            noisy.add_sample(Colour::new(v, v, v));
+        }
+        assert!(noisy.relative_error() > flat.relative_error());
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_write_ppm() {
+        let mut film = Film::new(2, 1);
+        film.add_sample(
+            0,
+            0,
+            (0.5, 0.5),
+            Colour::new(1.0, 1.0, 1.0),
+            &BoxFilter::default(),
+        );
+        let mut out = Vec::new();
+        film.write_ppm(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "P3\n2 1\n255\n\n255 255 255\n0 0 0\n"
+        );
+    }
+}
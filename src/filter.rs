@@ -0,0 +1,218 @@
+use std::f64::consts::PI;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// A pixel reconstruction filter. Each sample is splatted onto every pixel whose centre lies
+/// within `radius` of it, weighted by `evaluate` at the offset from that pixel centre.
+pub trait Filter: Send + Sync + Debug {
+    /// Extent of the filter's support in each direction, in pixels.
+    fn radius(&self) -> f64;
+
+    /// Weight for a sample offset (x, y) pixels from a pixel centre. May be negative.
+    fn evaluate(&self, x: f64, y: f64) -> f64;
+}
+
+/// Equal weight over a square; a radius of 0.5 gives a plain per-pixel average.
+#[derive(Debug, Clone, Copy)]
+pub struct BoxFilter {
+    pub radius: f64,
+}
+
+impl Default for BoxFilter {
+    fn default() -> Self {
+        Self { radius: 0.5 }
+    }
+}
+
+impl Filter for BoxFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        if x.abs() <= self.radius && y.abs() <= self.radius {
+            1.0
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Linear falloff from the centre (a triangle filter in each axis).
+#[derive(Debug, Clone, Copy)]
+pub struct TentFilter {
+    pub radius: f64,
+}
+
+impl Filter for TentFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        (self.radius - x.abs()).max(0.0) * (self.radius - y.abs()).max(0.0)
+    }
+}
+
+/// A Gaussian, shifted down so that it reaches zero at the radius.
+#[derive(Debug, Clone, Copy)]
+pub struct GaussianFilter {
+    pub radius: f64,
+    pub sigma: f64,
+}
+
+impl GaussianFilter {
+    fn gaussian(&self, x: f64) -> f64 {
+        let g = |v: f64| (-v * v / (2.0 * self.sigma * self.sigma)).exp();
+        (g(x) - g(self.radius)).max(0.0)
+    }
+}
+
+impl Filter for GaussianFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.gaussian(x) * self.gaussian(y)
+    }
+}
+
+/// The Mitchell-Netravali cubic. B = C = 1/3 is the usual compromise between ringing and blur.
+#[derive(Debug, Clone, Copy)]
+pub struct MitchellFilter {
+    pub radius: f64,
+    pub b: f64,
+    pub c: f64,
+}
+
+impl MitchellFilter {
+    fn mitchell(&self, x: f64) -> f64 {
+        // The cubic is defined over [-2, 2], so scale the radius onto that.
+        let x = (2.0 * x / self.radius).abs();
+        let (b, c) = (self.b, self.c);
+        if x <= 1.0 {
+            ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                + (6.0 - 2.0 * b))
+                / 6.0
+        } else if x <= 2.0 {
+            ((-b - 6.0 * c) * x * x * x
+                + (6.0 * b + 30.0 * c) * x * x
+                + (-12.0 * b - 48.0 * c) * x
+                + (8.0 * b + 24.0 * c))
+                / 6.0
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Filter for MitchellFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.mitchell(x) * self.mitchell(y)
+    }
+}
+
+/// A sinc windowed by a wider sinc, with `tau` lobes across the radius.
+#[derive(Debug, Clone, Copy)]
+pub struct LanczosFilter {
+    pub radius: f64,
+    pub tau: f64,
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 {
+        return 1.0;
+    }
+    (PI * x).sin() / (PI * x)
+}
+
+impl LanczosFilter {
+    fn windowed_sinc(&self, x: f64) -> f64 {
+        if x.abs() > self.radius {
+            return 0.0;
+        }
+        sinc(x) * sinc(x / self.tau)
+    }
+}
+
+impl Filter for LanczosFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.windowed_sinc(x) * self.windowed_sinc(y)
+    }
+}
+
+/// Builds a filter with sensible default parameters from its name.
+pub fn from_name(name: &str) -> Result<Arc<dyn Filter>, String> {
+    match name {
+        "box" => Ok(Arc::new(BoxFilter::default())),
+        "tent" => Ok(Arc::new(TentFilter { radius: 1.0 })),
+        "gaussian" => Ok(Arc::new(GaussianFilter {
+            radius: 1.5,
+            sigma: 0.5,
+        })),
+        "mitchell" => Ok(Arc::new(MitchellFilter {
+            radius: 2.0,
+            b: 1.0 / 3.0,
+            c: 1.0 / 3.0,
+        })),
+        "lanczos" => Ok(Arc::new(LanczosFilter {
+            radius: 2.0,
+            tau: 2.0,
+        })),
+        _ => Err(format!("unknown filter: {name}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case("box")]
+    #[case("tent")]
+    #[case("gaussian")]
+    #[case("mitchell")]
+    #[case("lanczos")]
+    fn test_filter_peaks_at_centre(#[case] name: &str) {
+        let filter = from_name(name).unwrap();
+        let centre = filter.evaluate(0.0, 0.0);
+        assert!(centre > 0.0);
+        for offset in [0.1, 0.4, 0.9, 1.5] {
+            assert!(filter.evaluate(offset, 0.0) <= centre);
+            assert_eq!(filter.evaluate(offset, 0.0), filter.evaluate(-offset, 0.0));
+        }
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case("box")]
+    #[case("tent")]
+    #[case("gaussian")]
+    #[case("mitchell")]
+    #[case("lanczos")]
+    fn test_filter_is_zero_outside_radius(#[case] name: &str) {
+        let filter = from_name(name).unwrap();
+        let outside = filter.radius() + 0.01;
+        assert_eq!(filter.evaluate(outside, 0.0), 0.0);
+        assert_eq!(filter.evaluate(0.0, -outside), 0.0);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_mitchell_has_negative_lobes() {
+        let filter = from_name("mitchell").unwrap();
+        assert!(filter.evaluate(1.5, 0.0) < 0.0);
+    }
+}
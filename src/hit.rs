@@ -1,14 +1,36 @@
 use std::ops::Range;
+use std::sync::Arc;
 
+use crate::environment::{Background, Gradient};
+use crate::light::Light;
+use crate::material::Material;
 use crate::ray::Ray;
+use crate::sampler::hash;
+use crate::texture::Texture;
 use crate::{Point3, Vec3, dot};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct HitRecord {
     pub p: Point3,
+    /// The shading normal, facing the ray. Materials scatter around this, and may tilt it to
+    /// add detail the geometry doesn't have.
     pub normal: Vec3,
+    /// The true surface normal, facing the ray, which tells which side of the surface is which.
+    pub geometric_normal: Vec3,
+    /// How the hit point moves with the surface coordinates: tangents along u and v.
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
     pub t: f64,
+    /// Surface coordinates of the hit, each in [0, 1].
+    pub u: f64,
+    pub v: f64,
     pub front_face: bool,
+    pub material: Arc<dyn Material>,
+    /// Index of the object hit within the scene's `HittableList`.
+    pub object_id: u32,
+    /// Index of the object's material among the scene's distinct materials, counting from 1;
+    /// 0 for objects without a single material.
+    pub material_id: u32,
 }
 
 impl HitRecord {
@@ -21,15 +43,80 @@ impl HitRecord {
             true => *outward_normal,
             false => -*outward_normal,
         };
+        self.geometric_normal = self.normal;
     }
 }
 
-pub trait Hittable {
+/// Cuts holes in a surface where a texture's first channel, its alpha, is low. Leaves and
+/// fences are much cheaper as a few masked shapes than modelled outline by outline.
+#[derive(Debug, Clone)]
+pub struct AlphaMask {
+    pub texture: Arc<dyn Texture>,
+    /// Hits where alpha is below this are skipped. With `None`, alpha is the chance of a hit
+    /// instead, so partly transparent surfaces let a share of rays through.
+    pub threshold: Option<f64>,
+}
+
+impl AlphaMask {
+    /// Hits only where alpha reaches `threshold`.
+    pub fn cutout(texture: Arc<dyn Texture>, threshold: f64) -> Self {
+        Self {
+            texture,
+            threshold: Some(threshold),
+        }
+    }
+
+    /// Hits with probability alpha.
+    pub fn stochastic(texture: Arc<dyn Texture>) -> Self {
+        Self {
+            texture,
+            threshold: None,
+        }
+    }
+
+    /// Whether `r` hits the surface at `t`, where it has surface coordinates (u, v). Stochastic
+    /// masks decide by hashing the ray, so tracing the same ray again gives the same answer.
+    pub fn is_opaque(&self, r: &Ray, t: f64, u: f64, v: f64) -> bool {
+        let p = r.at(t);
+        let alpha = self.texture.value(u, v, &p).x;
+        match self.threshold {
+            Some(threshold) => alpha >= threshold,
+            None if alpha >= 1.0 => true,
+            None if alpha <= 0.0 => false,
+            None => {
+                let key = hash(&[
+                    r.origin.x.to_bits(),
+                    r.origin.y.to_bits(),
+                    r.origin.z.to_bits(),
+                    r.direction.x.to_bits(),
+                    r.direction.y.to_bits(),
+                    r.direction.z.to_bits(),
+                    t.to_bits(),
+                ]);
+                ((key >> 11) as f64 / (1u64 << 53) as f64) < alpha
+            }
+        }
+    }
+}
+
+pub trait Hittable: Send + Sync {
     fn hit(&self, r: &Ray, interval: &Range<f64>) -> Option<HitRecord>;
+
+    /// The object's material, if it has a single one, so that lists can give it an ID.
+    fn material(&self) -> Option<&Arc<dyn Material>> {
+        None
+    }
 }
 
 pub struct HittableList {
     pub objects: Vec<Box<dyn Hittable>>,
+    /// What rays that miss every object see.
+    pub background: Arc<dyn Background>,
+    /// Lights that aren't objects, which no ray can hit.
+    pub lights: Vec<Box<dyn Light>>,
+    // Distinct materials in the order they were first added, and each object's index into them.
+    materials: Vec<Arc<dyn Material>>,
+    material_ids: Vec<u32>,
 }
 
 impl Default for HittableList {
@@ -42,14 +129,32 @@ impl HittableList {
     pub fn new() -> Self {
         Self {
             objects: Vec::new(),
+            background: Arc::new(Gradient),
+            lights: Vec::new(),
+            materials: Vec::new(),
+            material_ids: Vec::new(),
         }
     }
 
     pub fn clear(&mut self) {
         self.objects.clear();
+        self.lights.clear();
+        self.materials.clear();
+        self.material_ids.clear();
     }
 
     pub fn add(&mut self, object: Box<dyn Hittable>) {
+        let material_id = match object.material() {
+            Some(material) => match self.materials.iter().position(|m| Arc::ptr_eq(m, material)) {
+                Some(index) => index + 1,
+                None => {
+                    self.materials.push(material.clone());
+                    self.materials.len()
+                }
+            },
+            None => 0,
+        };
+        self.material_ids.push(material_id as u32);
         self.objects.push(object);
     }
 }
@@ -59,9 +164,11 @@ impl Hittable for HittableList {
         let mut local_interval = interval.clone();
         let mut hit_record = None;
 
-        for object in &self.objects {
-            if let Some(rec) = object.hit(r, &local_interval) {
+        for (index, object) in self.objects.iter().enumerate() {
+            if let Some(mut rec) = object.hit(r, &local_interval) {
                 local_interval.end = rec.t;
+                rec.object_id = index as u32;
+                rec.material_id = self.material_ids.get(index).copied().unwrap_or(0);
                 hit_record = Some(rec);
             }
         }
@@ -69,3 +176,72 @@ impl Hittable for HittableList {
         hit_record
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Diffuse;
+    use crate::sphere::Sphere;
+    use crate::texture::SolidColour;
+    use crate::{Colour, Point3, Vec3};
+    use rstest::rstest;
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case(0.0)]
+    #[case(0.3)]
+    #[case(0.8)]
+    #[case(1.0)]
+    fn test_stochastic_alpha_passes_its_share(#[case] alpha: f64) {
+        let mask = AlphaMask::stochastic(Arc::new(SolidColour::new(Colour::new(alpha, 0.0, 0.0))));
+        let n = 10000;
+        let hits = (0..n)
+            .filter(|&i| {
+                let r = Ray::new(
+                    Point3::new(i as f64 * 1e-3, 0.0, 0.0),
+                    Vec3::new(0.0, 0.0, -1.0),
+                );
+                mask.is_opaque(&r, 1.0, 0.5, 0.5)
+            })
+            .count();
+        assert!((hits as f64 / n as f64 - alpha).abs() < 0.02, "{hits}");
+        // The same ray always gets the same answer.
+        let r = Ray::new(Point3::new(0.1, 0.2, 0.3), Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(
+            mask.is_opaque(&r, 2.0, 0.5, 0.5),
+            mask.is_opaque(&r, 2.0, 0.5, 0.5)
+        );
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_list_assigns_object_and_material_ids() {
+        let red: Arc<dyn Material> = Arc::new(Diffuse {
+            albedo: Colour::new(0.8, 0.1, 0.1),
+        });
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5)));
+        world.add(Box::new(
+            Sphere::new(Point3::new(0.0, 0.0, -3.0), 0.5).with_material(red.clone()),
+        ));
+        world.add(Box::new(
+            Sphere::new(Point3::new(0.0, 0.0, -5.0), 0.5).with_material(red),
+        ));
+        world.add(Box::new(Sphere::new(Point3::new(0.0, 0.0, -7.0), 0.5)));
+        // A list has no single material.
+        let mut group = HittableList::new();
+        group.add(Box::new(Sphere::new(Point3::new(0.0, 0.0, -9.0), 0.5)));
+        world.add(Box::new(group));
+
+        let hit = |z: f64| {
+            let r = Ray::new(Point3::new(0.0, 0.0, z), Vec3::new(0.0, 0.0, -1.0));
+            let rec = world.hit(&r, &(0.001..f64::INFINITY)).unwrap();
+            (rec.object_id, rec.material_id)
+        };
+        assert_eq!(hit(0.0), (0, 1));
+        assert_eq!(hit(-2.0), (1, 2));
+        assert_eq!(hit(-4.0), (2, 2));
+        assert_eq!(hit(-6.0), (3, 1));
+        assert_eq!(hit(-8.0), (4, 0));
+    }
+}
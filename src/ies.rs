@@ -0,0 +1,315 @@
+//! Photometric profiles of real light fittings, from the IES LM-63 files manufacturers publish.
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::{Vec3, dot, unit_vector};
+
+/// The most values any one table in a file may hold. Real files have a few thousand at most.
+const MAX_IES_VALUES: usize = 1 << 20;
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// A count read from a file, which must be a whole number from 0 to `MAX_IES_VALUES`.
+fn count(value: f64) -> io::Result<usize> {
+    if value.fract() != 0.0 || !(0.0..=MAX_IES_VALUES as f64).contains(&value) {
+        return Err(invalid(&format!("bad count in IES file: {value}")));
+    }
+    Ok(value as usize)
+}
+
+/// How brightly a fitting shines in each direction, measured in candela over a grid of angles.
+///
+/// Only type C photometry is supported, which covers nearly all architectural fittings: the
+/// vertical angle runs from 0 straight down (the nadir) to 180 straight up, and the horizontal
+/// angle round the vertical, counterclockwise seen from above, from 0 along the fitting.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IesProfile {
+    /// Vertical angles in degrees, ascending.
+    pub vertical_angles: Vec<f64>,
+    /// Horizontal angles in degrees, ascending. Ending at 0, 90 or 180 means the fitting is
+    /// symmetric all round, in each quadrant or about the 0-180 plane.
+    pub horizontal_angles: Vec<f64>,
+    /// For each horizontal angle in turn, the candela at each vertical angle, with the file's
+    /// multipliers applied.
+    pub candela: Vec<f64>,
+    /// The brightest of `candela`.
+    pub peak: f64,
+}
+
+impl IesProfile {
+    /// Read an LM-63 file, any version from 1986 to 2019. Tilt data is skipped: it only
+    /// matters for lamps that change output when tilted.
+    pub fn read(input: &mut dyn Read) -> io::Result<IesProfile> {
+        let mut bytes = Vec::new();
+        input.read_to_end(&mut bytes)?;
+        // Keywords may be in any 8-bit encoding, but only the numbers matter.
+        let text = String::from_utf8_lossy(&bytes);
+        let mut lines = text.lines();
+        let tilt = lines
+            .by_ref()
+            .find_map(|line| line.trim().strip_prefix("TILT="))
+            .ok_or_else(|| invalid("IES file without TILT line"))?;
+        let rest: Vec<&str> = lines.collect();
+        let mut numbers = rest
+            .iter()
+            .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+            .filter(|word| !word.is_empty())
+            .map(|word| {
+                word.parse::<f64>()
+                    .map_err(|_| invalid(&format!("bad number in IES file: {word}")))
+            });
+        let mut next = || {
+            numbers
+                .next()
+                .unwrap_or_else(|| Err(invalid("truncated IES file")))
+        };
+        if tilt.trim() == "INCLUDE" {
+            // Lamp to fitting geometry, then pairs of angles and multipliers.
+            next()?;
+            let pairs = count(next()?)?;
+            for _ in 0..2 * pairs {
+                next()?;
+            }
+        }
+
+        let _lamps = next()?;
+        let _lumens_per_lamp = next()?;
+        let multiplier = next()?;
+        let vertical_count = count(next()?)?;
+        let horizontal_count = count(next()?)?;
+        let photometric_type = next()?;
+        for _ in 0..4 {
+            // Units, then the fitting's width, length and height.
+            next()?;
+        }
+        let ballast_factor = next()?;
+        let ballast_lamp_factor = next()?;
+        let _input_watts = next()?;
+        if photometric_type != 1.0 {
+            return Err(invalid("only type C IES photometry is supported"));
+        }
+        if vertical_count == 0 || horizontal_count == 0 {
+            return Err(invalid("IES file without angles"));
+        }
+        let candela_count = vertical_count
+            .checked_mul(horizontal_count)
+            .filter(|&count| count <= MAX_IES_VALUES)
+            .ok_or_else(|| invalid("IES file has too many candela values"))?;
+
+        let mut angles = |count: usize| -> io::Result<Vec<f64>> {
+            let angles = (0..count).map(|_| next()).collect::<io::Result<Vec<_>>>()?;
+            if angles.windows(2).any(|pair| pair[1] <= pair[0]) {
+                return Err(invalid("IES angles out of order"));
+            }
+            Ok(angles)
+        };
+        let vertical_angles = angles(vertical_count)?;
+        let horizontal_angles = angles(horizontal_count)?;
+        let scale = multiplier * ballast_factor * ballast_lamp_factor;
+        let candela = (0..candela_count)
+            .map(|_| next().map(|value| scale * value.max(0.0)))
+            .collect::<io::Result<Vec<_>>>()?;
+        let peak = candela.iter().copied().fold(0.0, f64::max);
+        Ok(IesProfile {
+            vertical_angles,
+            horizontal_angles,
+            candela,
+            peak,
+        })
+    }
+
+    pub fn load(path: &Path) -> io::Result<IesProfile> {
+        IesProfile::read(&mut fs::File::open(path)?)
+    }
+
+    /// The candela at the vertical and horizontal angles, in degrees, interpolated between the
+    /// measurements. Nothing shines outside the vertical angles measured.
+    pub fn candela_at(&self, vertical: f64, horizontal: f64) -> f64 {
+        let Some((row, t_vertical)) = segment(&self.vertical_angles, vertical) else {
+            return 0.0;
+        };
+        let horizontal = self.fold(horizontal.rem_euclid(360.0));
+        let (column, t_horizontal) = segment(&self.horizontal_angles, horizontal)
+            .unwrap_or((self.horizontal_angles.len() - 1, 0.0));
+        let rows = self.vertical_angles.len();
+        let at = |column: usize, row: usize| {
+            let column = column.min(self.horizontal_angles.len() - 1);
+            self.candela[column * rows + row.min(rows - 1)]
+        };
+        let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+        lerp(
+            lerp(at(column, row), at(column, row + 1), t_vertical),
+            lerp(at(column + 1, row), at(column + 1, row + 1), t_vertical),
+            t_horizontal,
+        )
+    }
+
+    /// Map a horizontal angle in [0, 360) onto the range measured, by the fitting's symmetry.
+    fn fold(&self, horizontal: f64) -> f64 {
+        let last = self.horizontal_angles[self.horizontal_angles.len() - 1];
+        let half = if horizontal > 180.0 {
+            360.0 - horizontal
+        } else {
+            horizontal
+        };
+        if last == 90.0 {
+            if half > 90.0 { 180.0 - half } else { half }
+        } else if last == 180.0 {
+            half
+        } else {
+            horizontal
+        }
+    }
+
+    /// The intensity along the unit vector `w`, out of the peak, for a fitting pointing its
+    /// nadir along the unit vector `nadir`. The 0 degree horizontal plane lies towards +X, or
+    /// +Z for fittings pointing along X.
+    pub fn relative_intensity(&self, nadir: &Vec3, w: &Vec3) -> f64 {
+        if self.peak <= 0.0 {
+            return 0.0;
+        }
+        let reference = if nadir.x.abs() > 0.999 {
+            Vec3::new(0.0, 0.0, 1.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let along = unit_vector(&(reference - dot(&reference, nadir) * *nadir));
+        let across = along.cross(*nadir);
+        let vertical = dot(w, nadir).clamp(-1.0, 1.0).acos().to_degrees();
+        let horizontal = dot(w, &across).atan2(dot(w, &along)).to_degrees();
+        self.candela_at(vertical, horizontal) / self.peak
+    }
+}
+
+/// The index of the interval of the ascending `angles` containing `angle`, and how far along
+/// it `angle` is, or `None` if it's outside them. A single angle is an interval on its own.
+fn segment(angles: &[f64], angle: f64) -> Option<(usize, f64)> {
+    let (first, last) = (angles[0], angles[angles.len() - 1]);
+    if angle < first || angle > last {
+        return None;
+    }
+    if angles.len() == 1 {
+        return Some((0, 0.0));
+    }
+    let i = (angles.partition_point(|&a| a <= angle).max(1) - 1).min(angles.len() - 2);
+    Some((i, (angle - angles[i]) / (angles[i + 1] - angles[i])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    /// A downlight, symmetric in each quadrant, brighter along its length than across it, in
+    /// the 2002 format with tilt data.
+    const DOWNLIGHT: &str = "IESNA:LM-63-2002
+[TEST] made up
+[MANUFAC] nobody
+TILT=INCLUDE
+1
+3
+0 45 90
+1 0.9 0.8
+1 1000 2 3 2 1 2 0.1 0.2 0.05
+0.5 1.0 20
+0 45 90
+0 90
+100 50 0
+80, 40, 0
+";
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_read() {
+        let profile = IesProfile::read(&mut DOWNLIGHT.as_bytes()).unwrap();
+        assert_eq!(profile.vertical_angles, vec![0.0, 45.0, 90.0]);
+        assert_eq!(profile.horizontal_angles, vec![0.0, 90.0]);
+        // Times the multiplier and the ballast factor.
+        assert_eq!(profile.candela, vec![100.0, 50.0, 0.0, 80.0, 40.0, 0.0]);
+        assert_eq!(profile.peak, 100.0);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case(0.0, 0.0, 100.0)]
+    #[case(22.5, 0.0, 75.0)]
+    #[case(45.0, 45.0, 45.0)]
+    #[case(45.0, 90.0, 40.0)]
+    // Folded back into the first quadrant.
+    #[case(45.0, 180.0, 50.0)]
+    #[case(45.0, 270.0, 40.0)]
+    #[case(45.0, -45.0, 45.0)]
+    #[case(120.0, 0.0, 0.0)]
+    fn test_candela_at(#[case] vertical: f64, #[case] horizontal: f64, #[case] want: f64) {
+        let profile = IesProfile::read(&mut DOWNLIGHT.as_bytes()).unwrap();
+        let candela = profile.candela_at(vertical, horizontal);
+        assert!((candela - want).abs() < 1e-9, "{candela}");
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_relative_intensity_is_aimed() {
+        let profile = IesProfile::read(&mut DOWNLIGHT.as_bytes()).unwrap();
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        assert_eq!(profile.relative_intensity(&down, &down), 1.0);
+        assert_eq!(profile.relative_intensity(&down, &-down), 0.0);
+        // 45 degrees down, along the fitting and then across it.
+        let along = unit_vector(&Vec3::new(1.0, -1.0, 0.0));
+        let across = unit_vector(&Vec3::new(0.0, -1.0, -1.0));
+        assert!((profile.relative_intensity(&down, &along) - 0.5).abs() < 1e-9);
+        assert!((profile.relative_intensity(&down, &across) - 0.4).abs() < 1e-9);
+        // Aimed sideways, straight ahead is its nadir.
+        let side = Vec3::new(1.0, 0.0, 0.0);
+        assert_eq!(profile.relative_intensity(&side, &side), 1.0);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case(
+        "IESNA91\n1 1000 1 1 1 1 2 0 0 0\n1 1 10\n0\n0\n5\n",
+        "IES file without TILT line"
+    )]
+    #[case(
+        "TILT=NONE\n1 1000 1 1 1 2 2 0 0 0\n1 1 10\n0\n0\n5\n",
+        "only type C IES photometry is supported"
+    )]
+    #[case(
+        "TILT=NONE\n1 1000 1 2 1 1 2 0 0 0\n1 1 10\n0 90\n0\n5\n",
+        "truncated IES file"
+    )]
+    #[case(
+        "TILT=NONE\n1 1000 1 2 1 1 2 0 0 0\n1 1 10\n90 0\n0\n5 5\n",
+        "IES angles out of order"
+    )]
+    #[case(
+        "TILT=NONE\n1 1000 1 1 1 1 2 0 0 0\n1 1 ten\n",
+        "bad number in IES file: ten"
+    )]
+    #[case(
+        "TILT=INCLUDE\n1\n1e30\n",
+        "bad count in IES file: 1000000000000000000000000000000"
+    )]
+    #[case("TILT=INCLUDE\n1\n-1\n", "bad count in IES file: -1")]
+    #[case("TILT=INCLUDE\n1\n1.5\n", "bad count in IES file: 1.5")]
+    #[case("TILT=INCLUDE\n1\nNaN\n", "bad count in IES file: NaN")]
+    #[case(
+        "TILT=NONE\n1 1000 1 inf 1 1 2 0 0 0\n1 1 10\n",
+        "bad count in IES file: inf"
+    )]
+    #[case(
+        "TILT=NONE\n1 1000 1 1e7 1 1 2 0 0 0\n1 1 10\n",
+        "bad count in IES file: 10000000"
+    )]
+    #[case(
+        "TILT=NONE\n1 1000 1 4096 4096 1 2 0 0 0\n1 1 10\n",
+        "IES file has too many candela values"
+    )]
+    fn test_read_errors(#[case] text: &str, #[case] want: &str) {
+        let err = IesProfile::read(&mut text.as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), want);
+    }
+}
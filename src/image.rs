@@ -0,0 +1,468 @@
+//! Reading images from files, for textures and environment maps.
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+use crate::Colour;
+
+/// A grid of colours, row by row from the top left.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Colour>,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+impl Image {
+    /// Read a PPM image, in either its text (P3) or binary (P6) form. Values are scaled to
+    /// [0, 1] as stored, without undoing any gamma.
+    pub fn read_ppm(input: &mut dyn Read) -> io::Result<Image> {
+        let mut bytes = Vec::new();
+        input.read_to_end(&mut bytes)?;
+        // The header is whitespace separated, with comments from # to the end of the line.
+        let mut pos = 0;
+        let mut token = || -> io::Result<&[u8]> {
+            loop {
+                match bytes.get(pos) {
+                    Some(b'#') => {
+                        while bytes.get(pos).is_some_and(|&b| b != b'\n') {
+                            pos += 1;
+                        }
+                    }
+                    Some(b) if b.is_ascii_whitespace() => pos += 1,
+                    Some(_) => break,
+                    None => return Err(invalid("truncated PPM image")),
+                }
+            }
+            let start = pos;
+            while bytes.get(pos).is_some_and(|b| !b.is_ascii_whitespace()) {
+                pos += 1;
+            }
+            Ok(&bytes[start..pos])
+        };
+        let number = |token: &[u8]| -> io::Result<usize> {
+            std::str::from_utf8(token)
+                .ok()
+                .and_then(|text| text.parse().ok())
+                .ok_or_else(|| invalid("bad number in PPM image"))
+        };
+        let binary = match token()? {
+            b"P3" => false,
+            b"P6" => true,
+            _ => return Err(invalid("not a PPM image")),
+        };
+        let width = number(token()?)?;
+        let height = number(token()?)?;
+        let max = number(token()?)?;
+        if max == 0 || max > 65535 {
+            return Err(invalid("bad maximum value in PPM image"));
+        }
+        let count = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(3))
+            .ok_or_else(|| invalid("PPM image too large"))?;
+        let values: Vec<usize> = if binary {
+            // A single whitespace byte separates the header from the data.
+            let data = bytes.get(pos + 1..).unwrap_or_default();
+            let size = if max > 255 { 2 } else { 1 };
+            if data.len() / size < count {
+                return Err(invalid("truncated PPM image"));
+            }
+            data.chunks(size)
+                .take(count)
+                .map(|chunk| chunk.iter().fold(0, |value, &b| value << 8 | b as usize))
+                .collect()
+        } else {
+            (0..count)
+                .map(|_| number(token()?))
+                .collect::<io::Result<_>>()?
+        };
+        let scale = 1.0 / max as f64;
+        let pixels = values
+            .chunks(3)
+            .map(|rgb| {
+                Colour::new(
+                    rgb[0] as f64 * scale,
+                    rgb[1] as f64 * scale,
+                    rgb[2] as f64 * scale,
+                )
+            })
+            .collect();
+        Ok(Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Read a Radiance RGBE (.hdr) image, either flat or run-length encoded. Only the usual
+    /// top-to-bottom, left-to-right orientation is supported.
+    pub fn read_hdr(input: &mut dyn Read) -> io::Result<Image> {
+        let mut bytes = Vec::new();
+        input.read_to_end(&mut bytes)?;
+        // Header lines, up to the resolution line.
+        let mut pos = 0;
+        let mut line = || -> io::Result<&[u8]> {
+            let start = pos;
+            let length = bytes[start..]
+                .iter()
+                .position(|&b| b == b'\n')
+                .ok_or_else(|| invalid("truncated HDR image"))?;
+            pos = start + length + 1;
+            Ok(&bytes[start..start + length])
+        };
+        if !line()?.starts_with(b"#?") {
+            return Err(invalid("not an HDR image"));
+        }
+        // Variables, up to a blank line.
+        loop {
+            let variable = line()?;
+            if variable.is_empty() {
+                break;
+            }
+            if variable.starts_with(b"FORMAT=") && variable != b"FORMAT=32-bit_rle_rgbe" {
+                return Err(invalid("unsupported HDR pixel format"));
+            }
+        }
+        let resolution = String::from_utf8_lossy(line()?).into_owned();
+        let (height, width) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+            ["-Y", height, "+X", width] => {
+                (height.parse::<usize>().ok(), width.parse::<usize>().ok())
+            }
+            _ => (None, None),
+        };
+        let (Some(height), Some(width)) = (height, width) else {
+            return Err(invalid("unsupported HDR orientation"));
+        };
+        let mut data = &bytes[pos..];
+        let count = width
+            .checked_mul(height)
+            .ok_or_else(|| invalid("HDR image too large"))?;
+        // Runs pack at most 127 pixels into 2 bytes for each of the 4 components, so there can't
+        // be more than 16 pixels to a byte; check before allocating for them.
+        if data.len().saturating_mul(16) < count {
+            return Err(invalid("truncated HDR image"));
+        }
+        let mut rgbe = vec![[0u8; 4]; count];
+        for row in rgbe.chunks_mut(width.max(1)) {
+            data = read_hdr_scanline(data, row)?;
+        }
+        let pixels = rgbe
+            .iter()
+            .map(|&[r, g, b, e]| {
+                if e == 0 {
+                    return Colour::default();
+                }
+                let scale = 2f64.powi(e as i32 - 136);
+                Colour::new(r as f64 * scale, g as f64 * scale, b as f64 * scale)
+            })
+            .collect();
+        Ok(Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Read a portable float map (.pfm), in colour (PF) or greyscale (Pf).
+    pub fn read_pfm(input: &mut dyn Read) -> io::Result<Image> {
+        let mut bytes = Vec::new();
+        input.read_to_end(&mut bytes)?;
+        // Three header lines: the type, the size, and a scale whose sign gives the byte order.
+        let mut header = Vec::new();
+        let mut pos = 0;
+        while header.len() < 4 {
+            while bytes.get(pos).is_some_and(|b| b.is_ascii_whitespace()) {
+                pos += 1;
+            }
+            let start = pos;
+            while bytes.get(pos).is_some_and(|b| !b.is_ascii_whitespace()) {
+                pos += 1;
+            }
+            if start == pos {
+                return Err(invalid("truncated PFM image"));
+            }
+            header.push(String::from_utf8_lossy(&bytes[start..pos]).into_owned());
+        }
+        let channels = match header[0].as_str() {
+            "PF" => 3,
+            "Pf" => 1,
+            _ => return Err(invalid("not a PFM image")),
+        };
+        let bad = |_| invalid("bad number in PFM image");
+        let width: usize = header[1].parse().map_err(bad)?;
+        let height: usize = header[2].parse().map_err(bad)?;
+        let scale: f64 = header[3]
+            .parse()
+            .map_err(|_| invalid("bad number in PFM image"))?;
+        let data = bytes.get(pos + 1..).unwrap_or_default();
+        let count = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(channels))
+            .ok_or_else(|| invalid("PFM image too large"))?;
+        if data.len() / 4 < count {
+            return Err(invalid("truncated PFM image"));
+        }
+        let values: Vec<f64> = data
+            .chunks(4)
+            .take(count)
+            .map(|chunk| {
+                let bytes = [chunk[0], chunk[1], chunk[2], chunk[3]];
+                if scale < 0.0 {
+                    f32::from_le_bytes(bytes) as f64
+                } else {
+                    f32::from_be_bytes(bytes) as f64
+                }
+            })
+            .collect();
+        // Rows are stored from the bottom up.
+        let mut pixels = Vec::with_capacity(width * height);
+        for row in values.chunks(width.max(1) * channels).rev() {
+            pixels.extend(row.chunks(channels).map(|value| match value {
+                [r, g, b] => Colour::new(*r, *g, *b),
+                [v, ..] => Colour::new(*v, *v, *v),
+                [] => Colour::default(),
+            }));
+        }
+        Ok(Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Load an image, read as HDR, PFM or PPM by the file's extension.
+    pub fn load(path: &Path) -> io::Result<Image> {
+        let mut input = BufReader::new(File::open(path)?);
+        let extension = path.extension().map(|e| e.to_ascii_lowercase());
+        match extension.as_ref().and_then(|e| e.to_str()) {
+            Some("hdr") => Image::read_hdr(&mut input),
+            Some("pfm") => Image::read_pfm(&mut input),
+            _ => Image::read_ppm(&mut input),
+        }
+    }
+
+    /// The mean colour over every pixel.
+    pub fn mean(&self) -> Colour {
+        let total = self
+            .pixels
+            .iter()
+            .fold(Colour::default(), |total, &pixel| total + pixel);
+        total / self.pixels.len().max(1) as f64
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> Colour {
+        self.pixels[y * self.width + x]
+    }
+
+    /// The colour at surface coordinates (u, v), with v = 0 at the bottom. The image repeats
+    /// beyond [0, 1], and is interpolated bilinearly between pixel centres.
+    pub fn bilinear(&self, u: f64, v: f64) -> Colour {
+        if self.pixels.is_empty() {
+            return Colour::default();
+        }
+        let x = u.rem_euclid(1.0) * self.width as f64 - 0.5;
+        let y = (1.0 - v).rem_euclid(1.0) * self.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let wrap = |i: f64, n: usize| i.rem_euclid(n as f64) as usize;
+        let (x0, x1) = (wrap(x0, self.width), wrap(x0 + 1.0, self.width));
+        let (y0, y1) = (wrap(y0, self.height), wrap(y0 + 1.0, self.height));
+        (1.0 - fy) * ((1.0 - fx) * self.pixel(x0, y0) + fx * self.pixel(x1, y0))
+            + fy * ((1.0 - fx) * self.pixel(x0, y1) + fx * self.pixel(x1, y1))
+    }
+}
+
+/// Read one scanline of RGBE pixels into `row`, returning the data after it. New-style
+/// run-length encoded scanlines store each component separately; anything else is flat.
+fn read_hdr_scanline<'a>(data: &'a [u8], row: &mut [[u8; 4]]) -> io::Result<&'a [u8]> {
+    let width = row.len();
+    let truncated = || invalid("truncated HDR image");
+    let encoded = (8..0x8000).contains(&width)
+        && data.len() >= 4
+        && data[0] == 2
+        && data[1] == 2
+        && ((data[2] as usize) << 8 | data[3] as usize) == width;
+    if !encoded {
+        let flat = data.get(..width * 4).ok_or_else(truncated)?;
+        for (pixel, value) in row.iter_mut().zip(flat.chunks(4)) {
+            pixel.copy_from_slice(value);
+        }
+        return Ok(&data[width * 4..]);
+    }
+    let mut data = &data[4..];
+    for component in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let (&count, rest) = data.split_first().ok_or_else(truncated)?;
+            let (run, count) = if count > 128 {
+                (true, count as usize - 128)
+            } else {
+                (false, count as usize)
+            };
+            let pixels = row.get_mut(x..x + count).ok_or_else(truncated)?;
+            if count == 0 {
+                return Err(invalid("bad run in HDR image"));
+            }
+            if run {
+                // A run of one value.
+                let (&value, rest) = rest.split_first().ok_or_else(truncated)?;
+                pixels.iter_mut().for_each(|pixel| pixel[component] = value);
+                data = rest;
+            } else {
+                let values = rest.get(..count).ok_or_else(truncated)?;
+                for (pixel, &value) in pixels.iter_mut().zip(values) {
+                    pixel[component] = value;
+                }
+                data = &rest[count..];
+            }
+            x += count;
+        }
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_read_text_ppm() {
+        let text = b"P3\n# two pixels\n2 1\n255\n255 0 0\n0 51 255\n";
+        let image = Image::read_ppm(&mut text.as_slice()).unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(image.pixels[0], Colour::new(1.0, 0.0, 0.0));
+        assert_eq!(image.pixels[1], Colour::new(0.0, 0.2, 1.0));
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_read_binary_ppm() {
+        let mut bytes = b"P6 1 2 255\n".to_vec();
+        bytes.extend([255, 0, 0, 0, 0, 255]);
+        let image = Image::read_ppm(&mut bytes.as_slice()).unwrap();
+        assert_eq!(
+            image.pixels,
+            [Colour::new(1.0, 0.0, 0.0), Colour::new(0.0, 0.0, 1.0)]
+        );
+        bytes.pop();
+        let err = Image::read_ppm(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_read_hdr() {
+        // A flat 2x1 image, then the same run-length encoded 8 pixels wide.
+        let mut bytes = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 2\n".to_vec();
+        bytes.extend([128, 64, 0, 129, 0, 0, 0, 0]);
+        let image = Image::read_hdr(&mut bytes.as_slice()).unwrap();
+        assert_eq!(
+            image.pixels,
+            [Colour::new(1.0, 0.5, 0.0), Colour::default()]
+        );
+
+        let mut bytes = b"#?RADIANCE\n\n-Y 1 +X 8\n".to_vec();
+        bytes.extend([2, 2, 0, 8]);
+        // Red: a run of eight 128s. Green: eight literal values. Blue: zero. Exponents: 129.
+        bytes.extend([136, 128]);
+        bytes.extend([8, 0, 32, 64, 96, 128, 160, 192, 224]);
+        bytes.extend([136, 0]);
+        bytes.extend([136, 129]);
+        let image = Image::read_hdr(&mut bytes.as_slice()).unwrap();
+        assert_eq!((image.width, image.height), (8, 1));
+        assert_eq!(image.pixels[0], Colour::new(1.0, 0.0, 0.0));
+        assert_eq!(image.pixels[4], Colour::new(1.0, 1.0, 0.0));
+
+        bytes.truncate(bytes.len() - 1);
+        let err = Image::read_hdr(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_read_pfm() {
+        // Little-endian, with the bottom row first.
+        let mut bytes = b"PF\n1 2\n-1.0\n".to_vec();
+        for value in [0.5f32, 0.0, 0.0, 0.0, 0.0, 8.0] {
+            bytes.extend(value.to_le_bytes());
+        }
+        let image = Image::read_pfm(&mut bytes.as_slice()).unwrap();
+        assert_eq!(
+            image.pixels,
+            [Colour::new(0.0, 0.0, 8.0), Colour::new(0.5, 0.0, 0.0)]
+        );
+
+        let mut bytes = b"Pf 2 1 1\n".to_vec();
+        for value in [2.0f32, 3.0] {
+            bytes.extend(value.to_be_bytes());
+        }
+        let image = Image::read_pfm(&mut bytes.as_slice()).unwrap();
+        assert_eq!(image.pixels[1], Colour::new(3.0, 3.0, 3.0));
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case(
+        Image::read_ppm,
+        b"P6 4294967296 4294967296 255\n",
+        "PPM image too large"
+    )]
+    #[case(
+        Image::read_ppm,
+        b"P3 6148914691236517206 1 255\n",
+        "PPM image too large"
+    )]
+    #[case(Image::read_ppm, b"P6 65536 65536 255\n\0\0\0", "truncated PPM image")]
+    #[case(
+        Image::read_hdr,
+        b"#?RADIANCE\n\n-Y 4294967296 +X 4294967296\n",
+        "HDR image too large"
+    )]
+    #[case(
+        Image::read_hdr,
+        b"#?RADIANCE\n\n-Y 65536 +X 65536\n\0",
+        "truncated HDR image"
+    )]
+    #[case(
+        Image::read_pfm,
+        b"PF 4294967296 4294967296 -1\n",
+        "PFM image too large"
+    )]
+    #[case(
+        Image::read_pfm,
+        b"PF 6148914691236517206 1 -1\n",
+        "PFM image too large"
+    )]
+    #[case(Image::read_pfm, b"PF 65536 65536 -1\n\0\0\0\0", "truncated PFM image")]
+    fn test_read_rejects_huge_sizes(
+        #[case] read: fn(&mut dyn Read) -> io::Result<Image>,
+        #[case] bytes: &[u8],
+        #[case] want: &str,
+    ) {
+        let err = read(&mut &bytes[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), want);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_bilinear_wraps_and_interpolates() {
+        let image = Image {
+            width: 2,
+            height: 1,
+            pixels: vec![Colour::new(0.0, 0.0, 0.0), Colour::new(1.0, 1.0, 1.0)],
+        };
+        // Pixel centres, halfway between them, and halfway across the seam.
+        assert_eq!(image.bilinear(0.25, 0.5).x, 0.0);
+        assert_eq!(image.bilinear(0.75, 0.5).x, 1.0);
+        assert_eq!(image.bilinear(0.5, 0.5).x, 0.5);
+        assert_eq!(image.bilinear(1.0, 0.5).x, 0.5);
+    }
+}
@@ -0,0 +1,819 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use crate::film::heatmap_colour;
+use crate::hit::{HitRecord, Hittable, HittableList};
+use crate::medium::{Medium, sample_free_flight, sample_henyey_greenstein};
+use crate::path_debug::PathVertex;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::spectrum::SampledWavelengths;
+use crate::stats::RenderStats;
+use crate::{Colour, dot, sample_cosine_hemisphere};
+
+/// Computes the colour seen along a camera ray. The path tracer renders the actual image; the
+/// others visualise something about the scene, to help work out why it renders the way it does.
+pub trait Integrator: Send + Sync + Debug {
+    /// The colour arriving along `r`, counting every ray traced into `stats`.
+    fn li(
+        &self,
+        r: &Ray,
+        world: &HittableList,
+        sampler: &mut dyn Sampler,
+        stats: &mut RenderStats,
+    ) -> Colour;
+
+    /// As `li`, also recording each vertex of the path followed into `path`. Integrators that
+    /// don't follow paths record nothing.
+    fn li_path(
+        &self,
+        r: &Ray,
+        world: &HittableList,
+        sampler: &mut dyn Sampler,
+        stats: &mut RenderStats,
+        _path: &mut Vec<PathVertex>,
+    ) -> Colour {
+        self.li(r, world, sampler, stats)
+    }
+
+    /// As `li`, also returning the first hit along `r`, for AOVs. By default it's traced again
+    /// for the purpose; integrators that trace it anyway hand theirs over instead.
+    fn li_first_hit(
+        &self,
+        r: &Ray,
+        world: &HittableList,
+        sampler: &mut dyn Sampler,
+        stats: &mut RenderStats,
+    ) -> (Colour, Option<HitRecord>) {
+        let colour = self.li(r, world, sampler, stats);
+        (colour, trace(r, world, stats))
+    }
+}
+
+/// Find the first object along `r`, counting the ray in `stats`.
+pub(crate) fn trace(r: &Ray, world: &HittableList, stats: &mut RenderStats) -> Option<HitRecord> {
+    stats.rays += 1;
+    stats.primitive_tests += world.objects.len() as u64;
+    world.hit(r, &(0.001..f64::INFINITY))
+}
+
+/// Whether anything is in the way along `r` before `t_max`, counting the ray in `stats`.
+fn occluded(r: &Ray, t_max: f64, world: &HittableList, stats: &mut RenderStats) -> bool {
+    stats.rays += 1;
+    stats.primitive_tests += world.objects.len() as u64;
+    world.hit(r, &(0.001..t_max)).is_some()
+}
+
+/// The spectrum of an RGB colour at a spectral ray's wavelengths, or the colour itself for an
+/// RGB ray.
+fn upsample(r: &Ray, rgb: Colour) -> Colour {
+    r.wavelengths.map_or(rgb, |w| w.upsample(rgb))
+}
+
+/// Scatters within a medium before giving up on a path. Each one costs a ray, so this keeps
+/// dense, barely absorbing media from tracing for ever; the light still inside is lost.
+const MAX_MEDIUM_EVENTS: usize = 256;
+
+/// Power heuristic weight for a sample drawn with density `f` that another strategy could
+/// have drawn with density `g`.
+fn power_heuristic(f: f64, g: f64) -> f64 {
+    let (f, g) = (f * f, g * g);
+    if f.is_infinite() { 1.0 } else { f / (f + g) }
+}
+
+/// Where a path is among the media it travels through, and what it needs to weigh light it
+/// finds against light sampled directly.
+#[derive(Debug, Clone, Copy, Default)]
+struct PathState {
+    /// The medium the ray is in, if anything but empty space.
+    inside: Option<Medium>,
+    /// The channel whose extinction samples distances in every medium along the path, picked
+    /// the first time it enters one.
+    channel: Option<usize>,
+    /// The density of the path's flights so far had each channel been the one sampled, over
+    /// their mean, or `None` before it has entered a medium.
+    pdf: Option<Colour>,
+    /// The density of the material having chosen the ray's direction, if the background was
+    /// also sampled directly from where it left, so the light it finds must be shared out.
+    scatter_pdf: Option<f64>,
+}
+
+/// Follows rays as they scatter off materials, and through the media inside them, until they
+/// escape to the sky.
+#[derive(Debug, Clone, Copy)]
+pub struct PathIntegrator {
+    /// Bounce limit, after which a path contributes nothing.
+    pub max_depth: usize,
+    /// Trace light at sampled wavelengths rather than as RGB, so that materials such as
+    /// dispersive glass can treat each wavelength differently. Noisier in colour for the same
+    /// number of samples.
+    pub spectral: bool,
+}
+
+impl Default for PathIntegrator {
+    fn default() -> Self {
+        Self {
+            max_depth: 50,
+            spectral: false,
+        }
+    }
+}
+
+impl PathIntegrator {
+    pub fn spectral() -> Self {
+        Self {
+            spectral: true,
+            ..Self::default()
+        }
+    }
+
+    /// The colour arriving along a camera ray. A spectral path carries radiance at each of its
+    /// wavelengths in place of RGB, converted back to RGB once it's done.
+    fn sample(
+        &self,
+        r: &Ray,
+        world: &HittableList,
+        sampler: &mut dyn Sampler,
+        stats: &mut RenderStats,
+        path: Option<&mut Vec<PathVertex>>,
+        first_hit: Option<&mut Option<HitRecord>>,
+    ) -> Colour {
+        let throughput = Colour::new(1.0, 1.0, 1.0);
+        if !self.spectral {
+            return self.ray_colour(
+                r,
+                PathState::default(),
+                world,
+                self.max_depth,
+                sampler,
+                stats,
+                throughput,
+                path,
+                first_hit,
+            );
+        }
+        let wavelengths = SampledWavelengths::sample(sampler.get_1d());
+        let r = r.with_wavelengths(Some(wavelengths));
+        let radiance = self.ray_colour(
+            &r,
+            PathState::default(),
+            world,
+            self.max_depth,
+            sampler,
+            stats,
+            throughput,
+            path,
+            first_hit,
+        );
+        wavelengths.to_rgb(radiance)
+    }
+
+    /// Follow `r` through `medium` to the next surface, scattering off the medium on the way.
+    /// Returns the ray that reaches it, what it hits and the weight of getting there, or `None`
+    /// if the walk gives up. Scatters in the medium don't count as bounces.
+    ///
+    /// Every flight along the path is sampled with one channel's extinction, and weighted by
+    /// the balance heuristic over all of them, which `state` keeps track of. Choosing afresh for
+    /// each walk would compound the variance each time the path goes back in.
+    fn walk_medium(
+        &self,
+        r: &Ray,
+        medium: &Medium,
+        state: &mut PathState,
+        world: &HittableList,
+        sampler: &mut dyn Sampler,
+        stats: &mut RenderStats,
+    ) -> Option<(Ray, Option<HitRecord>, Colour)> {
+        let sigma_s = upsample(r, medium.sigma_s);
+        let sigma_t = upsample(r, medium.sigma_a) + sigma_s;
+        let channel = *state
+            .channel
+            .get_or_insert_with(|| ((sampler.get_1d() * 3.0) as usize).min(2));
+        let pdf = state.pdf.get_or_insert(Colour::new(1.0, 1.0, 1.0));
+        let mut r = *r;
+        let mut weight = Colour::new(1.0, 1.0, 1.0);
+        for _ in 0..MAX_MEDIUM_EVENTS {
+            let hit_record = trace(&r, world, stats);
+            let speed = r.direction.length();
+            let t_max = hit_record
+                .as_ref()
+                .map_or(f64::INFINITY, |rec| rec.t * speed);
+            let flight = sample_free_flight(sigma_t, sigma_s, t_max, channel, sampler.get_1d());
+            // Rescaled so the mean stays 1, keeping the products in range.
+            *pdf *= flight.pdf;
+            let mean_pdf = (pdf.x + pdf.y + pdf.z) / 3.0;
+            if mean_pdf <= 0.0 {
+                return None;
+            }
+            *pdf /= mean_pdf;
+            weight *= flight.f / mean_pdf;
+            let Some(distance) = flight.scattered_at else {
+                return Some((r, hit_record, weight));
+            };
+            let forward = r.direction / speed;
+            let direction = sample_henyey_greenstein(&forward, medium.g, sampler.get_2d());
+            r = Ray::new(r.at(distance / speed), direction).with_wavelengths(r.wavelengths);
+        }
+        None
+    }
+
+    /// Light arriving at a hit straight from the background, along a direction sampled towards
+    /// it, weighted against the material finding it. `None` if either can't be sampled that
+    /// way, in which case the material's own scatters find all of it.
+    fn background_light(
+        &self,
+        r: &Ray,
+        rec: &HitRecord,
+        world: &HittableList,
+        sampler: &mut dyn Sampler,
+        stats: &mut RenderStats,
+    ) -> Option<Colour> {
+        let light = world.background.sample(sampler)?;
+        let evaluation = rec.material.eval(r, rec, &light.direction)?;
+        if evaluation.f == Colour::default() {
+            return Some(Colour::default());
+        }
+        let shadow_ray = Ray::new(rec.p, light.direction).with_wavelengths(r.wavelengths);
+        if occluded(&shadow_ray, f64::INFINITY, world, stats) {
+            return Some(Colour::default());
+        }
+        let weight = power_heuristic(light.pdf, evaluation.pdf) / light.pdf;
+        Some(weight * upsample(r, evaluation.f) * upsample(r, light.radiance))
+    }
+
+    /// Light arriving at a hit from the world's point, spot and directional lights. Nothing
+    /// else can find them, so there's nothing to weigh it against.
+    fn punctual_light(
+        &self,
+        r: &Ray,
+        rec: &HitRecord,
+        world: &HittableList,
+        stats: &mut RenderStats,
+    ) -> Colour {
+        let mut total = Colour::default();
+        for light in &world.lights {
+            let Some(incidence) = light.incidence(&rec.p) else {
+                continue;
+            };
+            let Some(evaluation) = rec.material.eval(r, rec, &incidence.direction) else {
+                // The material can't be evaluated anywhere, whichever light it is.
+                return total;
+            };
+            if evaluation.f == Colour::default() {
+                continue;
+            }
+            let shadow_ray = Ray::new(rec.p, incidence.direction).with_wavelengths(r.wavelengths);
+            if !occluded(&shadow_ray, incidence.distance, world, stats) {
+                total += upsample(r, evaluation.f) * upsample(r, incidence.irradiance);
+            }
+        }
+        total
+    }
+
+    /// `state` tracks the medium `r` is travelling through, if any. `throughput` is the product
+    /// of the attenuations so far, only needed to record the path. The first hit is copied to
+    /// `first_hit`, if given.
+    #[allow(clippy::too_many_arguments)]
+    fn ray_colour(
+        &self,
+        r: &Ray,
+        mut state: PathState,
+        world: &HittableList,
+        depth: usize,
+        sampler: &mut dyn Sampler,
+        stats: &mut RenderStats,
+        throughput: Colour,
+        mut path: Option<&mut Vec<PathVertex>>,
+        first_hit: Option<&mut Option<HitRecord>>,
+    ) -> Colour {
+        if depth == 0 {
+            return Colour::new(0.0, 0.0, 0.0);
+        }
+        // Nothing outside could have been sampled from inside a medium.
+        if state.inside.is_some() {
+            state.scatter_pdf = None;
+        }
+        let (r, hit_record, transmission) = match state.inside {
+            None => (*r, trace(r, world, stats), Colour::new(1.0, 1.0, 1.0)),
+            Some(medium) => match self.walk_medium(r, &medium, &mut state, world, sampler, stats) {
+                Some(walked) => walked,
+                None => return Colour::new(0.0, 0.0, 0.0),
+            },
+        };
+        let r = &r;
+        if let Some(first_hit) = first_hit {
+            *first_hit = hit_record.clone();
+        }
+        let throughput = throughput * transmission;
+        // Find the first object that intersects the ray, and return those details
+        let Some(hit_record) = hit_record else {
+            let mut colour = upsample(r, world.background.radiance(&r.direction));
+            if let Some(scatter_pdf) = state.scatter_pdf {
+                colour *= power_heuristic(scatter_pdf, world.background.pdf(&r.direction));
+            }
+            if let Some(path) = path {
+                path.push(PathVertex::escaped(r, throughput, colour));
+            }
+            return transmission * colour;
+        };
+        let emitted = upsample(r, hit_record.material.emitted(&hit_record));
+        let scatter = hit_record.material.scatter(r, &hit_record, sampler);
+        if let Some(path) = path.as_deref_mut() {
+            path.push(PathVertex::surface(
+                r,
+                throughput,
+                &hit_record,
+                emitted,
+                scatter.as_ref(),
+            ));
+        }
+        let (sky, punctual) = match state.inside {
+            None => (
+                self.background_light(r, &hit_record, world, sampler, stats),
+                self.punctual_light(r, &hit_record, world, stats),
+            ),
+            Some(_) => (None, Colour::default()),
+        };
+        let direct = sky.unwrap_or_default() + punctual;
+        let Some(scatter) = scatter else {
+            return transmission * (emitted + direct);
+        };
+        state.scatter_pdf = sky
+            .and(scatter.pdf)
+            .and_then(|_| {
+                hit_record
+                    .material
+                    .eval(r, &hit_record, &scatter.ray.direction)
+            })
+            .map(|evaluation| evaluation.pdf)
+            .filter(|&pdf| pdf > 0.0);
+        let mut attenuation = upsample(r, scatter.attenuation);
+        let mut wavelengths = r.wavelengths;
+        if let Some(wavelengths) = wavelengths.as_mut().filter(|_| scatter.dispersive) {
+            attenuation *= wavelengths.terminate_secondary();
+        }
+        // Crossing into a material with a medium inside enters it, and crossing back out
+        // leaves it. Any other surface leaves the ray where it was.
+        if let Some(inside) = hit_record.material.medium() {
+            let outward = if hit_record.front_face {
+                hit_record.normal
+            } else {
+                -hit_record.normal
+            };
+            state.inside = (dot(&scatter.ray.direction, &outward) < 0.0).then_some(inside);
+        }
+        transmission
+            * (emitted
+                + direct
+                + attenuation
+                    * self.ray_colour(
+                        &scatter.ray.with_wavelengths(wavelengths),
+                        state,
+                        world,
+                        depth - 1,
+                        sampler,
+                        stats,
+                        throughput * attenuation,
+                        path,
+                        None,
+                    ))
+    }
+}
+
+impl Integrator for PathIntegrator {
+    fn li(
+        &self,
+        r: &Ray,
+        world: &HittableList,
+        sampler: &mut dyn Sampler,
+        stats: &mut RenderStats,
+    ) -> Colour {
+        self.sample(r, world, sampler, stats, None, None)
+    }
+
+    fn li_path(
+        &self,
+        r: &Ray,
+        world: &HittableList,
+        sampler: &mut dyn Sampler,
+        stats: &mut RenderStats,
+        path: &mut Vec<PathVertex>,
+    ) -> Colour {
+        self.sample(r, world, sampler, stats, Some(path), None)
+    }
+
+    fn li_first_hit(
+        &self,
+        r: &Ray,
+        world: &HittableList,
+        sampler: &mut dyn Sampler,
+        stats: &mut RenderStats,
+    ) -> (Colour, Option<HitRecord>) {
+        let mut first_hit = None;
+        let colour = self.sample(r, world, sampler, stats, None, Some(&mut first_hit));
+        (colour, first_hit)
+    }
+}
+
+/// Shading normals at the first hit, mapped from [-1, 1] to [0, 1]. Misses are black.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NormalIntegrator;
+
+impl Integrator for NormalIntegrator {
+    fn li(
+        &self,
+        r: &Ray,
+        world: &HittableList,
+        _sampler: &mut dyn Sampler,
+        stats: &mut RenderStats,
+    ) -> Colour {
+        trace(r, world, stats).map_or(Colour::default(), |rec| {
+            0.5 * (rec.normal + Colour::new(1.0, 1.0, 1.0))
+        })
+    }
+}
+
+/// Surface (u, v) coordinates at the first hit as red and green. Misses are blue.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UvIntegrator;
+
+impl Integrator for UvIntegrator {
+    fn li(
+        &self,
+        r: &Ray,
+        world: &HittableList,
+        _sampler: &mut dyn Sampler,
+        stats: &mut RenderStats,
+    ) -> Colour {
+        trace(r, world, stats).map_or(Colour::new(0.0, 0.0, 1.0), |rec| {
+            Colour::new(rec.u, rec.v, 0.0)
+        })
+    }
+}
+
+/// Green where the first hit is on the outside of a surface, red where it's on the inside.
+/// Misses are black.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrontFaceIntegrator;
+
+impl Integrator for FrontFaceIntegrator {
+    fn li(
+        &self,
+        r: &Ray,
+        world: &HittableList,
+        _sampler: &mut dyn Sampler,
+        stats: &mut RenderStats,
+    ) -> Colour {
+        match trace(r, world, stats) {
+            Some(rec) if rec.front_face => Colour::new(0.0, 1.0, 0.0),
+            Some(_) => Colour::new(1.0, 0.0, 0.0),
+            None => Colour::default(),
+        }
+    }
+}
+
+/// Distance to the first hit, from white at the camera to black at `max_distance` and beyond.
+#[derive(Debug, Clone, Copy)]
+pub struct DepthIntegrator {
+    pub max_distance: f64,
+}
+
+impl Integrator for DepthIntegrator {
+    fn li(
+        &self,
+        r: &Ray,
+        world: &HittableList,
+        _sampler: &mut dyn Sampler,
+        stats: &mut RenderStats,
+    ) -> Colour {
+        let Some(rec) = trace(r, world, stats) else {
+            return Colour::default();
+        };
+        let distance = rec.t * r.direction.length();
+        let shade = 1.0 - (distance / self.max_distance).min(1.0);
+        Colour::new(shade, shade, shade)
+    }
+}
+
+/// Ambient occlusion: how much of the hemisphere above the first hit is open, counting only
+/// blockers within `distance`, weighted by the cosine to the normal. Misses are white.
+///
+/// Directions are sampled in proportion to that cosine, so each sample is simply open or not.
+#[derive(Debug, Clone, Copy)]
+pub struct AmbientOcclusionIntegrator {
+    pub distance: f64,
+}
+
+impl Integrator for AmbientOcclusionIntegrator {
+    fn li(
+        &self,
+        r: &Ray,
+        world: &HittableList,
+        sampler: &mut dyn Sampler,
+        stats: &mut RenderStats,
+    ) -> Colour {
+        let Some(rec) = trace(r, world, stats) else {
+            return Colour::new(1.0, 1.0, 1.0);
+        };
+        let direction = sample_cosine_hemisphere(&rec.normal, sampler.get_2d());
+        if occluded(&Ray::new(rec.p, direction), self.distance, world, stats) {
+            Colour::default()
+        } else {
+            Colour::new(1.0, 1.0, 1.0)
+        }
+    }
+}
+
+/// The intersection tests a full path tracer makes for each sample, as a heatmap from blue
+/// (none) to red (`max_tests` or more), to show where rendering time goes.
+#[derive(Debug, Clone, Copy)]
+pub struct CostIntegrator {
+    pub path: PathIntegrator,
+    pub max_tests: u64,
+}
+
+impl Integrator for CostIntegrator {
+    fn li(
+        &self,
+        r: &Ray,
+        world: &HittableList,
+        sampler: &mut dyn Sampler,
+        stats: &mut RenderStats,
+    ) -> Colour {
+        let tests_before = stats.primitive_tests;
+        self.path.li(r, world, sampler, stats);
+        let tests = stats.primitive_tests - tests_before;
+        heatmap_colour(tests as f64 / self.max_tests.max(1) as f64)
+    }
+}
+
+/// Builds an integrator with sensible default parameters from its name.
+pub fn from_name(name: &str) -> Result<Arc<dyn Integrator>, String> {
+    match name {
+        "path" => Ok(Arc::new(PathIntegrator::default())),
+        "spectral" => Ok(Arc::new(PathIntegrator::spectral())),
+        "normals" => Ok(Arc::new(NormalIntegrator)),
+        "uv" => Ok(Arc::new(UvIntegrator)),
+        "front-face" => Ok(Arc::new(FrontFaceIntegrator)),
+        "depth" => Ok(Arc::new(DepthIntegrator { max_distance: 10.0 })),
+        "ao" => Ok(Arc::new(AmbientOcclusionIntegrator { distance: 1.0 })),
+        "cost" => Ok(Arc::new(CostIntegrator {
+            path: PathIntegrator::default(),
+            max_tests: 16,
+        })),
+        _ => Err(format!("unknown integrator: {name}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::{Background, EnvironmentMap};
+    use crate::image::Image;
+    use crate::light::PointLight;
+    use crate::material::{Conductor, Diffuse, Material, Subsurface};
+    use crate::microfacet::TrowbridgeReitz;
+    use crate::principled::Principled;
+    use crate::sampler::SamplerKind;
+    use crate::sphere::Sphere;
+    use crate::{Point3, Vec3};
+    use rstest::rstest;
+    use std::f64::consts::PI;
+
+    fn test_world() -> HittableList {
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5)));
+        world.add(Box::new(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0)));
+        world
+    }
+
+    fn li(integrator: &dyn Integrator, r: &Ray) -> (Colour, RenderStats) {
+        let mut sampler = SamplerKind::Independent.build(1, 0);
+        sampler.start_pixel_sample(0, 0, 0);
+        let mut stats = RenderStats::default();
+        let colour = integrator.li(r, &test_world(), sampler.as_mut(), &mut stats);
+        (colour, stats)
+    }
+
+    fn towards_sphere() -> Ray {
+        Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0))
+    }
+
+    fn towards_sky() -> Ray {
+        Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0))
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case("path")]
+    #[case("spectral")]
+    #[case("normals")]
+    #[case("uv")]
+    #[case("front-face")]
+    #[case("depth")]
+    #[case("ao")]
+    #[case("cost")]
+    fn test_integrators_count_rays(#[case] name: &str) {
+        let integrator = from_name(name).unwrap();
+        let (_, stats) = li(integrator.as_ref(), &towards_sphere());
+        assert!(stats.rays >= 1);
+        assert_eq!(stats.primitive_tests, 2 * stats.rays);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_normal_integrator() {
+        let (colour, _) = li(&NormalIntegrator, &towards_sphere());
+        assert_eq!(colour, Colour::new(0.5, 0.5, 1.0));
+        let (colour, _) = li(&NormalIntegrator, &towards_sky());
+        assert_eq!(colour, Colour::default());
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_front_face_integrator() {
+        let (colour, _) = li(&FrontFaceIntegrator, &towards_sphere());
+        assert_eq!(colour, Colour::new(0.0, 1.0, 0.0));
+        let inside = Ray::new(Point3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 0.0, -1.0));
+        let (colour, _) = li(&FrontFaceIntegrator, &inside);
+        assert_eq!(colour, Colour::new(1.0, 0.0, 0.0));
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_depth_integrator() {
+        let integrator = DepthIntegrator { max_distance: 2.0 };
+        let (colour, _) = li(&integrator, &towards_sphere());
+        assert!((colour.x - 0.75).abs() < 1e-9);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_ambient_occlusion_integrator() {
+        // Nothing nearby can occlude the top of the sphere.
+        let down = Ray::new(Point3::new(0.0, 2.0, -1.0), Vec3::new(0.0, -1.0, 0.0));
+        let (colour, stats) = li(&AmbientOcclusionIntegrator { distance: 0.1 }, &down);
+        assert_eq!(colour, Colour::new(1.0, 1.0, 1.0));
+        assert_eq!(stats.rays, 2);
+        let (colour, _) = li(
+            &AmbientOcclusionIntegrator { distance: 0.1 },
+            &towards_sky(),
+        );
+        assert_eq!(colour, Colour::new(1.0, 1.0, 1.0));
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_spectral_path_matches_rgb_on_average() {
+        let mean = |integrator: &PathIntegrator| {
+            let world = test_world();
+            let mut sampler = SamplerKind::Independent.build(1, 0);
+            let mut stats = RenderStats::default();
+            let n = 4000;
+            let mut total = Colour::default();
+            for sample_index in 0..n {
+                sampler.start_pixel_sample(0, 0, sample_index);
+                total += integrator.li(&towards_sphere(), &world, sampler.as_mut(), &mut stats);
+            }
+            total / n as f64
+        };
+        let rgb = mean(&PathIntegrator::default());
+        let spectral = mean(&PathIntegrator::spectral());
+        assert!((rgb - spectral).length() < 0.03, "{rgb:?} != {spectral:?}");
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_subsurface_scattering_loses_only_what_it_absorbs() {
+        let mean = |albedo: f64| {
+            let mut world = HittableList::new();
+            let wax = Subsurface::new(
+                Colour::new(albedo, albedo, albedo),
+                Colour::new(0.05, 0.05, 0.05),
+                1.4,
+            );
+            world.add(Box::new(
+                Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5).with_material(Arc::new(wax)),
+            ));
+            let mut sampler = SamplerKind::Independent.build(1, 0);
+            let mut stats = RenderStats::default();
+            let n = 1000;
+            let (mut total, mut lost) = (Colour::default(), 0);
+            for sample_index in 0..n {
+                sampler.start_pixel_sample(0, 0, sample_index);
+                let colour = PathIntegrator::default().li(
+                    &towards_sphere(),
+                    &world,
+                    sampler.as_mut(),
+                    &mut stats,
+                );
+                total += colour;
+                lost += usize::from(colour == Colour::default());
+            }
+            // Scatters inside cost rays but not bounces.
+            assert!(stats.rays > 10 * n as u64);
+            (total / n as f64, lost)
+        };
+        // Nothing absorbs white wax, so paths only fail to get back out to the sky when they
+        // wander inside for too long.
+        let (white, lost) = mean(1.0);
+        assert!(lost < 50, "{lost}");
+        assert!(white.x > 0.5 && white.z <= 1.0, "{white:?}");
+        let (grey, _) = mean(0.5);
+        assert!(grey.x < 0.75 * white.x, "{grey:?} vs {white:?}");
+    }
+
+    /// An environment only found by rays that happen to escape towards it.
+    #[derive(Debug)]
+    struct Unsampled(EnvironmentMap);
+
+    impl Background for Unsampled {
+        fn radiance(&self, direction: &Vec3) -> Colour {
+            self.0.radiance(direction)
+        }
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case(Arc::new(Diffuse { albedo: Colour::new(0.8, 0.5, 0.2) }))]
+    #[case(Arc::new(Conductor::gold(TrowbridgeReitz::from_roughness(0.4, 0.4))))]
+    #[case(Arc::new(Principled { clearcoat: 1.0.into(), ..Principled::default() }))]
+    fn test_sampling_the_environment_agrees_with_finding_it(#[case] material: Arc<dyn Material>) {
+        // A dim sky with a bright patch up and to the side.
+        let (width, height) = (8, 4);
+        let mut pixels = vec![Colour::new(0.2, 0.2, 0.2); width * height];
+        pixels[width + 5] = Colour::new(20.0, 16.0, 12.0);
+        let map = EnvironmentMap::new(Image {
+            width,
+            height,
+            pixels,
+        });
+        let mean = |background: Arc<dyn Background>| {
+            let mut world = HittableList::new();
+            world.background = background;
+            world.add(Box::new(
+                Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5).with_material(material.clone()),
+            ));
+            let mut sampler = SamplerKind::Independent.build(1, 0);
+            let mut stats = RenderStats::default();
+            let n = 20000;
+            let mut total = Colour::default();
+            for sample_index in 0..n {
+                sampler.start_pixel_sample(0, 0, sample_index);
+                let r = Ray::new(Point3::new(0.3, 0.2, 0.0), Vec3::new(0.0, 0.0, -1.0));
+                total += PathIntegrator::default().li(&r, &world, sampler.as_mut(), &mut stats);
+            }
+            total / n as f64
+        };
+        let sampled = mean(Arc::new(map.clone()));
+        let found = mean(Arc::new(Unsampled(map)));
+        let error = (sampled - found).length() / found.length();
+        assert!(error < 0.05, "{sampled:?} vs {found:?}");
+    }
+
+    /// Nothing but the lights.
+    #[derive(Debug)]
+    struct Dark;
+
+    impl Background for Dark {
+        fn radiance(&self, _direction: &Vec3) -> Colour {
+            Colour::default()
+        }
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case(false, 0.5 / (2.0 * PI) * 4.0)]
+    #[case(true, 0.0)]
+    fn test_point_lights_are_found_and_shadowed(#[case] blocked: bool, #[case] want: f64) {
+        let mut world = HittableList::new();
+        world.background = Arc::new(Dark);
+        world.add(Box::new(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5)));
+        if blocked {
+            world.add(Box::new(Sphere::new(Point3::new(0.0, 0.0, 0.5), 0.1)));
+        }
+        // 1.5 away from the front of the sphere, so the irradiance there is 9 / 1.5² = 4.
+        world.lights.push(Box::new(PointLight::new(
+            Point3::new(0.0, 0.0, 1.0),
+            Colour::new(9.0, 9.0, 9.0),
+        )));
+        let mut sampler = SamplerKind::Independent.build(1, 0);
+        sampler.start_pixel_sample(0, 0, 0);
+        let colour = PathIntegrator::default().li(
+            &towards_sphere(),
+            &world,
+            sampler.as_mut(),
+            &mut RenderStats::default(),
+        );
+        assert!(
+            (colour - Colour::new(want, want, want)).length() < 1e-9,
+            "{colour:?}"
+        );
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_unknown_integrator() {
+        assert!(from_name("magic").is_err());
+    }
+}
@@ -0,0 +1,201 @@
+//! Materials built from a clear coating over another material, like car paint or varnish.
+use std::sync::Arc;
+
+use crate::hit::HitRecord;
+use crate::material::{Material, RoughDielectric, Scatter};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::{Colour, dot, unit_vector};
+
+/// Bounces between the base and the underside of the coating before giving up on a ray. Light
+/// still inside by then is lost, which only matters for very bright, barely absorbing layers.
+const MAX_BOUNCES: usize = 32;
+
+/// A dielectric coating over any base material. Light refracts into the coating, is absorbed
+/// as it crosses it, scatters off the base and bounces around inside until it gets back out.
+///
+/// The layers are evaluated stochastically by following one random walk through them per
+/// scatter (Guo et al., "Position-Free Monte Carlo Simulation for Arbitrary Layered BSDFs",
+/// 2018), so the base can be any material. The coating is taken to be thin next to the
+/// surface, so light leaves where it arrived. The density of the direction a walk leaves in
+/// isn't known, so scatters have no pdf.
+#[derive(Debug, Clone)]
+pub struct Layered {
+    pub coating: RoughDielectric,
+    pub base: Arc<dyn Material>,
+    /// Absorption coefficient of the coating per unit length, in scene units.
+    pub absorption: Colour,
+    pub thickness: f64,
+}
+
+impl Layered {
+    /// A clear coating, absorbing nothing.
+    pub fn new(coating: RoughDielectric, base: Arc<dyn Material>) -> Self {
+        Self {
+            coating,
+            base,
+            absorption: Colour::default(),
+            thickness: 0.0,
+        }
+    }
+
+    /// A tinted coating, absorbing light at `absorption` per unit length over `thickness`.
+    pub fn with_absorption(mut self, absorption: Colour, thickness: f64) -> Self {
+        self.absorption = absorption;
+        self.thickness = thickness;
+        self
+    }
+
+    /// The fraction of light crossing the coating at `cos_theta` to the normal that gets through.
+    fn transmittance(&self, cos_theta: f64) -> Colour {
+        let distance = self.thickness / cos_theta.abs().max(1e-6);
+        let optical_depth = self.absorption * distance;
+        Colour::new(
+            (-optical_depth.x).exp(),
+            (-optical_depth.y).exp(),
+            (-optical_depth.z).exp(),
+        )
+    }
+}
+
+impl Material for Layered {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        // Coatings are on the outside; rays inside the object only see the base.
+        if !rec.front_face {
+            return self.base.scatter(r_in, rec, sampler);
+        }
+        let top = self.coating.scatter(r_in, rec, sampler)?;
+        let mut dispersive = top.dispersive;
+        if dot(&top.ray.direction, &rec.normal) > 0.0 {
+            return Some(top);
+        }
+        // The underside of the coating, seen from within it.
+        let underside = HitRecord {
+            normal: -rec.normal,
+            geometric_normal: -rec.geometric_normal,
+            front_face: false,
+            material: rec.material.clone(),
+            ..*rec
+        };
+        let mut throughput = top.attenuation;
+        let mut direction = top.ray.direction;
+        for _ in 0..MAX_BOUNCES {
+            // Down through the coating to the base.
+            throughput *= self.transmittance(dot(&unit_vector(&direction), &rec.normal));
+            let down = Ray::new(rec.p, direction).with_wavelengths(r_in.wavelengths);
+            let base = self.base.scatter(&down, rec, sampler)?;
+            dispersive |= base.dispersive;
+            throughput *= base.attenuation;
+            direction = base.ray.direction;
+            let cos_up = dot(&unit_vector(&direction), &rec.normal);
+            if cos_up <= 0.0 {
+                // Into the base, which isn't modelled as going anywhere.
+                return None;
+            }
+            // Back up to the coating's underside, where it leaves or reflects back down.
+            throughput *= self.transmittance(cos_up);
+            let up = Ray::new(rec.p, direction).with_wavelengths(r_in.wavelengths);
+            let interface = self.coating.scatter(&up, &underside, sampler)?;
+            dispersive |= interface.dispersive;
+            throughput *= interface.attenuation;
+            direction = interface.ray.direction;
+            if dot(&direction, &rec.normal) > 0.0 {
+                return Some(Scatter {
+                    attenuation: throughput,
+                    ray: Ray::new(rec.p, direction),
+                    pdf: None,
+                    dispersive,
+                });
+            }
+        }
+        None
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Colour {
+        self.base.albedo(rec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::tests::hit_on_plane;
+    use crate::material::{self, Diffuse};
+    use crate::microfacet::TrowbridgeReitz;
+    use crate::sampler::SamplerKind;
+    use crate::spectrum::Ior;
+    use crate::{Point3, Vec3};
+    use rstest::rstest;
+
+    /// Mean attenuation of rays arriving at 45°, and the fraction leaving in the mirror
+    /// direction.
+    fn mean_scatter(material: &dyn Material) -> (Colour, f64) {
+        let mirror = unit_vector(&Vec3::new(1.0, 1.0, 0.0));
+        material::tests::mean_scatter(material, &hit_on_plane(), |scatter| {
+            if (unit_vector(&scatter.ray.direction) - mirror).length() < 1e-9 {
+                1.0
+            } else {
+                0.0
+            }
+        })
+    }
+
+    fn clear_coat() -> RoughDielectric {
+        RoughDielectric::new(
+            Ior::Constant(1.5),
+            TrowbridgeReitz::from_roughness(0.0, 0.0),
+        )
+    }
+
+    fn white() -> Arc<dyn Material> {
+        Arc::new(Diffuse {
+            albedo: Colour::new(1.0, 1.0, 1.0),
+        })
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_clear_coat_over_white_conserves_energy() {
+        let (attenuation, mirrored) = mean_scatter(&Layered::new(clear_coat(), white()));
+        assert!(
+            attenuation.x <= 1.0 && attenuation.x > 0.97,
+            "{attenuation:?}"
+        );
+        // Glass reflects about 5% at 45°.
+        assert!((0.03..0.08).contains(&mirrored), "{mirrored}");
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_absorption_tints_and_darkens() {
+        let tinted = |thickness| {
+            let layered = Layered::new(clear_coat(), white())
+                .with_absorption(Colour::new(0.0, 2.0, 4.0), thickness);
+            mean_scatter(&layered).0
+        };
+        let thin = tinted(0.1);
+        let thick = tinted(0.5);
+        assert!(thin.x > thin.y && thin.y > thin.z, "{thin:?}");
+        assert!(thick.y < thin.y && thick.z < thin.z);
+        // Red isn't absorbed, so only changes through the coating's reflection.
+        assert!((thick.x - thin.x).abs() < 0.02);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_inside_sees_only_base() {
+        let layered = Layered::new(clear_coat(), white());
+        let r = Ray::new(Point3::new(-1.0, -1.0, 0.0), Vec3::new(1.0, 1.0, 0.0));
+        let rec = HitRecord {
+            normal: Vec3::new(0.0, -1.0, 0.0),
+            geometric_normal: Vec3::new(0.0, -1.0, 0.0),
+            front_face: false,
+            ..hit_on_plane()
+        };
+        let mut sampler = SamplerKind::Independent.build(1, 0);
+        sampler.start_pixel_sample(0, 0, 0);
+        let scatter = layered.scatter(&r, &rec, sampler.as_mut()).unwrap();
+        assert!(scatter.ray.direction.y < 0.0);
+        assert_eq!(scatter.attenuation, Colour::new(1.0, 1.0, 1.0));
+    }
+}
@@ -1,14 +1,42 @@
+pub mod aov;
+pub mod budget;
 pub mod camera;
+pub mod checkpoint;
+pub mod denoise;
+pub mod detail;
+pub mod distributed;
+pub mod distribution;
+pub mod environment;
+pub mod film;
+pub mod filter;
 pub mod hit;
+pub mod ies;
+pub mod image;
+pub mod integrator;
+pub mod layered;
+pub mod light;
+pub mod material;
+pub mod medium;
+pub mod microfacet;
+pub mod path_debug;
+pub mod principled;
+pub mod progress;
 pub mod ray;
+pub mod sampler;
+pub mod scene;
+pub mod sky;
+pub mod spectrum;
 pub mod sphere;
+pub mod stats;
+pub mod texture;
+pub mod thin_film;
 
 use std::fmt;
 use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
 
 use crate::ray::Ray;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
 pub struct Vec3 {
     pub x: f64,
     pub y: f64,
@@ -58,6 +86,11 @@ impl Vec3 {
     pub fn length(&self) -> f64 {
         self.length_squared().sqrt()
     }
+
+    /// Relative luminance, treating the vector as a linear Rec. 709 colour.
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.x + 0.7152 * self.y + 0.0722 * self.z
+    }
 }
 
 pub fn unit_vector(v: &Vec3) -> Vec3 {
@@ -83,10 +116,68 @@ pub fn random_on_hemisphere(normal: &Vec3) -> Vec3 {
     -on_unit_sphere
 }
 
+/// Maps a pair of uniform samples to a uniformly distributed direction on the unit sphere.
+pub fn sample_uniform_sphere(u: (f64, f64)) -> Vec3 {
+    let z = 1.0 - 2.0 * u.0;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * std::f64::consts::PI * u.1;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// As `random_on_hemisphere`, but driven by a pair of sample values rather than the thread RNG.
+pub fn sample_on_hemisphere(normal: &Vec3, u: (f64, f64)) -> Vec3 {
+    let on_unit_sphere = sample_uniform_sphere(u);
+    if dot(&on_unit_sphere, normal) > 0.0 {
+        return on_unit_sphere;
+    }
+    -on_unit_sphere
+}
+
+/// Two unit vectors that, with `normal`, form an orthonormal basis (Duff et al., "Building an
+/// Orthonormal Basis, Revisited", 2017).
+pub fn orthonormal_basis(normal: &Vec3) -> (Vec3, Vec3) {
+    let sign = 1.0_f64.copysign(normal.z);
+    let a = -1.0 / (sign + normal.z);
+    let b = normal.x * normal.y * a;
+    (
+        Vec3::new(
+            1.0 + sign * normal.x * normal.x * a,
+            sign * b,
+            -sign * normal.x,
+        ),
+        Vec3::new(b, sign + normal.y * normal.y * a, -normal.y),
+    )
+}
+
+/// Maps a pair of uniform samples to a direction in the hemisphere around the unit vector
+/// `normal`, distributed in proportion to the cosine with it (pdf cos / pi).
+pub fn sample_cosine_hemisphere(normal: &Vec3, u: (f64, f64)) -> Vec3 {
+    let r = u.0.sqrt();
+    let phi = 2.0 * std::f64::consts::PI * u.1;
+    let (x, y, z) = (r * phi.cos(), r * phi.sin(), (1.0 - u.0).max(0.0).sqrt());
+    let (tangent, bitangent) = orthonormal_basis(normal);
+    x * tangent + y * bitangent + z * *normal
+}
+
 pub fn dot(left: &Vec3, right: &Vec3) -> f64 {
     left.x * right.x + left.y * right.y + left.z * right.z
 }
 
+/// Mirror `v` about the plane with unit normal `normal`.
+pub fn reflect(v: &Vec3, normal: &Vec3) -> Vec3 {
+    *v - 2.0 * dot(v, normal) * *normal
+}
+
+/// Bend the unit vector `v` through a surface with unit normal `normal` facing against it, where
+/// `eta_ratio` is the incident medium's index over the transmitted one's. Snell's law must allow
+/// the refraction.
+pub fn refract(v: &Vec3, normal: &Vec3, eta_ratio: f64) -> Vec3 {
+    let cos_theta = dot(&-*v, normal).min(1.0);
+    let perpendicular = eta_ratio * (*v + cos_theta * *normal);
+    let parallel = -(1.0 - perpendicular.length_squared()).abs().sqrt() * *normal;
+    perpendicular + parallel
+}
+
 impl fmt::Display for Vec3 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{} {} {}", self.x, self.y, self.z)
@@ -417,6 +508,45 @@ mod tests {
         assert_eq!(dot(&a, &b), 20.0);
     }
 
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case((0.0, 0.0))]
+    #[case((0.25, 0.5))]
+    #[case((0.9, 0.1))]
+    fn test_sample_on_hemisphere(#[case] u: (f64, f64)) {
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        let v = sample_on_hemisphere(&normal, u);
+        assert!((v.length() - 1.0).abs() < 1e-12);
+        assert!(dot(&v, &normal) >= 0.0);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case(Vec3::new(0.0, 1.0, 0.0))]
+    #[case(Vec3::new(0.0, 0.0, -1.0))]
+    #[case(unit_vector(&Vec3::new(1.0, -2.0, 0.5)))]
+    fn test_orthonormal_basis(#[case] normal: Vec3) {
+        let (t, b) = orthonormal_basis(&normal);
+        for v in [t, b] {
+            assert!((v.length() - 1.0).abs() < 1e-12);
+            assert!(dot(&v, &normal).abs() < 1e-12);
+        }
+        assert!(dot(&t, &b).abs() < 1e-12);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case((0.0, 0.0))]
+    #[case((0.25, 0.5))]
+    #[case((0.999, 0.1))]
+    fn test_sample_cosine_hemisphere(#[case] u: (f64, f64)) {
+        let normal = unit_vector(&Vec3::new(1.0, 1.0, 0.0));
+        let v = sample_cosine_hemisphere(&normal, u);
+        assert!((v.length() - 1.0).abs() < 1e-12);
+        // The first sample's radius on the disk sets the cosine.
+        assert!((dot(&v, &normal) - (1.0 - u.0).sqrt()).abs() < 1e-12);
+    }
+
     #[test_log::test(rstest)]
     #[rstest]
     fn test_ray_callables() {
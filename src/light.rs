@@ -0,0 +1,275 @@
+//! Lights with no size: points, spotlights and the parallel light of a far-off sun. No ray
+//! can hit them, so they only light the scene through the path tracer looking for them.
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use crate::ies::IesProfile;
+use crate::{Colour, Point3, Vec3, dot, unit_vector};
+
+/// Light arriving at a point from a light.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Incidence {
+    /// Unit length, pointing towards the light.
+    pub direction: Vec3,
+    /// How far away the light is along `direction`, infinite for lights at infinity.
+    pub distance: f64,
+    /// The light arriving, already allowing for distance and angle: irradiance on a surface
+    /// facing the light.
+    pub irradiance: Colour,
+}
+
+/// A light that lights points from exactly one direction each.
+pub trait Light: Send + Sync + Debug {
+    /// The light arriving at `p`, before anything in the way is considered. `None` where it
+    /// can't arrive at all.
+    fn incidence(&self, p: &Point3) -> Option<Incidence>;
+}
+
+/// Light shining from one point, falling off with the square of the distance. Equally in every
+/// direction, unless shaped by a measured profile hanging straight down.
+#[derive(Debug, Clone)]
+pub struct PointLight {
+    pub position: Point3,
+    /// Power per unit solid angle, in the brightest direction.
+    pub intensity: Colour,
+    pub profile: Option<Arc<IesProfile>>,
+}
+
+impl PointLight {
+    pub fn new(position: Point3, intensity: Colour) -> Self {
+        Self {
+            position,
+            intensity,
+            profile: None,
+        }
+    }
+
+    /// Shine as the fitting `profile` was measured to, its nadir straight down.
+    pub fn with_profile(mut self, profile: Arc<IesProfile>) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+}
+
+/// The way from a light at `position` to `p`, and how far it is.
+fn towards(position: &Point3, p: &Point3) -> Option<(Vec3, f64)> {
+    let offset = *position - *p;
+    let distance = offset.length();
+    (distance > 0.0).then(|| (offset / distance, distance))
+}
+
+impl Light for PointLight {
+    fn incidence(&self, p: &Point3) -> Option<Incidence> {
+        let (direction, distance) = towards(&self.position, p)?;
+        let shape = self.profile.as_ref().map_or(1.0, |profile| {
+            profile.relative_intensity(&Vec3::new(0.0, -1.0, 0.0), &-direction)
+        });
+        (shape > 0.0).then(|| Incidence {
+            direction,
+            distance,
+            irradiance: shape * self.intensity / (distance * distance),
+        })
+    }
+}
+
+/// A point light shining in a cone, fading out smoothly towards its edge. A measured profile
+/// can shape the light within the cone, its nadir along the middle.
+#[derive(Debug, Clone)]
+pub struct SpotLight {
+    pub position: Point3,
+    /// Unit length, along the middle of the cone.
+    pub direction: Vec3,
+    /// Power per unit solid angle, in the middle of the cone.
+    pub intensity: Colour,
+    /// Cosine of the angle from the middle out to the cone's edge, where the light ends.
+    pub cos_cone: f64,
+    /// Cosine of the angle out to where the light starts to fade.
+    pub cos_falloff_start: f64,
+    pub profile: Option<Arc<IesProfile>>,
+}
+
+impl SpotLight {
+    /// A spot at `position` shining towards `target`, with a cone 30 degrees from the middle to
+    /// the edge that fades over its last 5.
+    pub fn new(position: Point3, target: Point3, intensity: Colour) -> Self {
+        Self {
+            position,
+            direction: unit_vector(&(target - position)),
+            intensity,
+            cos_cone: 30_f64.to_radians().cos(),
+            cos_falloff_start: 25_f64.to_radians().cos(),
+            profile: None,
+        }
+    }
+
+    pub fn with_profile(mut self, profile: Arc<IesProfile>) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    /// Set the angles, in degrees, from the middle out to the edge of the cone and over which
+    /// the light fades inside it.
+    pub fn with_cone(mut self, angle: f64, falloff: f64) -> Self {
+        self.cos_cone = angle.to_radians().cos();
+        self.cos_falloff_start = (angle - falloff.clamp(0.0, angle)).to_radians().cos();
+        self
+    }
+
+    /// How much of the intensity shines out along the unit vector `w`.
+    fn falloff(&self, w: &Vec3) -> f64 {
+        let cos_theta = dot(w, &self.direction);
+        if cos_theta >= self.cos_falloff_start {
+            return 1.0;
+        }
+        if cos_theta <= self.cos_cone {
+            return 0.0;
+        }
+        let t = (cos_theta - self.cos_cone) / (self.cos_falloff_start - self.cos_cone);
+        t * t * (3.0 - 2.0 * t)
+    }
+
+    /// How much of the intensity shines out along the unit vector `w`, from the cone and the
+    /// profile.
+    fn shape(&self, w: &Vec3) -> f64 {
+        let profile = self.profile.as_ref().map_or(1.0, |profile| {
+            profile.relative_intensity(&self.direction, w)
+        });
+        self.falloff(w) * profile
+    }
+}
+
+impl Light for SpotLight {
+    fn incidence(&self, p: &Point3) -> Option<Incidence> {
+        let (direction, distance) = towards(&self.position, p)?;
+        let shape = self.shape(&-direction);
+        (shape > 0.0).then(|| Incidence {
+            direction,
+            distance,
+            irradiance: shape * self.intensity / (distance * distance),
+        })
+    }
+}
+
+/// Parallel light from infinitely far away, like sunlight, the same everywhere.
+#[derive(Debug, Clone, Copy)]
+pub struct DirectionalLight {
+    /// Unit length, the way the light travels.
+    pub direction: Vec3,
+    /// Power per unit area arriving on a surface facing the light.
+    pub irradiance: Colour,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vec3, irradiance: Colour) -> Self {
+        Self {
+            direction: unit_vector(&direction),
+            irradiance,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn incidence(&self, _p: &Point3) -> Option<Incidence> {
+        Some(Incidence {
+            direction: -self.direction,
+            distance: f64::INFINITY,
+            irradiance: self.irradiance,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_point_light_falls_off_with_distance_squared() {
+        let light = PointLight::new(Point3::new(0.0, 2.0, 0.0), Colour::new(8.0, 4.0, 2.0));
+        let incidence = light.incidence(&Point3::new(0.0, 0.0, 0.0)).unwrap();
+        assert_eq!(incidence.direction, Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(incidence.distance, 2.0);
+        assert_eq!(incidence.irradiance, Colour::new(2.0, 1.0, 0.5));
+        assert!(light.incidence(&light.position).is_none());
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case(0.0, 1.0)]
+    #[case(24.0, 1.0)]
+    #[case(27.5, 0.53)]
+    #[case(31.0, 0.0)]
+    fn test_spot_light_fades_at_the_edge(#[case] degrees: f64, #[case] want: f64) {
+        let light = SpotLight::new(
+            Point3::new(0.0, 1.0, 0.0),
+            Point3::new(0.0, 0.0, 0.0),
+            Colour::new(1.0, 1.0, 1.0),
+        );
+        let angle = degrees.to_radians();
+        let w = Vec3::new(angle.sin(), -angle.cos(), 0.0);
+        assert!(
+            (light.falloff(&w) - want).abs() < 0.02,
+            "{}",
+            light.falloff(&w)
+        );
+        let p = light.position + 2.0 * w;
+        match light.incidence(&p) {
+            Some(incidence) => assert!((incidence.irradiance.x - want / 4.0).abs() < 0.01),
+            None => assert_eq!(want, 0.0),
+        }
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_spot_light_cone() {
+        let light = SpotLight::new(
+            Point3::default(),
+            Point3::new(1.0, 0.0, 0.0),
+            Colour::new(1.0, 1.0, 1.0),
+        )
+        .with_cone(10.0, 0.0);
+        let inside = Vec3::new(1.0, 9_f64.to_radians().tan(), 0.0);
+        let outside = Vec3::new(1.0, 11_f64.to_radians().tan(), 0.0);
+        assert_eq!(light.falloff(&unit_vector(&inside)), 1.0);
+        assert_eq!(light.falloff(&unit_vector(&outside)), 0.0);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_profiles_shape_the_light() {
+        // Full intensity straight down, half at 45 degrees, nothing sideways or up.
+        let profile = Arc::new(IesProfile {
+            vertical_angles: vec![0.0, 45.0, 90.0],
+            horizontal_angles: vec![0.0],
+            candela: vec![500.0, 250.0, 0.0],
+            peak: 500.0,
+        });
+        let intensity = Colour::new(2.0, 2.0, 2.0);
+        let light = PointLight::new(Point3::default(), intensity).with_profile(profile.clone());
+        let below = light.incidence(&Point3::new(0.0, -1.0, 0.0)).unwrap();
+        assert_eq!(below.irradiance, intensity);
+        let diagonal = light.incidence(&Point3::new(1.0, -1.0, 0.0)).unwrap();
+        assert!((diagonal.irradiance.x - 0.5).abs() < 1e-9);
+        assert!(light.incidence(&Point3::new(0.0, 1.0, 0.0)).is_none());
+        // Aimed along +X, the profile follows the spot.
+        let spot = SpotLight::new(Point3::default(), Point3::new(1.0, 0.0, 0.0), intensity)
+            .with_profile(profile);
+        let ahead = spot.incidence(&Point3::new(1.0, 0.0, 0.0)).unwrap();
+        assert_eq!(ahead.irradiance, intensity);
+        let off_axis = spot.incidence(&Point3::new(1.0, 20_f64.to_radians().tan(), 0.0));
+        assert!(off_axis.unwrap().irradiance.x < 0.9 * intensity.x);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_directional_light_is_the_same_everywhere() {
+        let light = DirectionalLight::new(Vec3::new(0.0, -2.0, 0.0), Colour::new(3.0, 3.0, 3.0));
+        for p in [Point3::default(), Point3::new(100.0, -5.0, 7.0)] {
+            let incidence = light.incidence(&p).unwrap();
+            assert_eq!(incidence.direction, Vec3::new(0.0, 1.0, 0.0));
+            assert_eq!(incidence.distance, f64::INFINITY);
+            assert_eq!(incidence.irradiance, Colour::new(3.0, 3.0, 3.0));
+        }
+    }
+}
@@ -0,0 +1,732 @@
+use std::f64::consts::PI;
+use std::fmt::Debug;
+use std::sync::{Arc, LazyLock};
+
+use crate::hit::HitRecord;
+use crate::medium::{Medium, single_scattering_albedo};
+use crate::microfacet::{Frame, TrowbridgeReitz, fresnel_complex, fresnel_dielectric};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::spectrum::{Ior, SampledWavelengths};
+use crate::thin_film::ThinFilm;
+use crate::{Colour, Vec3, dot, reflect, refract, sample_on_hemisphere, unit_vector};
+
+/// A ray scattered off a surface.
+#[derive(Debug)]
+pub struct Scatter {
+    /// What the light arriving along `ray` is multiplied by: the BSDF times the cosine, divided
+    /// by `pdf`.
+    pub attenuation: Colour,
+    pub ray: Ray,
+    /// Density (per solid angle) of sampling `ray`'s direction, or `None` if it was the only
+    /// possible direction, as for a mirror.
+    pub pdf: Option<f64>,
+    /// Whether `ray`'s direction was chosen for the hero wavelength of a spectral ray alone, as
+    /// when light disperses through glass, so the other wavelengths it carries can't follow.
+    pub dispersive: bool,
+}
+
+/// How much light a surface scatters from one given direction into another.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Evaluation {
+    /// The BSDF times the cosine of the angle to the normal, consistent with what `scatter`
+    /// samples: averaged over many scatters, `attenuation` times the incoming light comes to
+    /// the integral of this times the incoming light.
+    pub f: Colour,
+    /// The density (per solid angle) of `scatter` choosing the direction.
+    pub pdf: f64,
+}
+
+/// How light interacts with a surface.
+pub trait Material: Send + Sync + Debug {
+    /// Scatter an incoming ray at a hit, or return `None` if the ray is absorbed.
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter>;
+
+    /// Light given off by the surface at a hit. Most materials emit nothing.
+    fn emitted(&self, _rec: &HitRecord) -> Colour {
+        Colour::default()
+    }
+
+    /// The surface's base colour at a hit, for the albedo AOV.
+    fn albedo(&self, rec: &HitRecord) -> Colour;
+
+    /// The medium filling objects made of this material, which light refracted into them
+    /// travels through. Most are empty inside, or opaque.
+    fn medium(&self) -> Option<Medium> {
+        None
+    }
+
+    /// How much light arriving along `direction` (pointing away from the surface) is scattered
+    /// back along `r_in`, for sampling lights directly. Returns `None` where the material
+    /// can't say, whatever the direction: for perfectly smooth surfaces, which only scatter in
+    /// directions no light sample will find, and for those that aren't supported.
+    fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _direction: &Vec3) -> Option<Evaluation> {
+        None
+    }
+}
+
+/// A matte surface, scattering evenly over the hemisphere around the normal.
+#[derive(Debug, Clone, Copy)]
+pub struct Diffuse {
+    pub albedo: Colour,
+}
+
+impl Default for Diffuse {
+    fn default() -> Self {
+        Self {
+            albedo: Colour::new(0.5, 0.5, 0.5),
+        }
+    }
+}
+
+impl Material for Diffuse {
+    fn scatter(&self, _r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        let direction = sample_on_hemisphere(&rec.normal, sampler.get_2d());
+        // Strictly the BSDF (albedo / pi) times the cosine over the pdf, but that's kept out to
+        // match how diffuse surfaces have always rendered here.
+        Some(Scatter {
+            attenuation: self.albedo,
+            ray: Ray::new(rec.p, direction),
+            pdf: Some(1.0 / (2.0 * PI)),
+            dispersive: false,
+        })
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Colour {
+        self.albedo
+    }
+
+    fn eval(&self, _r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Option<Evaluation> {
+        if dot(direction, &rec.normal) <= 0.0 {
+            return Some(Evaluation {
+                f: Colour::default(),
+                pdf: 0.0,
+            });
+        }
+        Some(Evaluation {
+            f: self.albedo / (2.0 * PI),
+            pdf: 1.0 / (2.0 * PI),
+        })
+    }
+}
+
+/// A clear, smooth surface such as glass or water, which reflects or refracts according to the
+/// Fresnel equations (in Schlick's approximation). Spectral rays are refracted by the index at
+/// their hero wavelength, splitting white light into colours; RGB rays use the index at
+/// `Ior::REFERENCE_WAVELENGTH`.
+#[derive(Debug, Clone, Copy)]
+pub struct Dielectric {
+    /// The index inside the surface, relative to outside.
+    pub ior: Ior,
+    pub thin_film: Option<ThinFilm>,
+}
+
+impl Dielectric {
+    pub fn new(ior: Ior) -> Self {
+        Self {
+            ior,
+            thin_film: None,
+        }
+    }
+
+    /// Coat the surface with a thin film, as on a soap bubble (which is a dielectric of index 1,
+    /// so light passes straight through what the film doesn't reflect).
+    pub fn with_thin_film(mut self, thin_film: ThinFilm) -> Self {
+        self.thin_film = Some(thin_film);
+        self
+    }
+
+    fn reflectance(cosine: f64, eta_ratio: f64) -> f64 {
+        let r0 = ((1.0 - eta_ratio) / (1.0 + eta_ratio)).powi(2);
+        r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
+    }
+}
+
+impl Material for Dielectric {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        let wavelength = r_in
+            .wavelengths
+            .map_or(Ior::REFERENCE_WAVELENGTH, |w| w.hero());
+        let ior = self.ior.at(wavelength);
+        let (n_i, n_t) = if rec.front_face {
+            (1.0, ior)
+        } else {
+            (ior, 1.0)
+        };
+        let eta_ratio = n_i / n_t;
+        let unit_direction = unit_vector(&r_in.direction);
+        let cos_theta = dot(&-unit_direction, &rec.normal).min(1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        // Always draw the sample, so the sampler's dimensions line up whichever way it goes.
+        let u = sampler.get_1d();
+        let cannot_refract = eta_ratio * sin_theta > 1.0;
+        // The reflectance of each channel, and whether only a spectral ray's hero wavelength
+        // can follow the path chosen.
+        let (reflectance, dispersive) = match (self.thin_film, &r_in.wavelengths) {
+            (None, wavelengths) => {
+                let r = Self::reflectance(cos_theta, eta_ratio);
+                (
+                    Colour::new(r, r, r),
+                    wavelengths.is_some() && self.ior.is_dispersive(),
+                )
+            }
+            (Some(film), Some(wavelengths)) => {
+                let substrate = Colour::new(n_t, n_t, n_t);
+                let r = film.reflectance_hero(
+                    cos_theta,
+                    n_i,
+                    substrate,
+                    Colour::default(),
+                    wavelengths,
+                );
+                (Colour::new(r, r, r), true)
+            }
+            (Some(film), None) => {
+                let substrate = Colour::new(n_t, n_t, n_t);
+                let r = film.reflectance_rgb(cos_theta, n_i, substrate, Colour::default());
+                (r, false)
+            }
+        };
+        // Reflect or refract with the mean reflectance, weighting the channels to match.
+        let chance = (reflectance.x + reflectance.y + reflectance.z) / 3.0;
+        let white = Colour::new(1.0, 1.0, 1.0);
+        let (direction, attenuation) = if cannot_refract {
+            (reflect(&unit_direction, &rec.normal), white)
+        } else if chance > u {
+            (reflect(&unit_direction, &rec.normal), reflectance / chance)
+        } else {
+            (
+                refract(&unit_direction, &rec.normal, eta_ratio),
+                (white - reflectance) / (1.0 - chance),
+            )
+        };
+        Some(Scatter {
+            attenuation,
+            ray: Ray::new(rec.p, direction),
+            pdf: None,
+            dispersive,
+        })
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Colour {
+        Colour::new(1.0, 1.0, 1.0)
+    }
+}
+
+/// A metal, reflecting with the Fresnel reflectance of its complex index of refraction `eta + ik`
+/// (one per RGB channel) off microfacets of the given roughness.
+#[derive(Debug, Clone, Copy)]
+pub struct Conductor {
+    pub eta: Colour,
+    pub k: Colour,
+    pub distribution: TrowbridgeReitz,
+    pub thin_film: Option<ThinFilm>,
+}
+
+impl Conductor {
+    pub fn new(eta: Colour, k: Colour, distribution: TrowbridgeReitz) -> Self {
+        Self {
+            eta,
+            k,
+            distribution,
+            thin_film: None,
+        }
+    }
+
+    /// Coat the metal with a thin film, like the oxide layer on heat-tinted steel.
+    pub fn with_thin_film(mut self, thin_film: ThinFilm) -> Self {
+        self.thin_film = Some(thin_film);
+        self
+    }
+
+    pub fn gold(distribution: TrowbridgeReitz) -> Self {
+        Self::new(
+            Colour::new(0.143119, 0.374957, 1.44248),
+            Colour::new(3.98316, 2.38572, 1.60322),
+            distribution,
+        )
+    }
+
+    pub fn copper(distribution: TrowbridgeReitz) -> Self {
+        Self::new(
+            Colour::new(0.200438, 0.924033, 1.10221),
+            Colour::new(3.91295, 2.45285, 2.14219),
+            distribution,
+        )
+    }
+
+    pub fn silver(distribution: TrowbridgeReitz) -> Self {
+        Self::new(
+            Colour::new(0.155265, 0.116723, 0.138342),
+            Colour::new(4.82835, 3.12225, 2.14696),
+            distribution,
+        )
+    }
+
+    pub fn aluminium(distribution: TrowbridgeReitz) -> Self {
+        Self::new(
+            Colour::new(1.65746, 0.880369, 0.521229),
+            Colour::new(9.22387, 6.26952, 4.837),
+            distribution,
+        )
+    }
+
+    /// The reflectance of each channel, and whether it's only right for a spectral ray's hero
+    /// wavelength.
+    fn fresnel(&self, cos_theta: f64, wavelengths: Option<&SampledWavelengths>) -> (Colour, bool) {
+        match (self.thin_film, wavelengths) {
+            (None, _) => (
+                Colour::new(
+                    fresnel_complex(cos_theta, self.eta.x, self.k.x),
+                    fresnel_complex(cos_theta, self.eta.y, self.k.y),
+                    fresnel_complex(cos_theta, self.eta.z, self.k.z),
+                ),
+                false,
+            ),
+            (Some(film), Some(wavelengths)) => {
+                let r = film.reflectance_hero(cos_theta, 1.0, self.eta, self.k, wavelengths);
+                (Colour::new(r, r, r), true)
+            }
+            (Some(film), None) => (
+                film.reflectance_rgb(cos_theta, 1.0, self.eta, self.k),
+                false,
+            ),
+        }
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        let frame = Frame::from_normal(&rec.normal);
+        let wo = frame.to_local(&-unit_vector(&r_in.direction));
+        let u = sampler.get_2d();
+        if wo.z <= 0.0 {
+            return None;
+        }
+        if self.distribution.effectively_smooth() {
+            let wi = Vec3::new(-wo.x, -wo.y, wo.z);
+            let (attenuation, dispersive) = self.fresnel(wo.z, r_in.wavelengths.as_ref());
+            return Some(Scatter {
+                attenuation,
+                ray: Ray::new(rec.p, frame.from_local(&wi)),
+                pdf: None,
+                dispersive,
+            });
+        }
+        let wm = self.distribution.sample_wm(&wo, u);
+        let wi = reflect(&-wo, &wm);
+        if wi.z <= 0.0 {
+            return None;
+        }
+        // The BRDF D F G / (4 cos_o cos_i), times cos_i, over the pdf D_wo(wm) / (4 |wo.wm|).
+        let pdf = self.distribution.visible_d(&wo, &wm) / (4.0 * dot(&wo, &wm).abs());
+        let (fresnel, dispersive) = self.fresnel(dot(&wo, &wm).abs(), r_in.wavelengths.as_ref());
+        let attenuation = fresnel * self.distribution.g(&wo, &wi) / self.distribution.g1(&wo);
+        Some(Scatter {
+            attenuation,
+            ray: Ray::new(rec.p, frame.from_local(&wi)),
+            pdf: Some(pdf),
+            dispersive,
+        })
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Colour {
+        self.fresnel(1.0, None).0
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Option<Evaluation> {
+        if self.distribution.effectively_smooth() {
+            return None;
+        }
+        let frame = Frame::from_normal(&rec.normal);
+        let wo = frame.to_local(&-unit_vector(&r_in.direction));
+        let wi = frame.to_local(&unit_vector(direction));
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Some(Evaluation {
+                f: Colour::default(),
+                pdf: 0.0,
+            });
+        }
+        let wm = unit_vector(&(wo + wi));
+        let (fresnel, dispersive) = self.fresnel(dot(&wo, &wm).abs(), r_in.wavelengths.as_ref());
+        if dispersive {
+            return None;
+        }
+        let d = self.distribution.d(&wm);
+        Some(Evaluation {
+            f: fresnel * d * self.distribution.g(&wo, &wi) / (4.0 * wo.z),
+            pdf: self.distribution.visible_d(&wo, &wm) / (4.0 * dot(&wo, &wm).abs()),
+        })
+    }
+}
+
+/// Frosted glass: a dielectric whose surface is rough, reflecting and transmitting through
+/// microfacets. Choosing between the two follows the Fresnel reflectance.
+#[derive(Debug, Clone, Copy)]
+pub struct RoughDielectric {
+    /// The index inside the surface, relative to outside.
+    pub ior: Ior,
+    pub distribution: TrowbridgeReitz,
+}
+
+impl RoughDielectric {
+    pub fn new(ior: Ior, distribution: TrowbridgeReitz) -> Self {
+        Self { ior, distribution }
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        if self.distribution.effectively_smooth() {
+            return Dielectric::new(self.ior).scatter(r_in, rec, sampler);
+        }
+        let wavelength = r_in
+            .wavelengths
+            .map_or(Ior::REFERENCE_WAVELENGTH, |w| w.hero());
+        let ior = self.ior.at(wavelength);
+        // The transmitted side's index over the incident side's.
+        let eta = if rec.front_face { ior } else { 1.0 / ior };
+        let frame = Frame::from_normal(&rec.normal);
+        let wo = frame.to_local(&-unit_vector(&r_in.direction));
+        let u_lobe = sampler.get_1d();
+        let u = sampler.get_2d();
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let wm = self.distribution.sample_wm(&wo, u);
+        let cos_o_m = dot(&wo, &wm);
+        let reflectance = fresnel_dielectric(cos_o_m, eta);
+        let (wi, pdf, f) = if u_lobe < reflectance {
+            let wi = reflect(&-wo, &wm);
+            if wi.z <= 0.0 {
+                return None;
+            }
+            let pdf = self.distribution.visible_d(&wo, &wm) / (4.0 * cos_o_m) * reflectance;
+            let f = self.distribution.d(&wm) * self.distribution.g(&wo, &wi) * reflectance
+                / (4.0 * wo.z * wi.z);
+            (wi, pdf, f)
+        } else {
+            let wi = refract(&-wo, &wm, 1.0 / eta);
+            if wi.z >= 0.0 {
+                return None;
+            }
+            let transmittance = 1.0 - reflectance;
+            let cos_i_m = dot(&wi, &wm);
+            let denom = cos_i_m + cos_o_m / eta;
+            let pdf = self.distribution.visible_d(&wo, &wm) * cos_i_m.abs() / (denom * denom)
+                * transmittance;
+            // Radiance isn't scaled by the change in solid angle, 1 / eta², to match how smooth
+            // glass transmits here; rays that go in also come out, so it cancels anyway.
+            let f = transmittance
+                * self.distribution.d(&wm)
+                * self.distribution.g(&wo, &wi)
+                * (cos_i_m * cos_o_m / (wi.z * wo.z * denom * denom)).abs();
+            (wi, pdf, f)
+        };
+        if pdf <= 0.0 {
+            return None;
+        }
+        let weight = f * wi.z.abs() / pdf;
+        Some(Scatter {
+            attenuation: Colour::new(weight, weight, weight),
+            ray: Ray::new(rec.p, frame.from_local(&wi)),
+            pdf: Some(pdf),
+            dispersive: r_in.wavelengths.is_some() && self.ior.is_dispersive(),
+        })
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Colour {
+        Colour::new(1.0, 1.0, 1.0)
+    }
+}
+
+/// Translucent materials like wax, marble or skin, where light refracts in, scatters around
+/// beneath the surface and comes back out some way from where it went in. The inside is a
+/// medium the path tracer walks through, so the objects must be closed.
+#[derive(Debug, Clone, Copy)]
+pub struct Subsurface {
+    /// The colour the surface looks overall, once light has scattered around inside.
+    pub albedo: Colour,
+    pub boundary: RoughDielectric,
+    pub medium: Medium,
+}
+
+impl Subsurface {
+    /// A smooth surface over a medium in which light travels `mean_free_path` on average
+    /// between scatters, per channel, in scene units. Longer paths look more translucent.
+    pub fn new(albedo: Colour, mean_free_path: Colour, ior: f64) -> Self {
+        let single = Colour::new(
+            single_scattering_albedo(albedo.x),
+            single_scattering_albedo(albedo.y),
+            single_scattering_albedo(albedo.z),
+        );
+        Self {
+            albedo,
+            boundary: RoughDielectric::new(
+                Ior::Constant(ior),
+                TrowbridgeReitz::from_roughness(0.0, 0.0),
+            ),
+            medium: Medium::from_albedo(single, mean_free_path),
+        }
+    }
+
+    pub fn with_roughness(mut self, roughness: f64) -> Self {
+        self.boundary.distribution = TrowbridgeReitz::from_roughness(roughness, roughness);
+        self
+    }
+
+    /// Scatter mostly forwards inside, for positive `g`, or backwards, for negative.
+    pub fn with_anisotropy(mut self, g: f64) -> Self {
+        self.medium = self.medium.with_anisotropy(g);
+        self
+    }
+}
+
+impl Material for Subsurface {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        self.boundary.scatter(r_in, rec, sampler)
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Colour {
+        self.albedo
+    }
+
+    fn medium(&self) -> Option<Medium> {
+        Some(self.medium)
+    }
+}
+
+/// The material objects get unless given another, shared so that they all have the same ID.
+pub(crate) static DEFAULT_MATERIAL: LazyLock<Arc<dyn Material>> =
+    LazyLock::new(|| Arc::new(Diffuse::default()));
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::hit::Hittable;
+    use crate::microfacet::Frame;
+    use crate::sampler::SamplerKind;
+    use crate::spectrum::SampledWavelengths;
+    use crate::sphere::Sphere;
+    use crate::{Point3, Vec3};
+    use rstest::rstest;
+    use std::ops::{AddAssign, Div};
+
+    /// The first scatter of a ray glancing off the top of a sphere made of `material`.
+    fn scatter_off_glass_with(
+        material: &dyn Material,
+        wavelengths: Option<SampledWavelengths>,
+    ) -> Scatter {
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, -2.0), 1.0);
+        let r = Ray::new(Point3::new(0.0, 0.7, 0.0), Vec3::new(0.0, 0.0, -1.0))
+            .with_wavelengths(wavelengths);
+        let rec = sphere.hit(&r, &(0.001..f64::INFINITY)).unwrap();
+        let mut sampler = SamplerKind::Independent.build(1, 0);
+        sampler.start_pixel_sample(0, 0, 0);
+        material.scatter(&r, &rec, sampler.as_mut()).unwrap()
+    }
+
+    /// Where a ray glancing off the top of a glass sphere goes, at a fixed sample that refracts.
+    fn scatter_off_glass(ior: Ior, wavelengths: Option<SampledWavelengths>) -> Scatter {
+        let glass = Dielectric::new(ior);
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, -2.0), 1.0);
+        let r = Ray::new(Point3::new(0.0, 0.7, 0.0), Vec3::new(0.0, 0.0, -1.0))
+            .with_wavelengths(wavelengths);
+        let rec = sphere.hit(&r, &(0.001..f64::INFINITY)).unwrap();
+        let mut sampler = SamplerKind::Independent.build(1, 0);
+        sampler.start_pixel_sample(0, 0, 0);
+        // Find a sample that refracts rather than reflects.
+        for sample_index in 0.. {
+            sampler.start_pixel_sample(0, 0, sample_index);
+            let scatter = glass.scatter(&r, &rec, sampler.as_mut()).unwrap();
+            if scatter.ray.direction.z < -0.5 {
+                return scatter;
+            }
+        }
+        unreachable!()
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_dielectric_disperses_spectral_rays() {
+        let blue = SampledWavelengths::sample(0.1);
+        let red = SampledWavelengths::sample(0.8);
+        assert!(blue.hero() < 450.0 && red.hero() > 680.0);
+        let blue_scatter = scatter_off_glass(Ior::BK7, Some(blue));
+        let red_scatter = scatter_off_glass(Ior::BK7, Some(red));
+        assert!(blue_scatter.dispersive && red_scatter.dispersive);
+        // Blue bends more, towards the axis through the sphere's centre.
+        assert!(blue_scatter.ray.direction.y < red_scatter.ray.direction.y);
+    }
+
+    /// A hit on the outside of a flat surface facing up, at the origin.
+    pub(crate) fn hit_on_plane() -> HitRecord {
+        HitRecord {
+            p: Point3::default(),
+            normal: Vec3::new(0.0, 1.0, 0.0),
+            geometric_normal: Vec3::new(0.0, 1.0, 0.0),
+            dpdu: Vec3::new(1.0, 0.0, 0.0),
+            dpdv: Vec3::new(0.0, 0.0, -1.0),
+            t: 1.0,
+            u: 0.0,
+            v: 0.0,
+            front_face: true,
+            material: DEFAULT_MATERIAL.clone(),
+            object_id: 0,
+            material_id: 0,
+        }
+    }
+
+    /// The mean attenuation of rays arriving at 45° to the normal of `rec`, a variant of
+    /// `hit_on_plane`, counting absorbed rays as zero, and the mean of what `collect` makes of
+    /// each scattered ray.
+    pub(crate) fn mean_scatter<T>(
+        material: &dyn Material,
+        rec: &HitRecord,
+        collect: impl Fn(&Scatter) -> T,
+    ) -> (Colour, T)
+    where
+        T: Default + AddAssign + Div<f64, Output = T>,
+    {
+        let r = Ray::new(Point3::new(-1.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 0.0));
+        let mut sampler = SamplerKind::Independent.build(1, 0);
+        let n = 20000;
+        let (mut attenuation, mut collected) = (Colour::default(), T::default());
+        for sample_index in 0..n {
+            sampler.start_pixel_sample(0, 0, sample_index);
+            if let Some(scatter) = material.scatter(&r, rec, sampler.as_mut()) {
+                attenuation += scatter.attenuation;
+                collected += collect(&scatter);
+            }
+        }
+        (attenuation / n as f64, collected / n as f64)
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_smooth_conductor_is_a_mirror() {
+        let gold = Conductor::gold(TrowbridgeReitz::from_roughness(0.0, 0.0));
+        let (attenuation, direction) =
+            mean_scatter(&gold, &hit_on_plane(), |s| unit_vector(&s.ray.direction));
+        assert!((direction - unit_vector(&Vec3::new(1.0, 1.0, 0.0))).length() < 1e-9);
+        // Gold is yellow.
+        assert!(attenuation.x > attenuation.y && attenuation.y > attenuation.z);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case(0.2, 0.95)]
+    #[case(0.5, 0.85)]
+    #[case(0.9, 0.45)]
+    fn test_rough_conductor_furnace(#[case] roughness: f64, #[case] at_least: f64) {
+        // A perfect reflector loses only the light that would scatter between microfacets more
+        // than once, which grows with roughness: nearly half at 0.9.
+        let mirror = Conductor::new(
+            Colour::new(1.0, 1.0, 1.0),
+            Colour::new(1e4, 1e4, 1e4),
+            TrowbridgeReitz::from_roughness(roughness, roughness),
+        );
+        let (attenuation, _) = mean_scatter(&mirror, &hit_on_plane(), |_| 0.0);
+        assert!(
+            attenuation.x <= 1.0 && attenuation.x > at_least,
+            "{attenuation:?}"
+        );
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_anisotropic_conductor_spreads_along_rough_axis() {
+        let spread = |distribution| {
+            let metal = Conductor::aluminium(distribution);
+            let r = Ray::new(Point3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+            let rec = hit_on_plane();
+            let frame = Frame::from_normal(&rec.normal);
+            let mut sampler = SamplerKind::Independent.build(1, 0);
+            let mut total = Vec3::default();
+            for sample_index in 0..5000 {
+                sampler.start_pixel_sample(0, 0, sample_index);
+                if let Some(scatter) = metal.scatter(&r, &rec, sampler.as_mut()) {
+                    let local = frame.to_local(&unit_vector(&scatter.ray.direction));
+                    total += local * local;
+                }
+            }
+            total
+        };
+        let brushed = spread(TrowbridgeReitz::from_roughness(0.7, 0.1));
+        assert!(brushed.x > 10.0 * brushed.y, "{brushed:?}");
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case(0.1)]
+    #[case(0.5)]
+    fn test_rough_dielectric_furnace(#[case] roughness: f64) {
+        let glass = RoughDielectric::new(
+            Ior::Constant(1.5),
+            TrowbridgeReitz::from_roughness(roughness, roughness),
+        );
+        let (attenuation, direction) =
+            mean_scatter(&glass, &hit_on_plane(), |s| unit_vector(&s.ray.direction));
+        assert!(
+            attenuation.x <= 1.0 && attenuation.x > 0.85,
+            "{attenuation:?}"
+        );
+        // Most light goes into the glass.
+        assert!(direction.y < 0.0);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_soap_bubble_reflects_in_colour() {
+        let film = ThinFilm::new(350.0, 1.33);
+        let bubble = Dielectric::new(Ior::Constant(1.0)).with_thin_film(film);
+        let r = Ray::new(Point3::new(-1.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 0.0));
+        let rec = hit_on_plane();
+        let mut sampler = SamplerKind::Independent.build(1, 0);
+        let n = 20000;
+        let (mut reflected, mut transmitted) = (Colour::default(), Colour::default());
+        for sample_index in 0..n {
+            sampler.start_pixel_sample(0, 0, sample_index);
+            let scatter = bubble.scatter(&r, &rec, sampler.as_mut()).unwrap();
+            if scatter.ray.direction.y > 0.0 {
+                reflected += scatter.attenuation;
+            } else {
+                // Light the film doesn't reflect passes straight through.
+                let straight = unit_vector(&r.direction);
+                assert!((scatter.ray.direction - straight).length() < 1e-12);
+                transmitted += scatter.attenuation;
+            }
+        }
+        let (reflected, transmitted) = (reflected / n as f64, transmitted / n as f64);
+        let cos_45 = 0.5_f64.sqrt();
+        let want = film.reflectance_rgb(cos_45, 1.0, Colour::new(1.0, 1.0, 1.0), Colour::default());
+        assert!(
+            (reflected - want).length() < 0.01,
+            "{reflected:?} != {want:?}"
+        );
+        assert!((reflected + transmitted - Colour::new(1.0, 1.0, 1.0)).length() < 0.02);
+        assert!(want.y - want.x.min(want.z) > 0.02, "{want:?}");
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_thin_film_follows_hero_wavelength() {
+        let film = ThinFilm::new(350.0, 1.33);
+        let wavelengths = Some(SampledWavelengths::sample(0.5));
+        let bubble = Dielectric::new(Ior::Constant(1.0)).with_thin_film(film);
+        assert!(scatter_off_glass_with(&bubble, wavelengths).dispersive);
+        let metal = Conductor::gold(TrowbridgeReitz::from_roughness(0.3, 0.3)).with_thin_film(film);
+        assert!(scatter_off_glass_with(&metal, wavelengths).dispersive);
+        assert!(!scatter_off_glass_with(&metal, None).dispersive);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_dielectric_without_dispersion() {
+        let rgb = scatter_off_glass(Ior::BK7, None);
+        assert!(!rgb.dispersive);
+        let constant = scatter_off_glass(Ior::Constant(1.5), Some(SampledWavelengths::sample(0.1)));
+        assert!(!constant.dispersive);
+        assert_eq!(rgb.pdf, None);
+    }
+}
@@ -0,0 +1,213 @@
+//! Participating media: volumes like wax, skin or milk that absorb and scatter light as it
+//! travels through them, rather than only at surfaces.
+use std::f64::consts::PI;
+
+use crate::{Colour, Vec3, orthonormal_basis};
+
+/// A homogeneous medium. Coefficients are per unit length in scene units, for each RGB channel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Medium {
+    pub sigma_a: Colour,
+    pub sigma_s: Colour,
+    /// Henyey-Greenstein asymmetry: the mean cosine between the directions before and after
+    /// scattering. 0 scatters evenly in all directions, positive values mostly forwards.
+    pub g: f64,
+}
+
+impl Medium {
+    /// A medium in which light travels `mean_free_path` on average between interactions, and
+    /// survives each with probability `albedo`.
+    pub fn from_albedo(albedo: Colour, mean_free_path: Colour) -> Self {
+        let sigma_t = Colour::new(
+            1.0 / mean_free_path.x,
+            1.0 / mean_free_path.y,
+            1.0 / mean_free_path.z,
+        );
+        let sigma_s = albedo * sigma_t;
+        Self {
+            sigma_a: sigma_t - sigma_s,
+            sigma_s,
+            g: 0.0,
+        }
+    }
+
+    pub fn with_anisotropy(mut self, g: f64) -> Self {
+        self.g = g;
+        self
+    }
+}
+
+/// The single-scattering albedo a medium needs for light scattering around inside it to come
+/// back out with `albedo` overall, from the fit by Chiang et al., "Practical and Controllable
+/// Subsurface Scattering for Production Path Tracing", 2016. Multiple scattering compounds
+/// absorption, so the medium must absorb much less than the surface looks like it does.
+pub fn single_scattering_albedo(albedo: f64) -> f64 {
+    let a = albedo.clamp(0.0, 1.0);
+    let s = 4.09712 + 4.20863 * a - (9.59217 + 41.6808 * a + 17.7126 * a * a).sqrt();
+    (1.0 - s * s).clamp(0.0, 1.0)
+}
+
+/// The outcome of following a ray through a medium, up to the next surface at `t_max`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FreeFlight {
+    /// How far along the ray it scattered, or `None` if it reached the surface.
+    pub scattered_at: Option<f64>,
+    /// The transmittance to there, times the scattering coefficient for a scatter.
+    pub f: Vec3,
+    /// The density of sampling that outcome had each channel been the one sampled from.
+    pub pdf: Vec3,
+}
+
+/// Sample how far light travels through a medium with extinction `sigma_t` and scattering
+/// `sigma_s` before it next scatters, using the extinction of one `channel` (an RGB channel, or
+/// one of a spectral path's wavelengths).
+///
+/// The channels may differ, so a walk picks one at random and keeps to it, multiplying up the
+/// `f` and `pdf` of each flight. Weighting its throughput by the product of the `f`s over the
+/// mean of the products of the `pdf`s combines the channels by the balance heuristic; weighting
+/// each flight by itself instead compounds the variance the longer the walk goes on.
+pub fn sample_free_flight(
+    sigma_t: Vec3,
+    sigma_s: Vec3,
+    t_max: f64,
+    channel: usize,
+    u: f64,
+) -> FreeFlight {
+    let extinction = [sigma_t.x, sigma_t.y, sigma_t.z][channel];
+    let distance = if extinction > 0.0 {
+        -(1.0 - u).ln() / extinction
+    } else {
+        f64::INFINITY
+    };
+    let transmittance = |t: f64| {
+        Vec3::new(
+            (-sigma_t.x * t).exp(),
+            (-sigma_t.y * t).exp(),
+            (-sigma_t.z * t).exp(),
+        )
+    };
+    if distance < t_max {
+        let tr = transmittance(distance);
+        FreeFlight {
+            scattered_at: Some(distance),
+            f: sigma_s * tr,
+            pdf: sigma_t * tr,
+        }
+    } else {
+        let tr = transmittance(t_max);
+        FreeFlight {
+            scattered_at: None,
+            f: tr,
+            pdf: tr,
+        }
+    }
+}
+
+/// Sample a new direction for light travelling along the unit vector `forward` that scatters
+/// with the Henyey-Greenstein phase function of asymmetry `g`. The phase function is its own
+/// sampling density, so there's no weight.
+pub fn sample_henyey_greenstein(forward: &Vec3, g: f64, u: (f64, f64)) -> Vec3 {
+    let cos_theta = if g.abs() < 1e-3 {
+        1.0 - 2.0 * u.0
+    } else {
+        let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u.0);
+        (1.0 + g * g - s * s) / (2.0 * g)
+    }
+    .clamp(-1.0, 1.0);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.1;
+    let (tangent, bitangent) = orthonormal_basis(forward);
+    sin_theta * phi.cos() * tangent + sin_theta * phi.sin() * bitangent + cos_theta * *forward
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dot;
+    use rstest::rstest;
+
+    /// A single flight's weight, from a channel picked by `u_channel`.
+    fn weight(sigma_t: Vec3, sigma_s: Vec3, t_max: f64, u: (f64, f64)) -> (bool, Vec3) {
+        let channel = ((u.0 * 3.0) as usize).min(2);
+        let flight = sample_free_flight(sigma_t, sigma_s, t_max, channel, u.1);
+        let pdf = (flight.pdf.x + flight.pdf.y + flight.pdf.z) / 3.0;
+        (flight.scattered_at.is_some(), flight.f / pdf)
+    }
+
+    fn grid(n: usize) -> impl Iterator<Item = (f64, f64)> {
+        (0..n).flat_map(move |i| {
+            (0..n).map(move |j| ((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64))
+        })
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case(0.0)]
+    #[case(0.7)]
+    #[case(-0.4)]
+    fn test_henyey_greenstein_mean_cosine(#[case] g: f64) {
+        let forward = Vec3::new(0.0, 0.6, 0.8);
+        let n = 200;
+        let total: f64 = grid(n)
+            .map(|u| {
+                let direction = sample_henyey_greenstein(&forward, g, u);
+                assert!((direction.length() - 1.0).abs() < 1e-9);
+                dot(&direction, &forward)
+            })
+            .sum();
+        assert!((total / (n * n) as f64 - g).abs() < 1e-3);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_free_flight_transmittance() {
+        // The expected weight of getting through to the surface is the transmittance.
+        let sigma_t = Vec3::new(0.5, 2.0, 6.0);
+        let t_max = 0.4;
+        let n = 300;
+        let mut through = Vec3::default();
+        for u in grid(n) {
+            if let (false, weight) = weight(sigma_t, sigma_t, t_max, u) {
+                through += weight;
+            }
+        }
+        let through = through / (n * n) as f64;
+        let want = Vec3::new((-0.2_f64).exp(), (-0.8_f64).exp(), (-2.4_f64).exp());
+        assert!((through - want).length() < 1e-2, "{through:?} != {want:?}");
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_scattering_without_absorption_keeps_energy() {
+        let sigma = Vec3::new(3.0, 3.0, 3.0);
+        for u in grid(20) {
+            let (_, weight) = weight(sigma, sigma, 1.0, u);
+            assert!((weight - Vec3::new(1.0, 1.0, 1.0)).length() < 1e-12);
+        }
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_single_scattering_albedo() {
+        assert!(single_scattering_albedo(0.0) < 1e-5);
+        assert!((single_scattering_albedo(1.0) - 1.0).abs() < 1e-4);
+        let mut last = 0.0;
+        for i in 1..=10 {
+            let albedo = i as f64 / 10.0;
+            let single = single_scattering_albedo(albedo);
+            assert!(
+                single >= last && single >= albedo - 1e-9,
+                "{albedo}: {single}"
+            );
+            last = single;
+        }
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_medium_from_albedo() {
+        let medium = Medium::from_albedo(Colour::new(0.8, 0.5, 0.0), Colour::new(0.5, 0.25, 1.0));
+        assert_eq!(medium.sigma_s, Colour::new(1.6, 2.0, 0.0));
+        assert!((medium.sigma_a - Colour::new(0.4, 2.0, 1.0)).length() < 1e-12);
+    }
+}
@@ -0,0 +1,255 @@
+//! Microfacet models of rough surfaces: the surface is treated as a mass of tiny mirrors whose
+//! normals follow the Trowbridge-Reitz (GGX) distribution. Directions here are in a local
+//! shading frame with the surface normal along z.
+use std::f64::consts::PI;
+
+use crate::{Vec3, dot, orthonormal_basis, unit_vector};
+
+/// A shading frame around a unit normal, for moving directions to and from local coordinates.
+#[derive(Debug, Clone, Copy)]
+pub struct Frame {
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
+    pub normal: Vec3,
+}
+
+impl Frame {
+    pub fn from_normal(normal: &Vec3) -> Self {
+        let (tangent, bitangent) = orthonormal_basis(normal);
+        Self {
+            tangent,
+            bitangent,
+            normal: *normal,
+        }
+    }
+
+    pub fn to_local(&self, v: &Vec3) -> Vec3 {
+        Vec3::new(
+            dot(v, &self.tangent),
+            dot(v, &self.bitangent),
+            dot(v, &self.normal),
+        )
+    }
+
+    pub fn from_local(&self, v: &Vec3) -> Vec3 {
+        v.x * self.tangent + v.y * self.bitangent + v.z * self.normal
+    }
+}
+
+fn cos2_theta(w: &Vec3) -> f64 {
+    w.z * w.z
+}
+
+fn tan2_theta(w: &Vec3) -> f64 {
+    (1.0 - cos2_theta(w)).max(0.0) / cos2_theta(w)
+}
+
+/// cos² and sin² of the azimuth of `w` about the normal.
+fn cos2_sin2_phi(w: &Vec3) -> (f64, f64) {
+    let sin2_theta = w.x * w.x + w.y * w.y;
+    if sin2_theta == 0.0 {
+        return (1.0, 0.0);
+    }
+    let cos2 = (w.x * w.x / sin2_theta).clamp(0.0, 1.0);
+    (cos2, 1.0 - cos2)
+}
+
+/// The Trowbridge-Reitz (GGX) distribution of microfacet normals, with separate roughness along
+/// the tangent and bitangent for brushed, anisotropic surfaces.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrowbridgeReitz {
+    pub alpha_x: f64,
+    pub alpha_y: f64,
+}
+
+impl TrowbridgeReitz {
+    /// From perceptual roughness in [0, 1], which is squared to get alpha so that it changes the
+    /// look of the surface roughly evenly.
+    pub fn from_roughness(roughness_x: f64, roughness_y: f64) -> Self {
+        Self {
+            alpha_x: roughness_x * roughness_x,
+            alpha_y: roughness_y * roughness_y,
+        }
+    }
+
+    /// Too smooth to sample as a distribution without numerical trouble, so treated as a mirror.
+    pub fn effectively_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < 1e-3
+    }
+
+    /// Density of microfacet normals, per unit of projected area.
+    pub fn d(&self, wm: &Vec3) -> f64 {
+        let tan2 = tan2_theta(wm);
+        if !tan2.is_finite() {
+            return 0.0;
+        }
+        let cos4 = cos2_theta(wm) * cos2_theta(wm);
+        let (cos2_phi, sin2_phi) = cos2_sin2_phi(wm);
+        let e = tan2
+            * (cos2_phi / (self.alpha_x * self.alpha_x) + sin2_phi / (self.alpha_y * self.alpha_y));
+        1.0 / (PI * self.alpha_x * self.alpha_y * cos4 * (1.0 + e) * (1.0 + e))
+    }
+
+    /// Smith's auxiliary function, the area of microfacets hidden from `w` over the visible area.
+    fn lambda(&self, w: &Vec3) -> f64 {
+        let tan2 = tan2_theta(w);
+        if !tan2.is_finite() {
+            return 0.0;
+        }
+        let (cos2_phi, sin2_phi) = cos2_sin2_phi(w);
+        let alpha2 =
+            cos2_phi * self.alpha_x * self.alpha_x + sin2_phi * self.alpha_y * self.alpha_y;
+        ((1.0 + alpha2 * tan2).sqrt() - 1.0) / 2.0
+    }
+
+    /// The fraction of microfacets visible from `w`.
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// The fraction of microfacets visible from both `wo` and `wi` (height-correlated Smith).
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Density of the normals visible from `w`, which `sample_wm` draws from. Normals facing
+    /// away from `w` can't be seen, so have none.
+    pub fn visible_d(&self, w: &Vec3, wm: &Vec3) -> f64 {
+        self.g1(w) / w.z.abs() * self.d(wm) * dot(w, wm).max(0.0)
+    }
+
+    /// Sample a microfacet normal visible from `w`, which must be above the surface (Heitz,
+    /// "Sampling the GGX Distribution of Visible Normals", 2018).
+    pub fn sample_wm(&self, w: &Vec3, u: (f64, f64)) -> Vec3 {
+        // Stretch to the hemisphere configuration, where the distribution is a unit sphere.
+        let wh = unit_vector(&Vec3::new(self.alpha_x * w.x, self.alpha_y * w.y, w.z));
+        let t1 = if wh.z < 0.99999 {
+            unit_vector(&Vec3::new(0.0, 0.0, 1.0).cross(wh))
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = wh.cross(t1);
+        // A uniform point on the disk, squashed onto the part of it that's visible.
+        let r = u.0.sqrt();
+        let phi = 2.0 * PI * u.1;
+        let (px, py) = (r * phi.cos(), r * phi.sin());
+        let h = (1.0 - px * px).sqrt();
+        let s = (1.0 + wh.z) / 2.0;
+        let py = (1.0 - s) * h + s * py;
+        let pz = (1.0 - px * px - py * py).max(0.0).sqrt();
+        let nh = px * t1 + py * t2 + pz * wh;
+        // And unstretch back to the ellipsoid.
+        unit_vector(&Vec3::new(
+            self.alpha_x * nh.x,
+            self.alpha_y * nh.y,
+            nh.z.max(1e-6),
+        ))
+    }
+}
+
+/// Fresnel reflectance of a dielectric boundary, for light arriving at `cos_theta_i` (> 0) to the
+/// normal, where `eta` is the transmitted side's index over the incident side's.
+pub fn fresnel_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i).max(0.0) / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+    let parallel = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let perpendicular = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    (parallel * parallel + perpendicular * perpendicular) / 2.0
+}
+
+/// Fresnel reflectance of a conductor with complex index `eta + ik`, for unpolarised light
+/// arriving at `cos_theta_i` to the normal.
+pub fn fresnel_complex(cos_theta_i: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos_theta_i.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let a = ((a2_plus_b2 + t0) / 2.0).max(0.0).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let t2 = 2.0 * cos_theta_i * a;
+    let perpendicular = (t1 - t2) / (t1 + t2);
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let parallel = perpendicular * (t3 - t4) / (t3 + t4);
+    (parallel + perpendicular) / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    /// Integrate `f` over the hemisphere above the surface with the midpoint rule.
+    fn integrate_hemisphere(f: impl Fn(&Vec3) -> f64) -> f64 {
+        let (n_theta, n_phi) = (400, 400);
+        let (d_theta, d_phi) = (PI / 2.0 / n_theta as f64, 2.0 * PI / n_phi as f64);
+        let mut total = 0.0;
+        for i in 0..n_theta {
+            let theta = (i as f64 + 0.5) * d_theta;
+            for j in 0..n_phi {
+                let phi = (j as f64 + 0.5) * d_phi;
+                let w = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                );
+                total += f(&w) * theta.sin() * d_theta * d_phi;
+            }
+        }
+        total
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case(0.3, 0.3)]
+    #[case(0.6, 0.6)]
+    #[case(0.3, 0.7)]
+    fn test_distribution_is_normalised(#[case] roughness_x: f64, #[case] roughness_y: f64) {
+        let distribution = TrowbridgeReitz::from_roughness(roughness_x, roughness_y);
+        let projected = integrate_hemisphere(|wm| distribution.d(wm) * wm.z);
+        assert!((projected - 1.0).abs() < 0.01, "{projected}");
+        // Likewise the visible normals, from some direction.
+        let w = unit_vector(&Vec3::new(0.6, 0.2, 0.5));
+        let visible = integrate_hemisphere(|wm| distribution.visible_d(&w, wm));
+        assert!((visible - 1.0).abs() < 0.01, "{visible}");
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_visible_normal_sampling_matches_density() {
+        let distribution = TrowbridgeReitz::from_roughness(0.5, 0.8);
+        let w = unit_vector(&Vec3::new(-0.4, 0.5, 0.6));
+        let want = integrate_hemisphere(|wm| wm.z * distribution.visible_d(&w, wm));
+        let n = 200;
+        let mut total = 0.0;
+        for i in 0..n {
+            for j in 0..n {
+                let u = ((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64);
+                let wm = distribution.sample_wm(&w, u);
+                assert!(dot(&w, &wm) >= -1e-9);
+                total += wm.z;
+            }
+        }
+        let got = total / (n * n) as f64;
+        assert!((got - want).abs() < 1e-3, "{got} != {want}");
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_fresnel() {
+        // Glass reflects about 4% head on, and everything past the critical angle from inside.
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-9);
+        assert_eq!(fresnel_dielectric(0.1, 1.0 / 1.5), 1.0);
+        // A conductor with no absorption is a dielectric.
+        for cos in [0.2, 0.5, 0.9] {
+            assert!((fresnel_complex(cos, 1.5, 0.0) - fresnel_dielectric(cos, 1.5)).abs() < 1e-9);
+        }
+        // Metals reflect most light.
+        assert!(fresnel_complex(1.0, 0.2, 3.9) > 0.9);
+    }
+}
@@ -0,0 +1,234 @@
+//! Records of single camera paths, for working out why a pixel came out the way it did.
+use std::fmt::Write;
+
+use crate::hit::HitRecord;
+use crate::material::Scatter;
+use crate::ray::Ray;
+use crate::{Colour, Point3, Vec3};
+
+/// One step along a path: a surface the ray hit, or where it left the scene. On a spectral path,
+/// `throughput` and `emitted` hold values at the path's three sampled wavelengths, not RGB.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathVertex {
+    /// Direction of the ray arriving here.
+    pub direction: Vec3,
+    /// The product of the attenuations of every earlier vertex.
+    pub throughput: Colour,
+    /// Light given off here: by the surface, or the background seen by an escaping ray.
+    pub emitted: Colour,
+    /// What was hit, or `None` if the ray escaped to the background.
+    pub surface: Option<SurfaceVertex>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SurfaceVertex {
+    pub position: Point3,
+    pub normal: Vec3,
+    pub front_face: bool,
+    pub t: f64,
+    pub object_id: u32,
+    pub material_id: u32,
+    /// The material's `Debug` representation.
+    pub material: String,
+    /// `None` if the material absorbed the ray, ending the path.
+    pub scatter: Option<ScatterVertex>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScatterVertex {
+    pub direction: Vec3,
+    pub attenuation: Colour,
+    /// `None` for directions that weren't sampled from a density, as for a mirror.
+    pub pdf: Option<f64>,
+}
+
+impl PathVertex {
+    pub(crate) fn surface(
+        r: &Ray,
+        throughput: Colour,
+        rec: &HitRecord,
+        emitted: Colour,
+        scatter: Option<&Scatter>,
+    ) -> Self {
+        Self {
+            direction: r.direction,
+            throughput,
+            emitted,
+            surface: Some(SurfaceVertex {
+                position: rec.p,
+                normal: rec.normal,
+                front_face: rec.front_face,
+                t: rec.t,
+                object_id: rec.object_id,
+                material_id: rec.material_id,
+                material: format!("{:?}", rec.material),
+                scatter: scatter.map(|s| ScatterVertex {
+                    direction: s.ray.direction,
+                    attenuation: s.attenuation,
+                    pdf: s.pdf,
+                }),
+            }),
+        }
+    }
+
+    pub(crate) fn escaped(r: &Ray, throughput: Colour, background: Colour) -> Self {
+        Self {
+            direction: r.direction,
+            throughput,
+            emitted: background,
+            surface: None,
+        }
+    }
+}
+
+/// Everything about one camera sample: where it was taken, what it returned, and each vertex of
+/// its path. A path that ends without escaping or being absorbed hit the depth limit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathDump {
+    pub pixel: (usize, usize),
+    pub sample_index: usize,
+    pub seed: u64,
+    pub camera_ray: (Point3, Vec3),
+    pub colour: Colour,
+    pub vertices: Vec<PathVertex>,
+}
+
+impl PathDump {
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        let _ = self.write_json(&mut out);
+        out
+    }
+
+    fn write_json(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(out, "{{")?;
+        writeln!(out, "  \"pixel\": [{}, {}],", self.pixel.0, self.pixel.1)?;
+        writeln!(out, "  \"sample_index\": {},", self.sample_index)?;
+        writeln!(out, "  \"seed\": {},", self.seed)?;
+        writeln!(
+            out,
+            "  \"camera_ray\": {{\"origin\": {}, \"direction\": {}}},",
+            json_vec(&self.camera_ray.0),
+            json_vec(&self.camera_ray.1)
+        )?;
+        writeln!(out, "  \"colour\": {},", json_vec(&self.colour))?;
+        writeln!(out, "  \"vertices\": [")?;
+        for (index, vertex) in self.vertices.iter().enumerate() {
+            let separator = if index + 1 < self.vertices.len() {
+                ","
+            } else {
+                ""
+            };
+            writeln!(out, "    {}{separator}", vertex_json(index, vertex))?;
+        }
+        writeln!(out, "  ]")?;
+        write!(out, "}}")
+    }
+}
+
+fn vertex_json(index: usize, vertex: &PathVertex) -> String {
+    let mut fields = vec![
+        format!("\"index\": {index}"),
+        format!("\"direction\": {}", json_vec(&vertex.direction)),
+        format!("\"throughput\": {}", json_vec(&vertex.throughput)),
+        format!("\"emitted\": {}", json_vec(&vertex.emitted)),
+    ];
+    match &vertex.surface {
+        None => fields.push("\"event\": \"escaped\"".to_string()),
+        Some(surface) => {
+            let event = if surface.scatter.is_some() {
+                "scattered"
+            } else {
+                "absorbed"
+            };
+            fields.extend([
+                format!("\"event\": \"{event}\""),
+                format!("\"position\": {}", json_vec(&surface.position)),
+                format!("\"normal\": {}", json_vec(&surface.normal)),
+                format!("\"front_face\": {}", surface.front_face),
+                format!("\"t\": {}", json_number(surface.t)),
+                format!("\"object_id\": {}", surface.object_id),
+                format!("\"material_id\": {}", surface.material_id),
+                format!("\"material\": {}", json_string(&surface.material)),
+            ]);
+            if let Some(scatter) = &surface.scatter {
+                fields.extend([
+                    format!("\"scattered\": {}", json_vec(&scatter.direction)),
+                    format!("\"attenuation\": {}", json_vec(&scatter.attenuation)),
+                    format!(
+                        "\"pdf\": {}",
+                        scatter.pdf.map_or("null".to_string(), json_number)
+                    ),
+                ]);
+            }
+        }
+    }
+    format!("{{{}}}", fields.join(", "))
+}
+
+/// JSON has no infinities or NaNs, so those become null.
+fn json_number(value: f64) -> String {
+    if value.is_finite() {
+        format!("{value}")
+    } else {
+        "null".to_string()
+    }
+}
+
+fn json_vec(v: &Vec3) -> String {
+    format!(
+        "[{}, {}, {}]",
+        json_number(v.x),
+        json_number(v.y),
+        json_number(v.z)
+    )
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case(1.5, "1.5")]
+    #[case(-2.0, "-2")]
+    #[case(f64::INFINITY, "null")]
+    #[case(f64::NAN, "null")]
+    fn test_json_number(#[case] value: f64, #[case] want: &str) {
+        assert_eq!(json_number(value), want);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_json_string_escapes() {
+        assert_eq!(json_string("a \"b\" \\ c\n"), r#""a \"b\" \\ c\u000a""#);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_escaped_vertex_json() {
+        let r = Ray::new(Point3::default(), Vec3::new(0.0, 1.0, 0.0));
+        let vertex =
+            PathVertex::escaped(&r, Colour::new(0.5, 0.5, 0.5), Colour::new(1.0, 1.0, 1.0));
+        assert_eq!(
+            vertex_json(2, &vertex),
+            "{\"index\": 2, \"direction\": [0, 1, 0], \"throughput\": [0.5, 0.5, 0.5], \
+             \"emitted\": [1, 1, 1], \"event\": \"escaped\"}"
+        );
+    }
+}
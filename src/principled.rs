@@ -0,0 +1,455 @@
+//! A single material with artist-friendly parameters that covers most real surfaces, after
+//! Burley, "Physically-Based Shading at Disney", 2012.
+use std::sync::Arc;
+
+use crate::hit::HitRecord;
+use crate::material::{Evaluation, Material, RoughDielectric, Scatter};
+use crate::microfacet::{Frame, TrowbridgeReitz};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::spectrum::Ior;
+use crate::texture::{Scalar, SolidColour, Texture};
+use crate::{Colour, Vec3, dot, reflect, sample_cosine_hemisphere, unit_vector};
+
+/// A principled BSDF. It's a stack of lobes: an optional clear coat over a blend of metal and
+/// dielectric, where the dielectric is glass to the extent of `transmission` and otherwise a
+/// glossy specular layer over a diffuse base with sheen.
+///
+/// Each scatter picks one lobe with the probability that light reaches and is reflected by it,
+/// so the reported pdf is that of the chosen lobe times the chance of picking it.
+#[derive(Debug, Clone)]
+pub struct Principled {
+    pub base_colour: Arc<dyn Texture>,
+    /// 0 for a dielectric, 1 for a metal whose reflectance is the base colour.
+    pub metallic: Scalar,
+    pub roughness: Scalar,
+    /// Strength of the dielectric's specular reflection; 0.5 is physically right for `ior`.
+    pub specular: Scalar,
+    /// How much the dielectric's specular reflection takes on the base colour's hue.
+    pub specular_tint: Scalar,
+    /// Soft retroreflection at grazing angles, as on cloth, in the base colour's hue.
+    pub sheen: Scalar,
+    /// Strength of a clear glossy coat over everything else.
+    pub clearcoat: Scalar,
+    pub clearcoat_roughness: Scalar,
+    /// How much of the dielectric is transparent glass, tinted by the base colour.
+    pub transmission: Scalar,
+    pub ior: Scalar,
+}
+
+impl Default for Principled {
+    fn default() -> Self {
+        Self {
+            base_colour: Arc::new(SolidColour::new(Colour::new(0.8, 0.8, 0.8))),
+            metallic: 0.0.into(),
+            roughness: 0.5.into(),
+            specular: 0.5.into(),
+            specular_tint: 0.0.into(),
+            sheen: 0.0.into(),
+            clearcoat: 0.0.into(),
+            clearcoat_roughness: 0.1.into(),
+            transmission: 0.0.into(),
+            ior: 1.5.into(),
+        }
+    }
+}
+
+/// Schlick's approximation to Fresnel reflectance, from the reflectance head on.
+fn schlick(f0: Colour, cos_theta: f64) -> Colour {
+    let weight = (1.0 - cos_theta).clamp(0.0, 1.0).powi(5);
+    f0 + (Colour::new(1.0, 1.0, 1.0) - f0) * weight
+}
+
+/// The hue of `colour` at unit luminance.
+fn tint(colour: Colour) -> Colour {
+    let luminance = colour.luminance();
+    if luminance > 0.0 {
+        colour / luminance
+    } else {
+        Colour::new(1.0, 1.0, 1.0)
+    }
+}
+
+fn lerp(a: Colour, b: Colour, t: f64) -> Colour {
+    (1.0 - t) * a + t * b
+}
+
+/// Sample a microfacet reflection of `wo`, returning the local direction, the microfacet normal
+/// and the density, or `None` for a mirror-smooth distribution (where the pdf is undefined).
+fn sample_reflection(
+    distribution: &TrowbridgeReitz,
+    wo: &Vec3,
+    u: (f64, f64),
+) -> (Vec3, Vec3, Option<f64>) {
+    if distribution.effectively_smooth() {
+        let wm = Vec3::new(0.0, 0.0, 1.0);
+        return (reflect(&-*wo, &wm), wm, None);
+    }
+    let wm = distribution.sample_wm(wo, u);
+    let pdf = distribution.visible_d(wo, &wm) / (4.0 * dot(wo, &wm).abs());
+    (reflect(&-*wo, &wm), wm, Some(pdf))
+}
+
+/// Walks down the stack of lobes with one uniform sample, picking each with the chance light
+/// gets to it and is reflected, which takes care of weighting the lobes.
+struct LobePicker {
+    u: f64,
+    /// The chance of having made every choice so far.
+    probability: f64,
+}
+
+impl LobePicker {
+    fn pick(&mut self, chance: f64) -> bool {
+        if chance > 0.0 && self.u < chance {
+            self.u /= chance;
+            self.probability *= chance;
+            true
+        } else {
+            self.u = (self.u - chance) / (1.0 - chance);
+            self.probability *= 1.0 - chance;
+            false
+        }
+    }
+}
+
+/// The BSDF times the cosine of a microfacet reflection from `wo` into `wi`, and the density
+/// of sampling it, or nothing for a mirror-smooth distribution, which no given direction hits.
+fn eval_reflection(
+    distribution: &TrowbridgeReitz,
+    fresnel: impl Fn(f64) -> Colour,
+    wo: &Vec3,
+    wi: &Vec3,
+) -> (Colour, f64) {
+    if distribution.effectively_smooth() {
+        return (Colour::default(), 0.0);
+    }
+    let wm = unit_vector(&(*wo + *wi));
+    let cos_o_m = dot(wo, &wm);
+    let f = fresnel(cos_o_m) * distribution.d(&wm) * distribution.g(wo, wi) / (4.0 * wo.z);
+    (f, distribution.visible_d(wo, &wm) / (4.0 * cos_o_m.abs()))
+}
+
+impl Principled {
+    /// A reflection off a lobe with `distribution`, attenuated by `fresnel` of the angle to the
+    /// microfacet and Smith shadowing, picked with probability `lobe_probability`.
+    #[allow(clippy::too_many_arguments)]
+    fn reflect_lobe(
+        rec: &HitRecord,
+        frame: &Frame,
+        wo: &Vec3,
+        distribution: &TrowbridgeReitz,
+        fresnel: impl Fn(f64) -> Colour,
+        lobe_probability: f64,
+        u: (f64, f64),
+    ) -> Option<Scatter> {
+        let (wi, wm, pdf) = sample_reflection(distribution, wo, u);
+        if wi.z <= 0.0 {
+            return None;
+        }
+        let shadowing = if pdf.is_some() {
+            distribution.g(wo, &wi) / distribution.g1(wo)
+        } else {
+            1.0
+        };
+        Some(Scatter {
+            attenuation: fresnel(dot(wo, &wm)) * shadowing,
+            ray: Ray::new(rec.p, frame.from_local(&wi)),
+            pdf: pdf.map(|pdf| pdf * lobe_probability),
+            dispersive: false,
+        })
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        let base_colour = self.base_colour.at(rec);
+        let metallic = self.metallic.at(rec).clamp(0.0, 1.0);
+        let roughness = self.roughness.at(rec).clamp(0.0, 1.0);
+        let clearcoat = self.clearcoat.at(rec).clamp(0.0, 1.0);
+        let transmission = self.transmission.at(rec).clamp(0.0, 1.0);
+        let ior = self.ior.at(rec);
+        let distribution = TrowbridgeReitz::from_roughness(roughness, roughness);
+
+        let frame = Frame::from_normal(&rec.normal);
+        let wo = frame.to_local(&-unit_vector(&r_in.direction));
+        let mut lobes = LobePicker {
+            u: sampler.get_1d(),
+            probability: 1.0,
+        };
+        let u = sampler.get_2d();
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let clearcoat_reflectance = clearcoat * schlick(Colour::new(0.04, 0.04, 0.04), wo.z).x;
+        if lobes.pick(clearcoat_reflectance) {
+            let roughness = self.clearcoat_roughness.at(rec).clamp(0.0, 1.0);
+            let coat = TrowbridgeReitz::from_roughness(roughness, roughness);
+            let white = |_| Colour::new(1.0, 1.0, 1.0);
+            return Self::reflect_lobe(rec, &frame, &wo, &coat, white, lobes.probability, u);
+        }
+        if lobes.pick(metallic) {
+            let fresnel = |cos| schlick(base_colour, cos);
+            return Self::reflect_lobe(
+                rec,
+                &frame,
+                &wo,
+                &distribution,
+                fresnel,
+                lobes.probability,
+                u,
+            );
+        }
+        if lobes.pick(transmission) {
+            // Glass reflects and refracts by its own Fresnel; only light passing into it is
+            // tinted, not light leaving through the back face.
+            let glass = RoughDielectric::new(Ior::Constant(ior), distribution);
+            let mut scatter = glass.scatter(r_in, rec, sampler)?;
+            if rec.front_face && dot(&scatter.ray.direction, &rec.normal) < 0.0 {
+                scatter.attenuation *= base_colour;
+            }
+            scatter.pdf = scatter.pdf.map(|pdf| pdf * lobes.probability);
+            return Some(scatter);
+        }
+        // Specular 0.5 gives the reflectance head on of a dielectric of index `ior`.
+        let f0 = ((ior - 1.0) / (ior + 1.0)).powi(2) * 2.0 * self.specular.at(rec).max(0.0);
+        let specular_colour = lerp(
+            Colour::new(1.0, 1.0, 1.0),
+            tint(base_colour),
+            self.specular_tint.at(rec).clamp(0.0, 1.0),
+        );
+        let specular_reflectance = schlick(Colour::new(f0, f0, f0), wo.z).x.min(1.0);
+        if lobes.pick(specular_reflectance) {
+            let fresnel = |_| specular_colour;
+            return Self::reflect_lobe(
+                rec,
+                &frame,
+                &wo,
+                &distribution,
+                fresnel,
+                lobes.probability,
+                u,
+            );
+        }
+
+        let wi = sample_cosine_hemisphere(&Vec3::new(0.0, 0.0, 1.0), u);
+        let cos_d = dot(&wi, &unit_vector(&(wi + wo)));
+        // The sheen lobe isn't divided by pi like the Lambertian one, so gains it back over the
+        // cosine-weighted pdf.
+        let sheen = self.sheen.at(rec).max(0.0) * std::f64::consts::PI * (1.0 - cos_d).powi(5);
+        Some(Scatter {
+            attenuation: base_colour + sheen * tint(base_colour),
+            ray: Ray::new(rec.p, frame.from_local(&wi)),
+            pdf: Some(wi.z / std::f64::consts::PI * lobes.probability),
+            dispersive: false,
+        })
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Colour {
+        self.base_colour.at(rec)
+    }
+
+    /// The sum over the lobes `scatter` picks between, each weighted by the chance of picking
+    /// it. Glass is left out, so materials with any transmission can't be evaluated.
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Option<Evaluation> {
+        if self.transmission.at(rec) > 0.0 {
+            return None;
+        }
+        let base_colour = self.base_colour.at(rec);
+        let metallic = self.metallic.at(rec).clamp(0.0, 1.0);
+        let roughness = self.roughness.at(rec).clamp(0.0, 1.0);
+        let clearcoat = self.clearcoat.at(rec).clamp(0.0, 1.0);
+        let ior = self.ior.at(rec);
+        let distribution = TrowbridgeReitz::from_roughness(roughness, roughness);
+
+        let frame = Frame::from_normal(&rec.normal);
+        let wo = frame.to_local(&-unit_vector(&r_in.direction));
+        let wi = frame.to_local(&unit_vector(direction));
+        let mut evaluation = Evaluation {
+            f: Colour::default(),
+            pdf: 0.0,
+        };
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Some(evaluation);
+        }
+        let mut add = |probability: f64, (f, pdf): (Colour, f64)| {
+            evaluation.f += probability * f;
+            evaluation.pdf += probability * pdf;
+        };
+
+        let clearcoat_reflectance = clearcoat * schlick(Colour::new(0.04, 0.04, 0.04), wo.z).x;
+        let roughness = self.clearcoat_roughness.at(rec).clamp(0.0, 1.0);
+        let coat = TrowbridgeReitz::from_roughness(roughness, roughness);
+        let white = |_| Colour::new(1.0, 1.0, 1.0);
+        add(
+            clearcoat_reflectance,
+            eval_reflection(&coat, white, &wo, &wi),
+        );
+        let mut remaining = 1.0 - clearcoat_reflectance;
+
+        let fresnel = |cos| schlick(base_colour, cos);
+        add(
+            remaining * metallic,
+            eval_reflection(&distribution, fresnel, &wo, &wi),
+        );
+        remaining *= 1.0 - metallic;
+
+        let f0 = ((ior - 1.0) / (ior + 1.0)).powi(2) * 2.0 * self.specular.at(rec).max(0.0);
+        let specular_colour = lerp(
+            Colour::new(1.0, 1.0, 1.0),
+            tint(base_colour),
+            self.specular_tint.at(rec).clamp(0.0, 1.0),
+        );
+        let specular_reflectance = schlick(Colour::new(f0, f0, f0), wo.z).x.min(1.0);
+        let fresnel = |_| specular_colour;
+        add(
+            remaining * specular_reflectance,
+            eval_reflection(&distribution, fresnel, &wo, &wi),
+        );
+        remaining *= 1.0 - specular_reflectance;
+
+        let cos_d = dot(&wi, &unit_vector(&(wi + wo)));
+        let sheen = self.sheen.at(rec).max(0.0) * std::f64::consts::PI * (1.0 - cos_d).powi(5);
+        let pdf = wi.z / std::f64::consts::PI;
+        add(
+            remaining,
+            ((base_colour + sheen * tint(base_colour)) * pdf, pdf),
+        );
+        Some(evaluation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Point3;
+    use crate::material;
+    use crate::material::tests::hit_on_plane;
+    use rstest::rstest;
+
+    /// Mean attenuation of rays arriving at 45°, and the fraction that went into the surface.
+    fn mean_scatter(material: &Principled) -> (Colour, f64) {
+        material::tests::mean_scatter(material, &hit_on_plane(), |scatter| {
+            if scatter.ray.direction.y < 0.0 {
+                1.0
+            } else {
+                0.0
+            }
+        })
+    }
+
+    fn solid(r: f64, g: f64, b: f64) -> Arc<dyn Texture> {
+        Arc::new(SolidColour::new(Colour::new(r, g, b)))
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_white_diffuse_conserves_energy() {
+        let material = Principled {
+            base_colour: solid(1.0, 1.0, 1.0),
+            specular: 0.0.into(),
+            ..Default::default()
+        };
+        // Schlick's approximation still reflects a little at grazing angles, where some of the
+        // glossy reflection goes below the horizon.
+        let (attenuation, transmitted) = mean_scatter(&material);
+        assert!(
+            attenuation.x <= 1.0 && attenuation.x > 0.999,
+            "{attenuation:?}"
+        );
+        assert_eq!(transmitted, 0.0);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_plastic_reflects_at_most_everything() {
+        let material = Principled {
+            base_colour: solid(1.0, 1.0, 1.0),
+            clearcoat: 1.0.into(),
+            ..Default::default()
+        };
+        let (attenuation, _) = mean_scatter(&material);
+        assert!(
+            attenuation.x <= 1.0 && attenuation.x > 0.95,
+            "{attenuation:?}"
+        );
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_metal_takes_base_colour() {
+        let material = Principled {
+            base_colour: solid(0.9, 0.6, 0.2),
+            metallic: 1.0.into(),
+            roughness: 0.2.into(),
+            ..Default::default()
+        };
+        let (attenuation, _) = mean_scatter(&material);
+        assert!(attenuation.x > attenuation.y && attenuation.y > attenuation.z);
+        assert!(attenuation.z > 0.2, "{attenuation:?}");
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_transmission_goes_into_surface() {
+        let material = Principled {
+            transmission: 1.0.into(),
+            roughness: 0.1.into(),
+            ..Default::default()
+        };
+        let (_, transmitted) = mean_scatter(&material);
+        assert!(transmitted > 0.9, "{transmitted}");
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case(true, 0.0)]
+    #[case(false, 1.0)]
+    fn test_transmission_tints_light_going_in(#[case] front_face: bool, #[case] green: f64) {
+        let material = Principled {
+            base_colour: solid(1.0, 0.0, 0.0),
+            transmission: 1.0.into(),
+            roughness: 0.0.into(),
+            // Low enough that light leaving at 45° isn't all reflected back in.
+            ior: 1.1.into(),
+            ..Default::default()
+        };
+        let rec = HitRecord {
+            front_face,
+            ..hit_on_plane()
+        };
+        let (_, transmitted) = material::tests::mean_scatter(&material, &rec, |scatter| {
+            if scatter.ray.direction.y < 0.0 {
+                scatter.attenuation
+            } else {
+                Colour::default()
+            }
+        });
+        assert!(transmitted.x > 0.5, "{transmitted:?}");
+        assert_eq!(transmitted.y / transmitted.x, green);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_parameters_follow_textures() {
+        #[derive(Debug)]
+        struct LeftRight;
+        impl Texture for LeftRight {
+            fn value(&self, u: f64, _v: f64, _p: &Point3) -> Colour {
+                if u < 0.5 {
+                    Colour::default()
+                } else {
+                    Colour::new(1.0, 1.0, 1.0)
+                }
+            }
+        }
+        let material = Principled {
+            base_colour: Arc::new(LeftRight),
+            ..Default::default()
+        };
+        let mut rec = hit_on_plane();
+        assert_eq!(material.albedo(&rec), Colour::default());
+        rec.u = 0.75;
+        assert_eq!(material.albedo(&rec), Colour::new(1.0, 1.0, 1.0));
+    }
+}
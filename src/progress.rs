@@ -0,0 +1,169 @@
+use std::fmt::Debug;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+/// A snapshot of how far a render has got.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ProgressUpdate {
+    /// Tiles rendered so far, counting each tile once per pass.
+    pub tiles_done: usize,
+    pub tiles_total: usize,
+    /// The pass being rendered, counting from 1.
+    pub pass: usize,
+    pub passes: usize,
+    /// Camera samples taken so far.
+    pub samples: u64,
+    /// Rays traced so far, camera rays and every bounce after them.
+    pub rays: u64,
+    pub elapsed: Duration,
+    /// Estimated time left, once there's something to estimate from.
+    pub eta: Option<Duration>,
+}
+
+impl ProgressUpdate {
+    pub(crate) fn new(tiles_total: usize, passes: usize) -> Self {
+        Self {
+            tiles_total,
+            passes,
+            ..Self::default()
+        }
+    }
+
+    /// Record a finished tile, updating the timings relative to `start`.
+    pub(crate) fn tile_done(&mut self, start: Instant, samples: u64, rays: u64) {
+        self.tiles_done += 1;
+        self.samples += samples;
+        self.rays += rays;
+        self.elapsed = start.elapsed();
+        let remaining = self.tiles_total.saturating_sub(self.tiles_done);
+        self.eta = Some(
+            self.elapsed
+                .mul_f64(remaining as f64 / self.tiles_done as f64),
+        );
+    }
+}
+
+/// Receives progress updates while rendering, from the thread that called `render`.
+pub trait ProgressObserver: Send + Sync + Debug {
+    fn update(&self, progress: &ProgressUpdate);
+
+    /// Called once the render is over, whether or not every tile was rendered.
+    fn finish(&self, _progress: &ProgressUpdate) {}
+}
+
+/// Ignores progress entirely; the default, for headless use.
+#[derive(Debug, Default)]
+pub struct NoProgress;
+
+impl ProgressObserver for NoProgress {
+    fn update(&self, _progress: &ProgressUpdate) {}
+}
+
+/// Draws an indicatif progress bar on the terminal.
+#[derive(Debug)]
+pub struct IndicatifProgress {
+    bar: ProgressBar,
+}
+
+impl Default for IndicatifProgress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IndicatifProgress {
+    pub fn new() -> Self {
+        let style = ProgressStyle::with_template(
+            "[Elapsed: {elapsed_precise}| ETA: {eta_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
+        )
+        .expect("progress bar template is valid");
+        Self {
+            bar: ProgressBar::new(0).with_style(style),
+        }
+    }
+}
+
+impl ProgressObserver for IndicatifProgress {
+    fn update(&self, progress: &ProgressUpdate) {
+        self.bar.set_length(progress.tiles_total as u64);
+        self.bar.set_position(progress.tiles_done as u64);
+        self.bar
+            .set_message(format!("pass {}/{}", progress.pass, progress.passes));
+    }
+
+    fn finish(&self, _progress: &ProgressUpdate) {
+        self.bar.finish();
+    }
+}
+
+/// Writes a log line at most once every `interval`, e.g. for services where the log is the only
+/// way to see what's going on.
+#[derive(Debug)]
+pub struct LogProgress {
+    interval: Duration,
+    last: Mutex<Option<Instant>>,
+}
+
+impl LogProgress {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last: Mutex::new(None),
+        }
+    }
+
+    fn log(progress: &ProgressUpdate) {
+        let eta = progress
+            .eta
+            .map_or(String::from("unknown"), |eta| format!("{:.0?}", eta));
+        log::info!(
+            "Rendered {}/{} tiles (pass {}/{}), {} samples, {} rays, elapsed {:.0?}, ETA {eta}",
+            progress.tiles_done,
+            progress.tiles_total,
+            progress.pass,
+            progress.passes,
+            progress.samples,
+            progress.rays,
+            progress.elapsed,
+        );
+    }
+}
+
+impl ProgressObserver for LogProgress {
+    fn update(&self, progress: &ProgressUpdate) {
+        let mut last = self.last.lock().unwrap();
+        if last.is_none_or(|last| last.elapsed() >= self.interval) {
+            *last = Some(Instant::now());
+            LogProgress::log(progress);
+        }
+    }
+
+    fn finish(&self, progress: &ProgressUpdate) {
+        LogProgress::log(progress);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_tile_done_estimates_time_left() {
+        let start = Instant::now() - Duration::from_secs(10);
+        let mut progress = ProgressUpdate::new(4, 1);
+        assert_eq!(progress.eta, None);
+
+        progress.tile_done(start, 16, 40);
+        progress.tile_done(start, 16, 35);
+        assert_eq!(progress.tiles_done, 2);
+        assert_eq!(progress.samples, 32);
+        assert_eq!(progress.rays, 75);
+        // Half way through after ~10s, so about another 10s to go.
+        let eta = progress.eta.unwrap().as_secs_f64();
+        assert!((9.5..11.0).contains(&eta), "eta {eta}");
+    }
+}
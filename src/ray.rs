@@ -1,14 +1,27 @@
+use crate::spectrum::SampledWavelengths;
 use crate::{Point3, Vec3};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct Ray {
     pub origin: Point3,
     pub direction: Vec3,
+    /// The wavelengths a spectral path carries, so materials can tell which light they're
+    /// scattering. `None` when rendering in RGB.
+    pub wavelengths: Option<SampledWavelengths>,
 }
 
 impl Ray {
     pub fn new(origin: Point3, direction: Vec3) -> Self {
-        Self { origin, direction }
+        Self {
+            origin,
+            direction,
+            wavelengths: None,
+        }
+    }
+
+    pub fn with_wavelengths(mut self, wavelengths: Option<SampledWavelengths>) -> Self {
+        self.wavelengths = wavelengths;
+        self
     }
 
     pub fn at(&self, t: f64) -> Point3 {
@@ -0,0 +1,722 @@
+use std::sync::Arc;
+
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+/// Generates the sample values consumed while tracing a single camera sample.
+///
+/// Samplers are deterministic: for a given seed, pixel and sample index they always produce the
+/// same values, in the same order. Each call to `get_1d`/`get_2d` consumes the next dimension, so
+/// the camera and integrator must request their dimensions in a consistent order (pixel offset
+/// first, then the scattering samples for each bounce).
+pub trait Sampler: Send {
+    /// Prepare to generate the `sample_index`th sample for pixel (i, j).
+    fn start_pixel_sample(&mut self, i: usize, j: usize, sample_index: usize);
+
+    /// The next single dimension, in [0, 1).
+    fn get_1d(&mut self) -> f64;
+
+    /// The next pair of dimensions, each in [0, 1).
+    fn get_2d(&mut self) -> (f64, f64);
+}
+
+/// Selects which `Sampler` implementation the camera uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SamplerKind {
+    /// Independent uniform random samples; the original behaviour.
+    Independent,
+    /// Jittered stratification, with strata shuffled between dimensions.
+    Stratified,
+    /// Halton sequence with random digit permutations per pixel.
+    Halton,
+    /// Sobol (0,2) sequence with Owen scrambling, padded across dimensions.
+    Sobol,
+    /// Progressive multi-jittered sequences.
+    Pmj,
+}
+
+impl SamplerKind {
+    pub fn build(&self, samples_per_pixel: usize, seed: u64) -> Box<dyn Sampler> {
+        self.setup(samples_per_pixel, seed).build()
+    }
+
+    /// Prepare to build many samplers of this kind, e.g. one per thread per pass of a render.
+    pub fn setup(&self, samples_per_pixel: usize, seed: u64) -> SamplerSetup {
+        let pmj_sets =
+            (*self == SamplerKind::Pmj).then(|| generate_pmj_sets(samples_per_pixel, seed));
+        SamplerSetup {
+            kind: *self,
+            samples_per_pixel,
+            seed,
+            pmj_sets,
+        }
+    }
+}
+
+/// Builds samplers of one kind, sample count and seed. Anything that's slow to set up, like the
+/// PMJ sequences, is made once here and shared by every sampler built.
+#[derive(Debug, Clone)]
+pub struct SamplerSetup {
+    kind: SamplerKind,
+    samples_per_pixel: usize,
+    seed: u64,
+    pmj_sets: Option<PmjSets>,
+}
+
+impl SamplerSetup {
+    pub fn build(&self) -> Box<dyn Sampler> {
+        let (samples_per_pixel, seed) = (self.samples_per_pixel, self.seed);
+        match (self.kind, &self.pmj_sets) {
+            (SamplerKind::Independent, _) => Box::new(IndependentSampler::new(seed)),
+            (SamplerKind::Stratified, _) => {
+                Box::new(StratifiedSampler::new(samples_per_pixel, seed))
+            }
+            (SamplerKind::Halton, _) => Box::new(HaltonSampler::new(seed)),
+            (SamplerKind::Sobol, _) => Box::new(SobolSampler::new(samples_per_pixel, seed)),
+            (SamplerKind::Pmj, Some(sets)) => Box::new(PmjSampler::with_sets(sets.clone(), seed)),
+            (SamplerKind::Pmj, None) => Box::new(PmjSampler::new(samples_per_pixel, seed)),
+        }
+    }
+}
+
+impl std::str::FromStr for SamplerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "independent" => Ok(SamplerKind::Independent),
+            "stratified" => Ok(SamplerKind::Stratified),
+            "halton" => Ok(SamplerKind::Halton),
+            "sobol" => Ok(SamplerKind::Sobol),
+            "pmj" => Ok(SamplerKind::Pmj),
+            _ => Err(format!("unknown sampler: {s}")),
+        }
+    }
+}
+
+/// Largest f64 below 1.0, used to keep sample values inside [0, 1).
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+#[inline]
+fn mix_bits(mut v: u64) -> u64 {
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5_d329_728e_a185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81da_def4_bc2d_d44d);
+    v ^= v >> 33;
+    v
+}
+
+/// Hashes a handful of integers down to a well mixed 64 bit value.
+#[inline]
+pub(crate) fn hash(values: &[u64]) -> u64 {
+    values
+        .iter()
+        .fold(0x9e37_79b9_7f4a_7c15, |h, &v| mix_bits(h ^ mix_bits(v)))
+}
+
+/// Returns the `index`th element of a random permutation of 0..len, chosen by `seed`.
+/// (Kensler, "Correlated Multi-Jittered Sampling")
+fn permutation_element(mut index: u32, len: u32, seed: u32) -> u32 {
+    let mut w = len.wrapping_sub(1);
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        index ^= seed;
+        index = index.wrapping_mul(0xe170_893d);
+        index ^= seed >> 16;
+        index ^= (index & w) >> 4;
+        index ^= seed >> 8;
+        index = index.wrapping_mul(0x0929_eb3f);
+        index ^= seed >> 23;
+        index ^= (index & w) >> 1;
+        index = index.wrapping_mul(1 | seed >> 27);
+        index = index.wrapping_mul(0x6935_fa69);
+        index ^= (index & w) >> 11;
+        index = index.wrapping_mul(0x74dc_b303);
+        index ^= (index & w) >> 2;
+        index = index.wrapping_mul(0x9e50_1cc3);
+        index ^= (index & w) >> 2;
+        index = index.wrapping_mul(0xc860_a3df);
+        index &= w;
+        index ^= index >> 5;
+        if index < len {
+            break;
+        }
+    }
+    (index.wrapping_add(seed)) % len
+}
+
+/// Nested uniform (Owen) scrambling of a 32 bit fixed point value.
+/// (Burley, "Practical Hash-based Owen Scrambling")
+#[inline]
+fn owen_scramble(v: u32, seed: u32) -> u32 {
+    let mut v = v.reverse_bits();
+    v ^= v.wrapping_mul(0x3d20_adea);
+    v = v.wrapping_add(seed);
+    v = v.wrapping_mul((seed >> 16) | 1);
+    v ^= v.wrapping_mul(0x0552_6c56);
+    v ^= v.wrapping_mul(0x53a2_2864);
+    v.reverse_bits()
+}
+
+#[inline]
+fn u32_to_unit(v: u32) -> f64 {
+    (v as f64 * (1.0 / 4_294_967_296.0)).min(ONE_MINUS_EPSILON)
+}
+
+/// Uniform random samples, seeded per pixel sample so renders are repeatable.
+pub struct IndependentSampler {
+    seed: u64,
+    rng: SmallRng,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: SmallRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, i: usize, j: usize, sample_index: usize) {
+        self.rng =
+            SmallRng::seed_from_u64(hash(&[i as u64, j as u64, sample_index as u64, self.seed]));
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.rng.random_range(0.0..1.0)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+/// Jittered stratified samples. Every dimension is stratified on its own, and the strata are
+/// shuffled per pixel and per dimension so that dimensions are not correlated with each other.
+pub struct StratifiedSampler {
+    x_strata: usize,
+    y_strata: usize,
+    seed: u64,
+    pixel: (usize, usize),
+    sample_index: usize,
+    dimension: u64,
+    rng: SmallRng,
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: usize, seed: u64) -> Self {
+        // Pick the squarest grid that holds every sample; any strata left over are never used.
+        let samples_per_pixel = samples_per_pixel.max(1);
+        let x_strata = (samples_per_pixel as f64).sqrt() as usize;
+        let y_strata = samples_per_pixel.div_ceil(x_strata);
+        Self {
+            x_strata,
+            y_strata,
+            seed,
+            pixel: (0, 0),
+            sample_index: 0,
+            dimension: 0,
+            rng: SmallRng::seed_from_u64(seed),
+        }
+    }
+
+    fn stratum(&mut self, count: usize) -> usize {
+        let (i, j) = self.pixel;
+        let h = hash(&[i as u64, j as u64, self.dimension, self.seed]);
+        self.dimension += 1;
+        permutation_element((self.sample_index % count) as u32, count as u32, h as u32) as usize
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, i: usize, j: usize, sample_index: usize) {
+        self.pixel = (i, j);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+        self.rng =
+            SmallRng::seed_from_u64(hash(&[i as u64, j as u64, sample_index as u64, self.seed]));
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let count = self.x_strata * self.y_strata;
+        let stratum = self.stratum(count);
+        let jitter: f64 = self.rng.random_range(0.0..1.0);
+        ((stratum as f64 + jitter) / count as f64).min(ONE_MINUS_EPSILON)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let stratum = self.stratum(self.x_strata * self.y_strata);
+        let (x, y) = (stratum % self.x_strata, stratum / self.x_strata);
+        let dx: f64 = self.rng.random_range(0.0..1.0);
+        let dy: f64 = self.rng.random_range(0.0..1.0);
+        (
+            ((x as f64 + dx) / self.x_strata as f64).min(ONE_MINUS_EPSILON),
+            ((y as f64 + dy) / self.y_strata as f64).min(ONE_MINUS_EPSILON),
+        )
+    }
+}
+
+const PRIMES: [u64; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+/// Radical inverse of `index` in the given base, with each digit passed through a random
+/// permutation chosen by `seed`. A zero seed gives the plain radical inverse.
+fn scrambled_radical_inverse(base: u64, mut index: u64, seed: u64) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv_base_m = 1.0;
+    let mut reversed = 0.0;
+    let mut digit_index = 0u64;
+    // Keep going while a scrambled trailing zero digit still changes the result.
+    while 1.0 - inv_base_m < 1.0 {
+        let digit = index % base;
+        let scrambled = if seed == 0 {
+            digit
+        } else {
+            let digit_seed = hash(&[base, digit_index, seed]) as u32;
+            permutation_element(digit as u32, base as u32, digit_seed) as u64
+        };
+        reversed += scrambled as f64 * inv_base_m * inv_base;
+        inv_base_m *= inv_base;
+        index /= base;
+        digit_index += 1;
+        if seed == 0 && index == 0 {
+            break;
+        }
+    }
+    reversed.min(ONE_MINUS_EPSILON)
+}
+
+/// The Halton sequence, one prime base per dimension. Each pixel gets its own digit scrambling,
+/// so neighbouring pixels don't share the same sample pattern.
+pub struct HaltonSampler {
+    seed: u64,
+    pixel_seed: u64,
+    sample_index: u64,
+    dimension: usize,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel_seed: 0,
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn next_dimension(&mut self) -> f64 {
+        // Past the prime table we cycle the bases, but with a fresh scramble for every cycle.
+        let base = PRIMES[self.dimension % PRIMES.len()];
+        let seed = hash(&[self.pixel_seed, (self.dimension / PRIMES.len()) as u64]) | 1;
+        self.dimension += 1;
+        scrambled_radical_inverse(base, self.sample_index, seed)
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, i: usize, j: usize, sample_index: usize) {
+        self.pixel_seed = hash(&[i as u64, j as u64, self.seed]);
+        self.sample_index = sample_index as u64;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.next_dimension()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.next_dimension(), self.next_dimension())
+    }
+}
+
+/// First two dimensions of the Sobol sequence as 32 bit fixed point values.
+fn sobol_2d(index: u32) -> (u32, u32) {
+    // Dimension 0 is the van der Corput sequence; dimension 1 uses the direction numbers
+    // for the primitive polynomial x + 1.
+    let mut x = 0u32;
+    let mut y = 0u32;
+    let mut v = 1u32 << 31;
+    let mut i = index;
+    let mut bit = 0;
+    while i != 0 {
+        if i & 1 == 1 {
+            x ^= 1 << (31 - bit);
+            y ^= v;
+        }
+        v ^= v >> 1;
+        i >>= 1;
+        bit += 1;
+    }
+    (x, y)
+}
+
+/// Owen scrambled Sobol samples. Every dimension (or pair of dimensions) reuses the first two
+/// Sobol dimensions with an independent scramble and sample index shuffle, which keeps the (0,2)
+/// stratification of each pair without needing direction numbers for higher dimensions.
+pub struct SobolSampler {
+    samples_per_pixel: u32,
+    seed: u64,
+    pixel: (usize, usize),
+    sample_index: u32,
+    dimension: u64,
+}
+
+impl SobolSampler {
+    pub fn new(samples_per_pixel: usize, seed: u64) -> Self {
+        Self {
+            samples_per_pixel: samples_per_pixel.max(1).next_power_of_two() as u32,
+            seed,
+            pixel: (0, 0),
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn next_hash(&mut self) -> u64 {
+        let (i, j) = self.pixel;
+        let h = hash(&[i as u64, j as u64, self.dimension, self.seed]);
+        self.dimension += 1;
+        h
+    }
+
+    fn shuffled_index(&self, h: u64) -> u32 {
+        // Only the first samples_per_pixel samples are shuffled, later ones (e.g. progressive
+        // passes beyond the nominal count) carry on through the sequence.
+        let block = self.sample_index / self.samples_per_pixel;
+        let within = self.sample_index % self.samples_per_pixel;
+        block * self.samples_per_pixel
+            + permutation_element(within, self.samples_per_pixel, (h >> 32) as u32)
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, i: usize, j: usize, sample_index: usize) {
+        self.pixel = (i, j);
+        self.sample_index = sample_index as u32;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let h = self.next_hash();
+        let (x, _) = sobol_2d(self.shuffled_index(h));
+        u32_to_unit(owen_scramble(x, h as u32))
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let h = self.next_hash();
+        let (x, y) = sobol_2d(self.shuffled_index(h));
+        let seeds = mix_bits(h);
+        (
+            u32_to_unit(owen_scramble(x, seeds as u32)),
+            u32_to_unit(owen_scramble(y, (seeds >> 32) as u32)),
+        )
+    }
+}
+
+/// Number of independent PMJ sequences generated; pixels and dimensions pick between them.
+const PMJ_SETS: usize = 32;
+
+/// Generates `count` (rounded up to a power of four) progressive multi-jittered points.
+/// (Christensen, Kensler & Kilpatrick, "Progressive Multi-Jittered Sample Sequences")
+fn generate_pmj(count: usize, rng: &mut SmallRng) -> Vec<(f64, f64)> {
+    let total = count.max(1).next_power_of_two().max(4);
+    let total = if total.trailing_zeros() % 2 == 1 {
+        total * 2
+    } else {
+        total
+    };
+    let mut samples = Vec::with_capacity(total);
+    samples.push((rng.random_range(0.0..1.0), rng.random_range(0.0..1.0)));
+
+    let mut x_strata = Vec::new();
+    let mut y_strata = Vec::new();
+
+    let mark_strata = |samples: &[(f64, f64)], xs: &mut Vec<bool>, ys: &mut Vec<bool>| {
+        let strata = 2 * samples.len();
+        xs.clear();
+        xs.resize(strata, false);
+        ys.clear();
+        ys.resize(strata, false);
+        for (x, y) in samples {
+            xs[(x * strata as f64) as usize] = true;
+            ys[(y * strata as f64) as usize] = true;
+        }
+    };
+
+    // Place a point inside half-cell (x_half, y_half) of cell (i, j) of an n x n grid, in 1D
+    // strata that are still free.
+    let generate = |i: usize,
+                    j: usize,
+                    x_half: usize,
+                    y_half: usize,
+                    n: usize,
+                    xs: &mut Vec<bool>,
+                    ys: &mut Vec<bool>,
+                    rng: &mut SmallRng| {
+        let strata = xs.len() as f64;
+        let x = loop {
+            let x = (i as f64 + 0.5 * (x_half as f64 + rng.random_range(0.0..1.0))) / n as f64;
+            let x = x.min(ONE_MINUS_EPSILON);
+            if !xs[(x * strata) as usize] {
+                break x;
+            }
+        };
+        let y = loop {
+            let y = (j as f64 + 0.5 * (y_half as f64 + rng.random_range(0.0..1.0))) / n as f64;
+            let y = y.min(ONE_MINUS_EPSILON);
+            if !ys[(y * strata) as usize] {
+                break y;
+            }
+        };
+        xs[(x * strata) as usize] = true;
+        ys[(y * strata) as usize] = true;
+        (x, y)
+    };
+
+    let cell_of = |(x, y): (f64, f64), n: usize| {
+        let (fx, fy) = (x * n as f64, y * n as f64);
+        let (i, j) = (fx as usize, fy as usize);
+        let x_half = (2.0 * (fx - i as f64)) as usize;
+        let y_half = (2.0 * (fy - j as f64)) as usize;
+        (i, j, x_half, y_half)
+    };
+
+    let mut n_samples = 1;
+    while n_samples < total {
+        // Even step: each existing point gets a partner in the diagonally opposite quadrant.
+        let n = (n_samples as f64).sqrt() as usize;
+        mark_strata(&samples, &mut x_strata, &mut y_strata);
+        for s in 0..n_samples {
+            let (i, j, x_half, y_half) = cell_of(samples[s], n);
+            let point = generate(
+                i,
+                j,
+                1 - x_half,
+                1 - y_half,
+                n,
+                &mut x_strata,
+                &mut y_strata,
+                rng,
+            );
+            samples.push(point);
+        }
+        n_samples *= 2;
+
+        // Odd step: fill the two remaining quadrants of each original cell.
+        mark_strata(&samples, &mut x_strata, &mut y_strata);
+        let mut odd = Vec::with_capacity(n_samples);
+        for (s, &point) in samples[..n_samples / 2].iter().enumerate() {
+            let (i, j, mut x_half, mut y_half) = cell_of(point, n);
+            if rng.random_bool(0.5) {
+                x_half = 1 - x_half;
+            } else {
+                y_half = 1 - y_half;
+            }
+            let first = generate(i, j, x_half, y_half, n, &mut x_strata, &mut y_strata, rng);
+            let second = generate(
+                i,
+                j,
+                1 - x_half,
+                1 - y_half,
+                n,
+                &mut x_strata,
+                &mut y_strata,
+                rng,
+            );
+            odd.push((s, first, second));
+        }
+        let half = n_samples / 2;
+        samples.resize(2 * n_samples, (0.0, 0.0));
+        for (s, first, second) in odd {
+            samples[n_samples + s] = first;
+            samples[n_samples + half + s] = second;
+        }
+        n_samples *= 2;
+    }
+    samples
+}
+
+/// The PMJ sequences for a sample count and seed.
+type PmjSets = Arc<Vec<Vec<(f64, f64)>>>;
+
+fn generate_pmj_sets(samples_per_pixel: usize, seed: u64) -> PmjSets {
+    let mut rng = SmallRng::seed_from_u64(seed);
+    let sets = (0..PMJ_SETS)
+        .map(|_| generate_pmj(samples_per_pixel, &mut rng))
+        .collect();
+    Arc::new(sets)
+}
+
+/// Progressive multi-jittered samples. A small set of PMJ sequences is generated up front and
+/// shared; each pixel and dimension picks one of them, so any prefix of a pixel's samples is
+/// still well stratified.
+pub struct PmjSampler {
+    sets: PmjSets,
+    seed: u64,
+    pixel: (usize, usize),
+    sample_index: usize,
+    dimension: u64,
+}
+
+impl PmjSampler {
+    pub fn new(samples_per_pixel: usize, seed: u64) -> Self {
+        Self::with_sets(generate_pmj_sets(samples_per_pixel, seed), seed)
+    }
+
+    /// A sampler using sequences already generated for another with the same seed.
+    fn with_sets(sets: PmjSets, seed: u64) -> Self {
+        Self {
+            sets,
+            seed,
+            pixel: (0, 0),
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn next_point(&mut self) -> (f64, f64) {
+        let (i, j) = self.pixel;
+        let h = hash(&[i as u64, j as u64, self.dimension, self.seed]);
+        self.dimension += 1;
+        let set = &self.sets[h as usize % self.sets.len()];
+        let point = set[self.sample_index % set.len()];
+        if self.sample_index < set.len() {
+            return point;
+        }
+        // Beyond the generated sequence, fall back to a random toroidal shift of it.
+        let shift = hash(&[h, (self.sample_index / set.len()) as u64]);
+        let (sx, sy) = (u32_to_unit(shift as u32), u32_to_unit((shift >> 32) as u32));
+        ((point.0 + sx).fract(), (point.1 + sy).fract())
+    }
+}
+
+impl Sampler for PmjSampler {
+    fn start_pixel_sample(&mut self, i: usize, j: usize, sample_index: usize) {
+        self.pixel = (i, j);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.next_point().0
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        self.next_point()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn collect_2d(kind: SamplerKind, count: usize) -> Vec<(f64, f64)> {
+        let mut sampler = kind.build(count, 7);
+        (0..count)
+            .map(|index| {
+                sampler.start_pixel_sample(3, 5, index);
+                sampler.get_2d()
+            })
+            .collect()
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case(SamplerKind::Independent)]
+    #[case(SamplerKind::Stratified)]
+    #[case(SamplerKind::Halton)]
+    #[case(SamplerKind::Sobol)]
+    #[case(SamplerKind::Pmj)]
+    fn test_samples_in_unit_range(#[case] kind: SamplerKind) {
+        let mut sampler = kind.build(64, 1);
+        for index in 0..64 {
+            sampler.start_pixel_sample(10, 20, index);
+            for _ in 0..8 {
+                let v = sampler.get_1d();
+                assert!((0.0..1.0).contains(&v), "{kind:?} gave {v}");
+                let (x, y) = sampler.get_2d();
+                assert!((0.0..1.0).contains(&x) && (0.0..1.0).contains(&y));
+            }
+        }
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case(SamplerKind::Independent)]
+    #[case(SamplerKind::Stratified)]
+    #[case(SamplerKind::Halton)]
+    #[case(SamplerKind::Sobol)]
+    #[case(SamplerKind::Pmj)]
+    fn test_samples_are_repeatable(#[case] kind: SamplerKind) {
+        assert_eq!(collect_2d(kind, 16), collect_2d(kind, 16));
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case(SamplerKind::Stratified, 4)]
+    #[case(SamplerKind::Sobol, 4)]
+    #[case(SamplerKind::Sobol, 8)]
+    #[case(SamplerKind::Pmj, 4)]
+    #[case(SamplerKind::Pmj, 8)]
+    #[case(SamplerKind::Pmj, 32)]
+    fn test_pixel_samples_are_stratified(#[case] kind: SamplerKind, #[case] grid: usize) {
+        // grid * grid samples should land one per cell of a grid x grid lattice.
+        let points = collect_2d(kind, grid * grid);
+        let mut cells = vec![false; grid * grid];
+        for (x, y) in points {
+            let cell = (y * grid as f64) as usize * grid + (x * grid as f64) as usize;
+            assert!(!cells[cell], "{kind:?} put two samples in cell {cell}");
+            cells[cell] = true;
+        }
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_pmj_sets_are_shared_between_samplers() {
+        let setup = SamplerKind::Pmj.setup(64, 3);
+        let sets = setup.pmj_sets.clone().unwrap();
+        let samplers = [setup.build(), setup.build()];
+        assert_eq!(Arc::strong_count(&sets), 2 + samplers.len());
+
+        let [mut shared, mut own] = [setup.build(), SamplerKind::Pmj.build(64, 3)];
+        for index in 0..64 {
+            shared.start_pixel_sample(3, 5, index);
+            own.start_pixel_sample(3, 5, index);
+            assert_eq!(shared.get_2d(), own.get_2d());
+        }
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case(2, 1, 0.5)]
+    #[case(2, 3, 0.75)]
+    #[case(3, 1, 1.0 / 3.0)]
+    #[case(3, 5, 7.0 / 9.0)]
+    fn test_radical_inverse(#[case] base: u64, #[case] index: u64, #[case] want: f64) {
+        assert!((scrambled_radical_inverse(base, index, 0) - want).abs() < 1e-12);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_permutation_element_is_a_permutation() {
+        for len in [1, 5, 16, 100] {
+            let mut seen = vec![false; len as usize];
+            for i in 0..len {
+                let p = permutation_element(i, len, 0xdead_beef) as usize;
+                assert!(!seen[p]);
+                seen[p] = true;
+            }
+        }
+    }
+}
@@ -0,0 +1,654 @@
+//! Scenes described in a plain text file, one statement per line:
+//!
+//! ```text
+//! # Comments run to the end of the line.
+//! texture NAME solid R,G,B
+//! texture NAME checker SCALE EVEN ODD
+//! texture NAME image FILE
+//! material NAME TYPE [KEY=VALUE ...]
+//! sphere X Y Z RADIUS [MATERIAL] [alpha=TEXTURE] [alpha_threshold=A]
+//! environment FILE [rotation=DEGREES] [intensity=X]
+//! sky ELEVATION AZIMUTH [turbidity=T] [intensity=X]
+//! light point X Y Z INTENSITY [ies=FILE]
+//! light spot X Y Z TARGET_X TARGET_Y TARGET_Z INTENSITY [cone=DEGREES] [falloff=DEGREES]
+//!     [ies=FILE]
+//! light directional DX DY DZ IRRADIANCE
+//! ```
+//!
+//! Textures and materials must be defined before they're used. The material types, with the
+//! parameters each takes, are:
+//!
+//! - `diffuse`: `albedo`
+//! - `dielectric`: `ior`
+//! - `conductor`: `metal` (`gold`, `copper`, `silver` or `aluminium`) or `eta` and `k`;
+//!   `roughness`, or `roughness_x` and `roughness_y` for a brushed look
+//!
+//!   Both of these take an optional iridescent thin film, with `film_thickness` (in nanometres)
+//!   and `film_ior`.
+//! - `rough-dielectric`: `ior`, `roughness`
+//! - `layered`: `base` (a material defined earlier), and the coating's `ior`, `roughness`,
+//!   `absorption` (a colour, per unit length) and `thickness`
+//! - `principled`: `base_colour`, `metallic`, `roughness`, `specular`, `specular_tint`, `sheen`,
+//!   `clearcoat`, `clearcoat_roughness`, `transmission`, `ior`
+//! - `normal-map`: `base` (a material defined earlier), `map` (a tangent-space normal map) and
+//!   `strength`
+//! - `bump`: `base`, `height` (a texture, its first channel read) and `scale`
+//! - `subsurface`: `albedo`, `mean_free_path` (a colour, in scene units), `ior` (a number),
+//!   `roughness` and `anisotropy`
+//!
+//! Colours are written `R,G,B`, and an index of refraction is a number or `bk7` for dispersive
+//! crown glass. Principled parameters may also name a texture as `@NAME`; scalar parameters read
+//! its first channel.
+//!
+//! Images are PPM, Radiance HDR or PFM files, found relative to the scene file.
+//!
+//! An `environment` lights the scene from an equirectangular image in place of the default sky,
+//! turned about the vertical by `rotation` and brightened by `intensity`. A `sky` does the same
+//! with a clear daylight sky, the sun `ELEVATION` degrees above the horizon and `AZIMUTH` degrees
+//! round from straight ahead (-Z) towards +X; `turbidity` runs from 2 for very clear air to 10
+//! for haze.
+//!
+//! Lights are points with no size, which light the scene without being seen. Point and spot
+//! lights' `INTENSITY` (a colour) falls off with the square of the distance; a spot shines
+//! towards its target in a cone `cone` degrees from the middle to the edge (30 by default),
+//! fading over the outer `falloff` degrees (5 by default). A directional light shines along
+//! (DX, DY, DZ), giving `IRRADIANCE` to surfaces facing it.
+//!
+//! Point and spot lights can be shaped by a fitting's IES photometric file, found relative to
+//! the scene file like images. `INTENSITY` is then the intensity in the fitting's brightest
+//! direction. A point light's fitting hangs straight down; a spot's points at its target.
+//!
+//! An object's `alpha` (a texture's first channel) cuts holes in it. Rays pass through where it's
+//! below `alpha_threshold`, or without a threshold, with probability one minus alpha.
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::detail::{Detailed, SurfaceDetail};
+use crate::environment::EnvironmentMap;
+use crate::hit::{AlphaMask, HittableList};
+use crate::ies::IesProfile;
+use crate::image::Image;
+use crate::layered::Layered;
+use crate::light::{DirectionalLight, Light, PointLight, SpotLight};
+use crate::material::{Conductor, Dielectric, Diffuse, Material, RoughDielectric, Subsurface};
+use crate::microfacet::TrowbridgeReitz;
+use crate::principled::Principled;
+use crate::sky::SunSky;
+use crate::spectrum::Ior;
+use crate::sphere::Sphere;
+use crate::texture::{Checker, ImageTexture, Scalar, SolidColour, Texture};
+use crate::thin_film::ThinFilm;
+use crate::{Colour, Point3};
+
+/// Everything loaded from a scene file.
+pub struct Scene {
+    pub world: HittableList,
+}
+
+/// Load a scene file. Mistakes in it are reported as `InvalidData` errors naming the line.
+pub fn load(path: &Path) -> io::Result<Scene> {
+    let text = fs::read_to_string(path)?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    parse_in(&text, dir).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Parse a scene description, with files it names relative to the working directory.
+pub fn parse(text: &str) -> Result<Scene, String> {
+    parse_in(text, Path::new(""))
+}
+
+fn parse_in(text: &str, dir: &Path) -> Result<Scene, String> {
+    let mut parser = Parser {
+        dir: dir.to_path_buf(),
+        ..Parser::default()
+    };
+    for (index, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.is_empty() {
+            continue;
+        }
+        parser
+            .statement(&words)
+            .map_err(|err| format!("line {}: {err}", index + 1))?;
+    }
+    Ok(Scene {
+        world: parser.world,
+    })
+}
+
+#[derive(Default)]
+struct Parser {
+    /// What files are named relative to.
+    dir: PathBuf,
+    textures: HashMap<String, Arc<dyn Texture>>,
+    materials: HashMap<String, Arc<dyn Material>>,
+    world: HittableList,
+}
+
+impl Parser {
+    fn statement(&mut self, words: &[&str]) -> Result<(), String> {
+        match words {
+            ["texture", name, kind, args @ ..] => {
+                let texture = self.texture(kind, args)?;
+                self.textures.insert(name.to_string(), texture);
+            }
+            ["material", name, kind, args @ ..] => {
+                let mut params = Params::new(args)?;
+                let material = self.material(kind, &mut params)?;
+                params.finish()?;
+                self.materials.insert(name.to_string(), material);
+            }
+            ["sphere", x, y, z, radius, rest @ ..] => {
+                let centre = Point3::new(number(x)?, number(y)?, number(z)?);
+                let mut sphere = Sphere::new(centre, number(radius)?);
+                let (material, rest) = match rest {
+                    [material, rest @ ..] if !material.contains('=') => (Some(material), rest),
+                    _ => (None, rest),
+                };
+                if let Some(material) = material {
+                    sphere = sphere.with_material(self.named_material(material)?);
+                }
+                let mut params = Params::new(rest)?;
+                if let Some(alpha) = self.alpha_mask(&mut params)? {
+                    sphere = sphere.with_alpha(alpha);
+                }
+                params.finish()?;
+                self.world.add(Box::new(sphere));
+            }
+            ["environment", file, rest @ ..] => {
+                let image =
+                    Image::load(&self.dir.join(file)).map_err(|err| format!("{file}: {err}"))?;
+                let mut environment = EnvironmentMap::new(image);
+                let mut params = Params::new(rest)?;
+                if let Some(rotation) = params.take("rotation") {
+                    environment = environment.with_rotation(number(rotation)?);
+                }
+                if let Some(intensity) = params.take("intensity") {
+                    environment = environment.with_intensity(number(intensity)?);
+                }
+                params.finish()?;
+                self.world.background = Arc::new(environment);
+            }
+            ["sky", elevation, azimuth, rest @ ..] => {
+                let mut sky = SunSky::new(number(elevation)?, number(azimuth)?);
+                let mut params = Params::new(rest)?;
+                if let Some(turbidity) = params.take("turbidity") {
+                    sky = sky.with_turbidity(number(turbidity)?);
+                }
+                if let Some(intensity) = params.take("intensity") {
+                    sky = sky.with_intensity(number(intensity)?);
+                }
+                params.finish()?;
+                self.world.background = Arc::new(sky);
+            }
+            ["light", kind, args @ ..] => {
+                let light = self.light(kind, args)?;
+                self.world.lights.push(light);
+            }
+            [keyword, ..] => return Err(format!("unknown statement: {keyword}")),
+            [] => {}
+        }
+        Ok(())
+    }
+
+    fn light(&self, kind: &str, args: &[&str]) -> Result<Box<dyn Light>, String> {
+        let point = |x: &str, y: &str, z: &str| {
+            Ok::<_, String>(Point3::new(number(x)?, number(y)?, number(z)?))
+        };
+        match (kind, args) {
+            ("point", [x, y, z, intensity, rest @ ..]) => {
+                let mut light = PointLight::new(point(x, y, z)?, parse_colour(intensity)?);
+                let mut params = Params::new(rest)?;
+                if let Some(profile) = self.ies_profile(&mut params)? {
+                    light = light.with_profile(profile);
+                }
+                params.finish()?;
+                Ok(Box::new(light))
+            }
+            ("spot", [x, y, z, tx, ty, tz, intensity, rest @ ..]) => {
+                let mut spot = SpotLight::new(
+                    point(x, y, z)?,
+                    point(tx, ty, tz)?,
+                    parse_colour(intensity)?,
+                );
+                let mut params = Params::new(rest)?;
+                let cone = params.take("cone").map(number).transpose()?;
+                let falloff = params.take("falloff").map(number).transpose()?;
+                if cone.is_some() || falloff.is_some() {
+                    spot = spot.with_cone(cone.unwrap_or(30.0), falloff.unwrap_or(5.0));
+                }
+                if let Some(profile) = self.ies_profile(&mut params)? {
+                    spot = spot.with_profile(profile);
+                }
+                params.finish()?;
+                Ok(Box::new(spot))
+            }
+            ("directional", [x, y, z, irradiance]) => Ok(Box::new(DirectionalLight::new(
+                point(x, y, z)?,
+                parse_colour(irradiance)?,
+            ))),
+            ("point", _) => Err("point: expected X Y Z INTENSITY".to_string()),
+            ("spot", _) => {
+                Err("spot: expected X Y Z TARGET_X TARGET_Y TARGET_Z INTENSITY".to_string())
+            }
+            ("directional", _) => Err("directional: expected DX DY DZ IRRADIANCE".to_string()),
+            _ => Err(format!("unknown light type: {kind}")),
+        }
+    }
+
+    /// The profile named by an `ies` parameter, if there is one.
+    fn ies_profile(&self, params: &mut Params) -> Result<Option<Arc<IesProfile>>, String> {
+        let Some(file) = params.take("ies") else {
+            return Ok(None);
+        };
+        let profile =
+            IesProfile::load(&self.dir.join(file)).map_err(|err| format!("{file}: {err}"))?;
+        Ok(Some(Arc::new(profile)))
+    }
+
+    fn texture(&self, kind: &str, args: &[&str]) -> Result<Arc<dyn Texture>, String> {
+        match (kind, args) {
+            ("solid", [colour]) => Ok(Arc::new(SolidColour::new(parse_colour(colour)?))),
+            ("checker", [scale, even, odd]) => Ok(Arc::new(Checker {
+                scale: number(scale)?,
+                even: self.named_texture(even)?,
+                odd: self.named_texture(odd)?,
+            })),
+            ("image", [file]) => {
+                let image =
+                    Image::load(&self.dir.join(file)).map_err(|err| format!("{file}: {err}"))?;
+                Ok(Arc::new(ImageTexture::new(Arc::new(image))))
+            }
+            ("image", _) => Err("image: expected FILE".to_string()),
+            ("solid", _) => Err("solid: expected R,G,B".to_string()),
+            ("checker", _) => Err("checker: expected SCALE EVEN ODD".to_string()),
+            _ => Err(format!("unknown texture type: {kind}")),
+        }
+    }
+
+    fn material(&self, kind: &str, params: &mut Params) -> Result<Arc<dyn Material>, String> {
+        match kind {
+            "diffuse" => {
+                let mut diffuse = Diffuse::default();
+                if let Some(albedo) = params.take("albedo") {
+                    diffuse.albedo = parse_colour(albedo)?;
+                }
+                Ok(Arc::new(diffuse))
+            }
+            "dielectric" => {
+                let mut dielectric = Dielectric::new(ior(params)?);
+                if let Some(film) = thin_film(params)? {
+                    dielectric = dielectric.with_thin_film(film);
+                }
+                Ok(Arc::new(dielectric))
+            }
+            "conductor" => {
+                let distribution = distribution(params)?;
+                let conductor = match (params.take("metal"), params.take("eta"), params.take("k")) {
+                    (Some("gold"), None, None) => Conductor::gold(distribution),
+                    (Some("copper"), None, None) => Conductor::copper(distribution),
+                    (Some("silver"), None, None) => Conductor::silver(distribution),
+                    (Some("aluminium"), None, None) => Conductor::aluminium(distribution),
+                    (Some(metal), None, None) => return Err(format!("unknown metal: {metal}")),
+                    (None, Some(eta), Some(k)) => {
+                        Conductor::new(parse_colour(eta)?, parse_colour(k)?, distribution)
+                    }
+                    _ => return Err("conductor: expected metal, or eta and k".to_string()),
+                };
+                Ok(Arc::new(match thin_film(params)? {
+                    Some(film) => conductor.with_thin_film(film),
+                    None => conductor,
+                }))
+            }
+            "rough-dielectric" => Ok(Arc::new(RoughDielectric::new(
+                ior(params)?,
+                distribution(params)?,
+            ))),
+            "layered" => {
+                let base = params.take("base").ok_or("layered: expected base")?;
+                let coating = RoughDielectric::new(ior(params)?, distribution(params)?);
+                let mut layered = Layered::new(coating, self.named_material(base)?);
+                match (params.take("absorption"), params.take("thickness")) {
+                    (Some(absorption), Some(thickness)) => {
+                        layered =
+                            layered.with_absorption(parse_colour(absorption)?, number(thickness)?);
+                    }
+                    (None, None) => {}
+                    _ => return Err("layered: expected both absorption and thickness".to_string()),
+                }
+                Ok(Arc::new(layered))
+            }
+            "subsurface" => {
+                let albedo = params.take("albedo").map(parse_colour).transpose()?;
+                let mean_free_path = params
+                    .take("mean_free_path")
+                    .map(parse_colour)
+                    .transpose()?;
+                let ior = params.take("ior").map(number).transpose()?;
+                let mut subsurface = Subsurface::new(
+                    albedo.unwrap_or(Colour::new(0.8, 0.8, 0.8)),
+                    mean_free_path.ok_or("subsurface: expected mean_free_path")?,
+                    ior.unwrap_or(1.4),
+                );
+                if let Some(roughness) = params.take("roughness") {
+                    subsurface = subsurface.with_roughness(number(roughness)?);
+                }
+                if let Some(g) = params.take("anisotropy") {
+                    subsurface = subsurface.with_anisotropy(number(g)?);
+                }
+                Ok(Arc::new(subsurface))
+            }
+            "normal-map" | "bump" => {
+                let base = params
+                    .take("base")
+                    .ok_or(format!("{kind}: expected base"))?;
+                let base = self.named_material(base)?;
+                let (texture, amount) = if kind == "bump" {
+                    ("height", "scale")
+                } else {
+                    ("map", "strength")
+                };
+                let texture = params
+                    .take(texture)
+                    .ok_or(format!("{kind}: expected {texture}"))?;
+                let texture = self.colour_texture(texture)?;
+                let amount = params.take(amount).map(number).transpose()?.unwrap_or(1.0);
+                let detail = if kind == "bump" {
+                    SurfaceDetail::Bump {
+                        height: texture,
+                        scale: amount,
+                    }
+                } else {
+                    SurfaceDetail::NormalMap {
+                        map: texture,
+                        strength: amount,
+                    }
+                };
+                Ok(Arc::new(Detailed::new(base, detail)))
+            }
+            "principled" => {
+                let mut principled = Principled::default();
+                if let Some(value) = params.take("base_colour") {
+                    principled.base_colour = self.colour_texture(value)?;
+                }
+                for (key, field) in [
+                    ("metallic", &mut principled.metallic),
+                    ("roughness", &mut principled.roughness),
+                    ("specular", &mut principled.specular),
+                    ("specular_tint", &mut principled.specular_tint),
+                    ("sheen", &mut principled.sheen),
+                    ("clearcoat", &mut principled.clearcoat),
+                    ("clearcoat_roughness", &mut principled.clearcoat_roughness),
+                    ("transmission", &mut principled.transmission),
+                    ("ior", &mut principled.ior),
+                ] {
+                    if let Some(value) = params.take(key) {
+                        *field = self.scalar(value)?;
+                    }
+                }
+                Ok(Arc::new(principled))
+            }
+            _ => Err(format!("unknown material type: {kind}")),
+        }
+    }
+
+    fn named_texture(&self, name: &str) -> Result<Arc<dyn Texture>, String> {
+        self.textures
+            .get(name)
+            .cloned()
+            .ok_or_else(|| format!("unknown texture: {name}"))
+    }
+
+    fn named_material(&self, name: &str) -> Result<Arc<dyn Material>, String> {
+        self.materials
+            .get(name)
+            .cloned()
+            .ok_or_else(|| format!("unknown material: {name}"))
+    }
+
+    /// A colour, or a texture given as `@NAME`.
+    fn colour_texture(&self, value: &str) -> Result<Arc<dyn Texture>, String> {
+        match value.strip_prefix('@') {
+            Some(name) => self.named_texture(name),
+            None => Ok(Arc::new(SolidColour::new(parse_colour(value)?))),
+        }
+    }
+
+    /// An object's `alpha` texture, cut out at `alpha_threshold` if given.
+    fn alpha_mask(&self, params: &mut Params) -> Result<Option<AlphaMask>, String> {
+        let threshold = params.take("alpha_threshold").map(number).transpose()?;
+        let Some(alpha) = params.take("alpha") else {
+            return match threshold {
+                Some(_) => Err("alpha_threshold without alpha".to_string()),
+                None => Ok(None),
+            };
+        };
+        let texture = self.colour_texture(alpha)?;
+        Ok(Some(match threshold {
+            Some(threshold) => AlphaMask::cutout(texture, threshold),
+            None => AlphaMask::stochastic(texture),
+        }))
+    }
+
+    /// A number, or a texture given as `@NAME`.
+    fn scalar(&self, value: &str) -> Result<Scalar, String> {
+        match value.strip_prefix('@') {
+            Some(name) => Ok(Scalar::Texture(self.named_texture(name)?)),
+            None => Ok(Scalar::Value(number(value)?)),
+        }
+    }
+}
+
+/// A material's `KEY=VALUE` parameters, which must all be used.
+struct Params<'a> {
+    values: HashMap<&'a str, &'a str>,
+}
+
+impl<'a> Params<'a> {
+    fn new(args: &[&'a str]) -> Result<Self, String> {
+        let mut values = HashMap::new();
+        for arg in args {
+            let (key, value) = arg
+                .split_once('=')
+                .ok_or_else(|| format!("expected KEY=VALUE, got {arg}"))?;
+            if values.insert(key, value).is_some() {
+                return Err(format!("{key} given twice"));
+            }
+        }
+        Ok(Self { values })
+    }
+
+    fn take(&mut self, key: &str) -> Option<&'a str> {
+        self.values.remove(key)
+    }
+
+    fn finish(self) -> Result<(), String> {
+        let mut unused: Vec<_> = self.values.into_keys().collect();
+        unused.sort();
+        match unused.first() {
+            Some(key) => Err(format!("unknown parameter: {key}")),
+            None => Ok(()),
+        }
+    }
+}
+
+fn number(word: &str) -> Result<f64, String> {
+    word.parse()
+        .map_err(|_| format!("expected a number, got {word}"))
+}
+
+fn parse_colour(word: &str) -> Result<Colour, String> {
+    match word.split(',').map(number).collect::<Result<Vec<_>, _>>()?[..] {
+        [r, g, b] => Ok(Colour::new(r, g, b)),
+        _ => Err(format!("expected R,G,B, got {word}")),
+    }
+}
+
+fn ior(params: &mut Params) -> Result<Ior, String> {
+    match params.take("ior") {
+        None => Ok(Ior::Constant(1.5)),
+        Some("bk7") => Ok(Ior::BK7),
+        Some(value) => Ok(Ior::Constant(number(value)?)),
+    }
+}
+
+fn thin_film(params: &mut Params) -> Result<Option<ThinFilm>, String> {
+    let thickness = params.take("film_thickness").map(number).transpose()?;
+    let ior = params.take("film_ior").map(number).transpose()?;
+    match (thickness, ior) {
+        (Some(thickness), Some(ior)) => Ok(Some(ThinFilm::new(thickness, ior))),
+        (None, None) => Ok(None),
+        _ => Err("expected both film_thickness and film_ior".to_string()),
+    }
+}
+
+fn distribution(params: &mut Params) -> Result<TrowbridgeReitz, String> {
+    let roughness = params.take("roughness").map(number).transpose()?;
+    let x = params.take("roughness_x").map(number).transpose()?;
+    let y = params.take("roughness_y").map(number).transpose()?;
+    match (roughness, x, y) {
+        (Some(r), None, None) => Ok(TrowbridgeReitz::from_roughness(r, r)),
+        (None, Some(x), Some(y)) => Ok(TrowbridgeReitz::from_roughness(x, y)),
+        (None, None, None) => Ok(TrowbridgeReitz::from_roughness(0.0, 0.0)),
+        _ => Err("expected roughness, or roughness_x and roughness_y".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Vec3;
+    use crate::hit::Hittable;
+    use crate::ray::Ray;
+    use rstest::rstest;
+
+    const SCENE: &str = "
+        # A glossy red ball on a checked floor.
+        texture white solid 0.9,0.9,0.9
+        texture black solid 0.1,0.1,0.1
+        texture floor checker 0.5 white black
+        material ground principled base_colour=@floor roughness=0.8
+        material paint principled base_colour=0.8,0.1,0.1 clearcoat=1
+        material brushed conductor metal=aluminium roughness_x=0.6 roughness_y=0.1
+        material bubble dielectric ior=1 film_thickness=350 film_ior=1.33
+        material tinted conductor metal=silver film_thickness=250 film_ior=2.4
+        material varnish layered base=ground absorption=0.1,0.3,0.9 thickness=0.2
+        material bumpy bump base=paint height=@floor scale=0.01
+        material wax subsurface albedo=0.9,0.8,0.6 mean_free_path=0.05,0.03,0.01 anisotropy=0.3
+        sphere 0 0 -1 0.5 paint  # the ball
+        sphere 0 -100.5 -1 100 ground
+        sphere 1 0 -1 0.5 brushed
+        sphere -1 0 -1 0.5
+        sphere 0 0 1 0.5 varnish
+        sphere 0 5 0 0.5 alpha=@floor alpha_threshold=0.5
+        sky 30 90 turbidity=4
+        light point 0 3 0 10,10,10
+        light spot 0 3 0 0 0 -1 20,20,20 cone=40 falloff=10
+        light directional 0 -1 0 1,1,1
+    ";
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_parse_scene() {
+        let scene = parse(SCENE).unwrap();
+        assert_eq!(scene.world.objects.len(), 6);
+        let r = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let rec = scene.world.hit(&r, &(0.001..f64::INFINITY)).unwrap();
+        assert_eq!(rec.material.albedo(&rec), Colour::new(0.8, 0.1, 0.1));
+        // The floor's colour comes from the checker.
+        let down = Ray::new(Point3::new(0.1, 0.0, -3.0), Vec3::new(0.0, -1.0, 0.0));
+        let rec = scene.world.hit(&down, &(0.001..f64::INFINITY)).unwrap();
+        let albedo = rec.material.albedo(&rec);
+        assert!(albedo == Colour::new(0.9, 0.9, 0.9) || albedo == Colour::new(0.1, 0.1, 0.1));
+        // The sun is low in the east.
+        let sun = Vec3::new(30_f64.to_radians().cos(), 0.5, 0.0);
+        assert!(scene.world.background.radiance(&sun).luminance() > 1e4);
+        assert_eq!(scene.world.lights.len(), 3);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_load_finds_images_next_to_the_scene() {
+        let dir = std::env::temp_dir().join(format!("rt-scene-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("red.ppm"), "P3 1 1 255 255 0 0").unwrap();
+        let downlight = "TILT=NONE\n1 1000 1 2 1 1 2 0 0 0\n1 1 10\n0 90\n0\n200 0\n";
+        fs::write(dir.join("down.ies"), downlight).unwrap();
+        let text = "texture red image red.ppm\nmaterial m diffuse\nsphere 0 0 -1 0.5 m\n\
+                    material tiled principled base_colour=@red\nsphere 0 0 -3 0.5 tiled\n\
+                    environment red.ppm rotation=90 intensity=2\n\
+                    light point 0 2 0 4,4,4 ies=down.ies";
+        fs::write(dir.join("scene.txt"), text).unwrap();
+        let scene = load(&dir.join("scene.txt"));
+        fs::remove_dir_all(&dir).unwrap();
+        let scene = scene.unwrap();
+        let r = Ray::new(Point3::new(0.0, 0.0, -2.0), Vec3::new(0.0, 0.0, -1.0));
+        let rec = scene.world.hit(&r, &(0.001..f64::INFINITY)).unwrap();
+        assert_eq!(rec.material.albedo(&rec), Colour::new(1.0, 0.0, 0.0));
+        let sky = scene.world.background.radiance(&Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(sky, Colour::new(2.0, 0.0, 0.0));
+        // The fitting shines down, not up.
+        let light = &scene.world.lights[0];
+        let below = light.incidence(&Point3::new(0.0, 0.0, 0.0)).unwrap();
+        assert_eq!(below.irradiance, Colour::new(1.0, 1.0, 1.0));
+        assert!(light.incidence(&Point3::new(0.0, 4.0, 0.0)).is_none());
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case("sphere 0 0 0", "line 1: unknown statement: sphere")]
+    #[case("sphere 0 0 0 1 shiny", "line 1: unknown material: shiny")]
+    #[case(
+        "sphere 0 0 0 1 alpha_threshold=0.5",
+        "line 1: alpha_threshold without alpha"
+    )]
+    #[case("sphere 0 0 0 1 opacity=1", "line 1: unknown parameter: opacity")]
+    #[case(
+        "\nmaterial m diffuse colour=1,0,0",
+        "line 2: unknown parameter: colour"
+    )]
+    #[case("material m conductor metal=lead", "line 1: unknown metal: lead")]
+    #[case("material m layered ior=1.5", "line 1: layered: expected base")]
+    #[case(
+        "material m subsurface albedo=1,1,1",
+        "line 1: subsurface: expected mean_free_path"
+    )]
+    #[case(
+        "material m dielectric film_ior=1.3",
+        "line 1: expected both film_thickness and film_ior"
+    )]
+    #[case(
+        "material m principled metallic=@rust",
+        "line 1: unknown texture: rust"
+    )]
+    #[case("texture t solid 1,0", "line 1: expected R,G,B, got 1,0")]
+    #[case("material m bump base=m height=@t", "line 1: unknown material: m")]
+    #[case(
+        "texture t image missing.ppm",
+        "line 1: missing.ppm: No such file or directory (os error 2)"
+    )]
+    #[case("material m diffuse albedo=1,x,0", "line 1: expected a number, got x")]
+    #[case("sky 30", "line 1: unknown statement: sky")]
+    #[case("light area 0 0 0", "line 1: unknown light type: area")]
+    #[case(
+        "light point 0 0 0 1,1,1 ies=missing.ies",
+        "line 1: missing.ies: No such file or directory (os error 2)"
+    )]
+    #[case("light point 0 0 0", "line 1: point: expected X Y Z INTENSITY")]
+    #[case(
+        "light spot 0 1 0 0 0 0 1,1,1 angle=20",
+        "line 1: unknown parameter: angle"
+    )]
+    #[case("sky 30 0 haze=2", "line 1: unknown parameter: haze")]
+    #[case(
+        "environment missing.hdr",
+        "line 1: missing.hdr: No such file or directory (os error 2)"
+    )]
+    fn test_parse_errors(#[case] text: &str, #[case] want: &str) {
+        assert_eq!(parse(text).err().unwrap(), want);
+    }
+}
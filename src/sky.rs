@@ -0,0 +1,333 @@
+//! A clear daylight sky and the sun in it, from the analytic model of Preetham, Shirley and
+//! Smits, "A Practical Analytic Model for Daylight", 1999.
+use std::f64::consts::PI;
+
+use crate::environment::{Background, LightSample};
+use crate::microfacet::Frame;
+use crate::sampler::Sampler;
+use crate::spectrum::xyz_d65_to_srgb;
+use crate::{Colour, Vec3, dot, sample_cosine_hemisphere, unit_vector};
+
+/// Half the angle the sun's disc covers, in radians.
+const SUN_ANGULAR_RADIUS: f64 = 0.00465;
+
+/// The sun's luminance before the atmosphere dims it, in kcd/m² like the sky model.
+const SUN_LUMINANCE: f64 = 1.6e6;
+
+/// What luminance in kcd/m² is multiplied by, so a clear midday sky is about as bright as the
+/// default gradient.
+const LUMINANCE_SCALE: f64 = 0.1;
+
+/// Chance of sampling the sun's disc rather than the rest of the sky.
+const SUN_SAMPLE_PROBABILITY: f64 = 0.5;
+
+/// Perez et al.'s formula for how sky brightness varies with the angle from the zenith and the
+/// angle from the sun, one set of coefficients per quantity.
+#[derive(Debug, Clone, Copy)]
+struct Perez {
+    a: f64,
+    b: f64,
+    c: f64,
+    d: f64,
+    e: f64,
+}
+
+impl Perez {
+    /// Coefficients linear in turbidity, as `[slope, intercept]` pairs.
+    fn fit(coefficients: [[f64; 2]; 5], turbidity: f64) -> Self {
+        let [a, b, c, d, e] = coefficients.map(|[slope, intercept]| slope * turbidity + intercept);
+        Self { a, b, c, d, e }
+    }
+
+    fn f(&self, cos_theta: f64, gamma: f64) -> f64 {
+        let cos_theta = cos_theta.max(1e-3);
+        (1.0 + self.a * (self.b / cos_theta).exp())
+            * (1.0 + self.c * (self.d * gamma).exp() + self.e * gamma.cos().powi(2))
+    }
+}
+
+/// A cubic in the sun's zenith angle for each power of turbidity, from T² down to 1.
+fn zenith_chromaticity(rows: [[f64; 4]; 3], turbidity: f64, theta_sun: f64) -> f64 {
+    let powers = [theta_sun.powi(3), theta_sun.powi(2), theta_sun, 1.0];
+    let cubic = |row: [f64; 4]| row.iter().zip(powers).map(|(c, p)| c * p).sum::<f64>();
+    turbidity * turbidity * cubic(rows[0]) + turbidity * cubic(rows[1]) + cubic(rows[2])
+}
+
+/// Sample a direction within `cos_max` of `axis`, uniformly over the solid angle.
+fn sample_cone(axis: &Vec3, cos_max: f64, u: (f64, f64)) -> Vec3 {
+    let cos_theta = 1.0 - u.0 * (1.0 - cos_max);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.1;
+    Frame::from_normal(axis).from_local(&Vec3::new(
+        sin_theta * phi.cos(),
+        sin_theta * phi.sin(),
+        cos_theta,
+    ))
+}
+
+/// The sky on a clear day, for outdoor scenes, lit by a sun of the right colour for its height
+/// and how hazy the air is. Y is up. The sun is sampled directly, and so is the sky, in
+/// proportion to the cosine with the zenith.
+///
+/// The model only covers the sky, so the sun is kept at or above the horizon, and below it the
+/// sky at the horizon carries on down.
+#[derive(Debug, Clone)]
+pub struct SunSky {
+    /// Unit length, pointing at the centre of the sun.
+    pub sun_direction: Vec3,
+    /// How hazy the air is: 2 for very clear, about 3 for a clear day, 10 for thin fog.
+    pub turbidity: f64,
+    /// What the model's values are multiplied by.
+    pub intensity: f64,
+    /// For luminance Y and chromaticities x and y.
+    perez: [Perez; 3],
+    /// Y, x and y straight up.
+    zenith: [f64; 3],
+    sun_radiance: Colour,
+}
+
+impl SunSky {
+    /// The sun `elevation` degrees above the horizon, and `azimuth` degrees round from -Z
+    /// (straight ahead of the default camera) towards +X.
+    pub fn new(elevation: f64, azimuth: f64) -> Self {
+        let (elevation, azimuth) = (
+            elevation.clamp(0.0, 90.0).to_radians(),
+            azimuth.to_radians(),
+        );
+        let sun_direction = Vec3::new(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos(),
+        );
+        Self::fitted(sun_direction, 3.0, 1.0)
+    }
+
+    pub fn with_turbidity(self, turbidity: f64) -> Self {
+        Self::fitted(self.sun_direction, turbidity, self.intensity)
+    }
+
+    pub fn with_intensity(mut self, intensity: f64) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    /// The sky with the model's coefficients worked out for the sun's position and the
+    /// turbidity.
+    fn fitted(sun_direction: Vec3, turbidity: f64, intensity: f64) -> Self {
+        let t = turbidity;
+        let theta_sun = sun_direction.y.clamp(0.0, 1.0).acos();
+        let perez = [
+            Perez::fit(
+                [
+                    [0.1787, -1.4630],
+                    [-0.3554, 0.4275],
+                    [-0.0227, 5.3251],
+                    [0.1206, -2.5771],
+                    [-0.0670, 0.3703],
+                ],
+                t,
+            ),
+            Perez::fit(
+                [
+                    [-0.0193, -0.2592],
+                    [-0.0665, 0.0008],
+                    [-0.0004, 0.2125],
+                    [-0.0641, -0.8989],
+                    [-0.0033, 0.0452],
+                ],
+                t,
+            ),
+            Perez::fit(
+                [
+                    [-0.0167, -0.2608],
+                    [-0.0950, 0.0092],
+                    [-0.0079, 0.2102],
+                    [-0.0441, -1.6537],
+                    [-0.0109, 0.0529],
+                ],
+                t,
+            ),
+        ];
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+        let zenith = [
+            ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0),
+            zenith_chromaticity(
+                [
+                    [0.00166, -0.00375, 0.00209, 0.0],
+                    [-0.02903, 0.06377, -0.03202, 0.00394],
+                    [0.11693, -0.21196, 0.06052, 0.25886],
+                ],
+                t,
+                theta_sun,
+            ),
+            zenith_chromaticity(
+                [
+                    [0.00275, -0.00610, 0.00317, 0.0],
+                    [-0.04214, 0.08970, -0.04153, 0.00516],
+                    [0.15346, -0.26756, 0.06670, 0.26688],
+                ],
+                t,
+                theta_sun,
+            ),
+        ];
+
+        // Sunlight loses blue to scattering off air molecules (Rayleigh) and more evenly to
+        // haze (Angstrom's formula), more so the more air it passes through low in the sky.
+        let theta_degrees = theta_sun.to_degrees();
+        let air_mass =
+            1.0 / (theta_sun.cos() + 0.15 * (93.885 - theta_degrees).powf(-1.253)).max(1e-3);
+        let beta = 0.04608 * t - 0.04586;
+        let transmittance = |micrometres: f64| {
+            let rayleigh = 0.008735 * micrometres.powf(-4.08);
+            let haze = beta * micrometres.powf(-1.3);
+            (-(rayleigh + haze) * air_mass).exp()
+        };
+        let sun_radiance = SUN_LUMINANCE
+            * Colour::new(
+                transmittance(0.68),
+                transmittance(0.55),
+                transmittance(0.44),
+            );
+        Self {
+            sun_direction,
+            turbidity,
+            intensity,
+            perez,
+            zenith,
+            sun_radiance,
+        }
+    }
+
+    /// The sky alone along the unit vector `direction`.
+    fn sky_radiance(&self, direction: &Vec3) -> Colour {
+        let cos_theta = direction.y.max(0.0);
+        let gamma = dot(direction, &self.sun_direction).clamp(-1.0, 1.0).acos();
+        let theta_sun = self.sun_direction.y.clamp(0.0, 1.0).acos();
+        let [luminance, x, y] = [0, 1, 2].map(|i| {
+            let perez = &self.perez[i];
+            self.zenith[i] * perez.f(cos_theta, gamma) / perez.f(1.0, theta_sun)
+        });
+        if y <= 0.0 {
+            return Colour::default();
+        }
+        let xyz = Vec3::new(x / y * luminance, luminance, (1.0 - x - y) / y * luminance);
+        let rgb = xyz_d65_to_srgb(xyz);
+        Colour::new(rgb.x.max(0.0), rgb.y.max(0.0), rgb.z.max(0.0))
+    }
+
+    fn in_sun(&self, direction: &Vec3) -> bool {
+        dot(direction, &self.sun_direction) >= SUN_ANGULAR_RADIUS.cos()
+    }
+}
+
+impl Background for SunSky {
+    fn radiance(&self, direction: &Vec3) -> Colour {
+        let direction = unit_vector(direction);
+        let mut radiance = self.sky_radiance(&direction);
+        if self.in_sun(&direction) {
+            radiance += self.sun_radiance;
+        }
+        self.intensity * LUMINANCE_SCALE * radiance
+    }
+
+    fn sample(&self, sampler: &mut dyn Sampler) -> Option<LightSample> {
+        let u_light = sampler.get_1d();
+        let u = sampler.get_2d();
+        let direction = if u_light < SUN_SAMPLE_PROBABILITY {
+            sample_cone(&self.sun_direction, SUN_ANGULAR_RADIUS.cos(), u)
+        } else {
+            sample_cosine_hemisphere(&Vec3::new(0.0, 1.0, 0.0), u)
+        };
+        let pdf = self.pdf(&direction);
+        (pdf > 0.0).then(|| LightSample {
+            direction,
+            radiance: self.radiance(&direction),
+            pdf,
+        })
+    }
+
+    fn pdf(&self, direction: &Vec3) -> f64 {
+        let direction = unit_vector(direction);
+        let sun = if self.in_sun(&direction) {
+            1.0 / (2.0 * PI * (1.0 - SUN_ANGULAR_RADIUS.cos()))
+        } else {
+            0.0
+        };
+        let sky = direction.y.max(0.0) / PI;
+        SUN_SAMPLE_PROBABILITY * sun + (1.0 - SUN_SAMPLE_PROBABILITY) * sky
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::SamplerKind;
+    use rstest::rstest;
+
+    const UP: Vec3 = Vec3 {
+        x: 0.0,
+        y: 1.0,
+        z: 0.0,
+    };
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_sun_position() {
+        let sky = SunSky::new(30.0, 90.0);
+        let want = Vec3::new(30_f64.to_radians().cos(), 0.5, 0.0);
+        assert!((sky.sun_direction - want).length() < 1e-12);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_zenith_matches_the_model() {
+        // From Preetham et al.'s fit, for the sun 30 degrees from the zenith on a clear day.
+        let sky = SunSky::new(60.0, 0.0);
+        let zenith = sky.sky_radiance(&UP);
+        let luminance = 0.2126 * zenith.x + 0.7152 * zenith.y + 0.0722 * zenith.z;
+        assert!((luminance - sky.zenith[0]).abs() < 1e-3 * sky.zenith[0]);
+        assert!((sky.zenith[0] - 10.37).abs() < 0.05, "{}", sky.zenith[0]);
+        assert!(zenith.z > zenith.x, "the sky is blue: {zenith:?}");
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_sky_is_brighter_towards_the_sun() {
+        let sky = SunSky::new(20.0, 0.0);
+        let towards = sky.radiance(&Vec3::new(0.0, 0.3, -1.0));
+        let away = sky.radiance(&Vec3::new(0.0, 0.3, 1.0));
+        assert!(towards.luminance() > 2.0 * away.luminance());
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_low_hazy_suns_are_dimmer_and_redder() {
+        let noon = SunSky::new(80.0, 0.0).sun_radiance;
+        let sunset = SunSky::new(3.0, 0.0).sun_radiance;
+        let hazy = SunSky::new(80.0, 0.0).with_turbidity(8.0).sun_radiance;
+        assert!(sunset.luminance() < noon.luminance());
+        assert!(sunset.x / sunset.z > noon.x / noon.z);
+        assert!(hazy.luminance() < noon.luminance());
+        assert!(noon.z > 0.0 && noon.x / noon.z < 1.5, "{noon:?}");
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_sampling_matches_pdf() {
+        let sky = SunSky::new(40.0, 120.0);
+        let mut sampler = SamplerKind::Independent.build(1, 0);
+        let n = 1000;
+        let mut in_sun = 0;
+        for sample_index in 0..n {
+            sampler.start_pixel_sample(0, 0, sample_index);
+            let sample = sky.sample(sampler.as_mut()).unwrap();
+            assert!((sample.pdf - sky.pdf(&sample.direction)).abs() < 1e-9 * sample.pdf);
+            assert!(sample.direction.y >= 0.0);
+            if sky.in_sun(&sample.direction) {
+                in_sun += 1;
+                assert!(sample.radiance.luminance() > 1e4);
+            }
+        }
+        assert!((in_sun as f64 / n as f64 - SUN_SAMPLE_PROBABILITY).abs() < 0.05);
+    }
+}
@@ -0,0 +1,368 @@
+//! Spectral rendering support. A spectral path carries radiance at a few sampled wavelengths in
+//! place of RGB values; RGB colours from the scene are turned into spectra as they're used, and
+//! each sample's spectral radiance is projected onto the CIE XYZ matching functions and then to
+//! linear sRGB before it reaches the film. That last step is linear, so accumulating sRGB is the
+//! same as accumulating XYZ and converting at the end.
+use std::sync::LazyLock;
+
+use crate::{Colour, Vec3};
+
+/// The range of wavelengths sampled, in nanometres.
+pub const LAMBDA_MIN: f64 = 380.0;
+pub const LAMBDA_MAX: f64 = 780.0;
+
+/// How many wavelengths each path carries, one per `Vec3` component.
+const N_WAVELENGTHS: usize = 3;
+
+/// Wavelengths sampled for one path, spread evenly across the visible range from a random hero
+/// wavelength (Wilkie et al., "Hero Wavelength Spectral Sampling", 2014). The path's radiance for
+/// wavelength i is kept in component i of a `Vec3`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SampledWavelengths {
+    pub lambda: [f64; N_WAVELENGTHS],
+    /// Set once something, such as dispersion, has sent the path where only the hero wavelength
+    /// could go.
+    secondary_terminated: bool,
+}
+
+impl SampledWavelengths {
+    /// Wavelengths from a uniform sample `u` in [0, 1). Each is uniformly distributed over the
+    /// visible range.
+    pub fn sample(u: f64) -> Self {
+        let mut lambda = [0.0; N_WAVELENGTHS];
+        for (i, l) in lambda.iter_mut().enumerate() {
+            let offset = (u + i as f64 / N_WAVELENGTHS as f64).fract();
+            *l = LAMBDA_MIN + offset * (LAMBDA_MAX - LAMBDA_MIN);
+        }
+        Self {
+            lambda,
+            secondary_terminated: false,
+        }
+    }
+
+    /// The hero wavelength, which the path follows where wavelengths disagree.
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    /// Drop every wavelength but the hero, returning what to multiply the path's radiance by:
+    /// the secondaries' share moves to the hero so the estimate stays unbiased.
+    pub fn terminate_secondary(&mut self) -> Vec3 {
+        if self.secondary_terminated {
+            return Vec3::new(1.0, 1.0, 1.0);
+        }
+        self.secondary_terminated = true;
+        Vec3::new(N_WAVELENGTHS as f64, 0.0, 0.0)
+    }
+
+    /// An RGB colour's spectrum at each wavelength.
+    pub fn upsample(&self, rgb: Colour) -> Vec3 {
+        Vec3::new(
+            rgb_to_spectrum(rgb, self.lambda[0]),
+            rgb_to_spectrum(rgb, self.lambda[1]),
+            rgb_to_spectrum(rgb, self.lambda[2]),
+        )
+    }
+
+    /// Linear sRGB of spectral radiance sampled at these wavelengths.
+    pub fn to_rgb(&self, radiance: Vec3) -> Colour {
+        let values = [radiance.x, radiance.y, radiance.z];
+        let mut xyz = Vec3::default();
+        for (l, value) in self.lambda.iter().zip(values) {
+            // Each wavelength has pdf 1 / (LAMBDA_MAX - LAMBDA_MIN), which the normalisation
+            // of the matching functions takes care of.
+            xyz += value * cie_xyz_normalised(*l);
+        }
+        xyz_to_srgb(xyz / N_WAVELENGTHS as f64)
+    }
+}
+
+/// A piecewise Gaussian with different widths either side of its peak.
+fn lobe(lambda: f64, mu: f64, sigma_below: f64, sigma_above: f64) -> f64 {
+    let sigma = if lambda < mu {
+        sigma_below
+    } else {
+        sigma_above
+    };
+    let t = (lambda - mu) / sigma;
+    (-0.5 * t * t).exp()
+}
+
+/// The CIE 1931 2° colour matching functions, from the multi-lobe fit of Wyman et al., "Simple
+/// Analytic Approximations to the CIE XYZ Color Matching Functions", 2013.
+pub fn cie_xyz(lambda: f64) -> Vec3 {
+    Vec3::new(
+        1.056 * lobe(lambda, 599.8, 37.9, 31.0) + 0.362 * lobe(lambda, 442.0, 16.0, 26.7)
+            - 0.065 * lobe(lambda, 501.1, 20.4, 26.2),
+        0.821 * lobe(lambda, 568.8, 46.9, 40.5) + 0.286 * lobe(lambda, 530.9, 16.3, 31.1),
+        1.217 * lobe(lambda, 437.0, 11.8, 36.0) + 0.681 * lobe(lambda, 459.0, 26.0, 13.8),
+    )
+}
+
+/// Each matching function's mean over the sampled range, so that a spectrum of constant 1 comes
+/// out as XYZ (1, 1, 1), the equal energy white.
+static CIE_MEANS: LazyLock<Vec3> = LazyLock::new(|| {
+    let steps = 4000;
+    let step = (LAMBDA_MAX - LAMBDA_MIN) / steps as f64;
+    let mut total = Vec3::default();
+    for i in 0..steps {
+        total += cie_xyz(LAMBDA_MIN + (i as f64 + 0.5) * step);
+    }
+    total / steps as f64
+});
+
+fn cie_xyz_normalised(lambda: f64) -> Vec3 {
+    cie_xyz(lambda) / *CIE_MEANS
+}
+
+type Matrix = [[f64; 3]; 3];
+
+fn multiply(m: &Matrix, v: Vec3) -> Vec3 {
+    Vec3::new(
+        m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+        m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+        m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+    )
+}
+
+/// XYZ (D65 white) to linear sRGB.
+const XYZ_TO_SRGB: Matrix = [
+    [3.2404542, -1.5371385, -0.4985314],
+    [-0.9692660, 1.8760108, 0.0415560],
+    [0.0556434, -0.2040259, 1.0572252],
+];
+
+/// The Bradford cone response matrix and its inverse, for chromatic adaptation.
+const BRADFORD: Matrix = [
+    [0.8951, 0.2664, -0.1614],
+    [-0.7502, 1.7135, 0.0367],
+    [0.0389, -0.0685, 1.0296],
+];
+const BRADFORD_INVERSE: Matrix = [
+    [0.9869929, -0.1470543, 0.1599627],
+    [0.4323053, 0.5183603, 0.0492912],
+    [-0.0085287, 0.0400428, 0.9684867],
+];
+
+const D65_WHITE: Vec3 = Vec3 {
+    x: 0.95047,
+    y: 1.0,
+    z: 1.08883,
+};
+
+/// Linear sRGB from XYZ relative to the equal energy white. RGB colours are upsampled to spectra
+/// whose white is flat, so the scene is adapted from that white to sRGB's D65 to keep white
+/// surfaces white.
+fn xyz_to_srgb(xyz: Vec3) -> Colour {
+    let cone = multiply(&BRADFORD, xyz);
+    let e_white = multiply(&BRADFORD, Vec3::new(1.0, 1.0, 1.0));
+    let d65_white = multiply(&BRADFORD, D65_WHITE);
+    let adapted = cone * (d65_white / e_white);
+    multiply(&XYZ_TO_SRGB, multiply(&BRADFORD_INVERSE, adapted))
+}
+
+/// Linear sRGB from XYZ as measured, with no chromatic adaptation, so that light with
+/// chromaticity other than D65 keeps its tint.
+pub(crate) fn xyz_d65_to_srgb(xyz: Vec3) -> Colour {
+    multiply(&XYZ_TO_SRGB, xyz)
+}
+
+/// Basis spectra from Smits, "An RGB-to-Spectrum Conversion for Reflectances", 1999, sampled at
+/// ten wavelengths evenly spaced from 380 to 720 nm.
+const SMITS_WHITE: [f64; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const SMITS_CYAN: [f64; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const SMITS_MAGENTA: [f64; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const SMITS_YELLOW: [f64; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [f64; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f64; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const SMITS_BLUE: [f64; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+/// Linearly interpolate one of Smits' basis spectra, holding the end values outside its range.
+fn smits(basis: &[f64; 10], lambda: f64) -> f64 {
+    let x = ((lambda - 380.0) / (720.0 - 380.0) * 9.0).clamp(0.0, 9.0);
+    let i = (x as usize).min(8);
+    let t = x - i as f64;
+    basis[i] * (1.0 - t) + basis[i + 1] * t
+}
+
+/// The value at `lambda` of a smooth spectrum that looks like `rgb`, built from white plus
+/// Smits' primary and secondary basis spectra.
+pub fn rgb_to_spectrum(rgb: Colour, lambda: f64) -> f64 {
+    let (r, g, b) = (rgb.x, rgb.y, rgb.z);
+    let s = |basis: &[f64; 10]| smits(basis, lambda);
+    if r <= g && r <= b {
+        r * s(&SMITS_WHITE)
+            + if g <= b {
+                (g - r) * s(&SMITS_CYAN) + (b - g) * s(&SMITS_BLUE)
+            } else {
+                (b - r) * s(&SMITS_CYAN) + (g - b) * s(&SMITS_GREEN)
+            }
+    } else if g <= r && g <= b {
+        g * s(&SMITS_WHITE)
+            + if r <= b {
+                (r - g) * s(&SMITS_MAGENTA) + (b - r) * s(&SMITS_BLUE)
+            } else {
+                (b - g) * s(&SMITS_MAGENTA) + (r - b) * s(&SMITS_RED)
+            }
+    } else {
+        b * s(&SMITS_WHITE)
+            + if r <= g {
+                (r - b) * s(&SMITS_YELLOW) + (g - r) * s(&SMITS_GREEN)
+            } else {
+                (g - b) * s(&SMITS_YELLOW) + (r - g) * s(&SMITS_RED)
+            }
+    }
+}
+
+/// A refractive index that may vary with wavelength.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ior {
+    Constant(f64),
+    /// n = a + b / λ², with λ in micrometres.
+    Cauchy {
+        a: f64,
+        b: f64,
+    },
+    /// n² = 1 + Σ bᵢ λ² / (λ² - cᵢ), with λ in micrometres.
+    Sellmeier {
+        b: [f64; 3],
+        c: [f64; 3],
+    },
+}
+
+impl Ior {
+    /// The wavelength indices are usually quoted at (the helium d line), used when rendering in
+    /// RGB.
+    pub const REFERENCE_WAVELENGTH: f64 = 587.6;
+
+    /// Schott N-BK7, a common borosilicate crown glass.
+    pub const BK7: Ior = Ior::Sellmeier {
+        b: [1.03961212, 0.231792344, 1.01046945],
+        c: [0.00600069867, 0.0200179144, 103.560653],
+    };
+
+    /// The index at `lambda` nanometres.
+    pub fn at(&self, lambda: f64) -> f64 {
+        let micrometres = lambda / 1000.0;
+        let l2 = micrometres * micrometres;
+        match *self {
+            Ior::Constant(n) => n,
+            Ior::Cauchy { a, b } => a + b / l2,
+            Ior::Sellmeier { b, c } => {
+                let sum: f64 = b.iter().zip(c).map(|(b, c)| b * l2 / (l2 - c)).sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, Ior::Constant(_))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    /// Average `to_rgb` over many evenly spread wavelength samples.
+    fn integrate(spectrum: impl Fn(f64) -> f64) -> Colour {
+        let n = 2000;
+        let mut total = Colour::default();
+        for i in 0..n {
+            let wavelengths = SampledWavelengths::sample((i as f64 + 0.5) / n as f64);
+            let l = wavelengths.lambda;
+            let radiance = Vec3::new(spectrum(l[0]), spectrum(l[1]), spectrum(l[2]));
+            total += wavelengths.to_rgb(radiance);
+        }
+        total / n as f64
+    }
+
+    fn assert_close(a: Colour, b: Colour, tolerance: f64) {
+        assert!((a - b).length() < tolerance, "{a:?} != {b:?}");
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_sampled_wavelengths_are_spread_across_range() {
+        let wavelengths = SampledWavelengths::sample(0.9);
+        for l in wavelengths.lambda {
+            assert!((LAMBDA_MIN..LAMBDA_MAX).contains(&l));
+        }
+        let mut sorted = wavelengths.lambda;
+        sorted.sort_by(f64::total_cmp);
+        let spacing = (LAMBDA_MAX - LAMBDA_MIN) / 3.0;
+        assert!((sorted[1] - sorted[0] - spacing).abs() < 1e-9);
+        assert!((sorted[2] - sorted[1] - spacing).abs() < 1e-9);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_flat_spectrum_is_white() {
+        assert_close(integrate(|_| 1.0), Colour::new(1.0, 1.0, 1.0), 1e-3);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case(Colour::new(1.0, 1.0, 1.0), 0.02)]
+    #[case(Colour::new(0.5, 0.5, 0.5), 0.01)]
+    #[case(Colour::new(0.8, 0.3, 0.1), 0.15)]
+    #[case(Colour::new(0.1, 0.6, 0.2), 0.15)]
+    #[case(Colour::new(0.2, 0.3, 0.9), 0.15)]
+    fn test_upsampled_colours_round_trip(#[case] rgb: Colour, #[case] tolerance: f64) {
+        let round_trip = integrate(|l| rgb_to_spectrum(rgb, l));
+        assert_close(round_trip, rgb, tolerance);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_terminating_secondaries_keeps_hero_estimate() {
+        let mut wavelengths = SampledWavelengths::sample(0.3);
+        let radiance = Vec3::new(2.0, 5.0, 7.0);
+        let weight = wavelengths.terminate_secondary();
+        let hero_only = wavelengths.to_rgb(radiance * weight);
+        let hero = wavelengths.hero();
+        assert_close(
+            hero_only,
+            xyz_to_srgb(2.0 * cie_xyz_normalised(hero)),
+            1e-12,
+        );
+        // A second termination mustn't scale the hero up again.
+        assert_eq!(wavelengths.terminate_secondary(), Vec3::new(1.0, 1.0, 1.0));
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case(486.1, 1.5224)]
+    #[case(587.6, 1.5168)]
+    #[case(656.3, 1.5143)]
+    fn test_bk7_sellmeier(#[case] lambda: f64, #[case] want: f64) {
+        assert!((Ior::BK7.at(lambda) - want).abs() < 1e-4);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_cauchy_disperses_blue_more() {
+        let ior = Ior::Cauchy {
+            a: 1.5046,
+            b: 0.0042,
+        };
+        assert!(ior.at(450.0) > ior.at(650.0));
+        assert!(ior.is_dispersive());
+        assert!(!Ior::Constant(1.5).is_dispersive());
+    }
+}
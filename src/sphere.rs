@@ -1,12 +1,18 @@
+use std::f64::consts::PI;
 use std::ops::Range;
+use std::sync::Arc;
 
-use crate::hit::{HitRecord, Hittable};
+use crate::hit::{AlphaMask, HitRecord, Hittable};
+use crate::material::{DEFAULT_MATERIAL, Material};
 use crate::{Point3, Ray, Vec3, dot};
 
 #[derive(Debug)]
 pub struct Sphere {
     pub centre: Point3,
     pub radius: f64,
+    pub material: Arc<dyn Material>,
+    /// Holes cut in the surface, which rays pass through.
+    pub alpha: Option<AlphaMask>,
 }
 
 impl Sphere {
@@ -14,8 +20,46 @@ impl Sphere {
         Self {
             centre,
             radius: radius.max(0.0),
+            material: DEFAULT_MATERIAL.clone(),
+            alpha: None,
         }
     }
+
+    /// Surface coordinates of a point on the unit sphere: u is the angle around the Y axis from
+    /// X = -1, and v the angle from Y = -1 to Y = +1, both scaled to [0, 1].
+    pub fn uv(p: &Point3) -> (f64, f64) {
+        let theta = (-p.y).clamp(-1.0, 1.0).acos();
+        let phi = (-p.z).atan2(p.x) + PI;
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    /// Tangents along u and v of the sphere with radius `radius` at the point whose unit
+    /// outward normal is `n`, matching `uv`. At the poles, where v's tangent isn't defined, any
+    /// tangent perpendicular to u's is used.
+    pub fn tangents(n: &Vec3, radius: f64) -> (Vec3, Vec3) {
+        let dpdu = 2.0 * PI * radius * Vec3::new(n.z, 0.0, -n.x);
+        let sin_theta = (n.x * n.x + n.z * n.z).sqrt();
+        if sin_theta < 1e-9 {
+            return (
+                Vec3::new(0.0, 0.0, -2.0 * PI * radius),
+                Vec3::new(PI * radius, 0.0, 0.0),
+            );
+        }
+        let dpdv =
+            PI * radius * Vec3::new(-n.y * n.x / sin_theta, sin_theta, -n.y * n.z / sin_theta);
+        (dpdu, dpdv)
+    }
+
+    /// The sphere's material. Defaults to a grey `Diffuse`.
+    pub fn with_material(mut self, material: Arc<dyn Material>) -> Self {
+        self.material = material;
+        self
+    }
+
+    pub fn with_alpha(mut self, alpha: AlphaMask) -> Self {
+        self.alpha = Some(alpha);
+        self
+    }
 }
 
 impl Hittable for Sphere {
@@ -30,36 +74,53 @@ impl Hittable for Sphere {
             return None;
         }
 
+        // The nearer root, unless it's out of range or cut away, when the ray goes on through
+        // to the far side.
         let sqrtd = discriminant.sqrt();
-        let mut root = (h - sqrtd) / a;
-        if !interval.contains(&root) {
-            root = (h + sqrtd) / a;
-            if !interval.contains(&root) {
-                return None;
-            }
-        }
-
-        let t = root;
+        let t = [(h - sqrtd) / a, (h + sqrtd) / a]
+            .into_iter()
+            .filter(|root| interval.contains(root))
+            .find(|&root| {
+                self.alpha.as_ref().is_none_or(|alpha| {
+                    let (u, v) = Sphere::uv(&((ray.at(root) - self.centre) / self.radius));
+                    alpha.is_opaque(ray, root, u, v)
+                })
+            })?;
         let p = ray.at(t);
         let outward_normal = (p - self.centre) / self.radius;
+        let (u, v) = Sphere::uv(&outward_normal);
+        let (dpdu, dpdv) = Sphere::tangents(&outward_normal, self.radius);
 
         let mut rec = HitRecord {
             t,
             p,
+            u,
+            v,
             normal: outward_normal,
+            geometric_normal: outward_normal,
+            dpdu,
+            dpdv,
             front_face: false, // placeholder
+            material: self.material.clone(),
+            object_id: 0,
+            material_id: 0,
         };
 
         rec.set_face_normal(ray, &outward_normal);
 
         Some(rec)
     }
+
+    fn material(&self) -> Option<&Arc<dyn Material>> {
+        Some(&self.material)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{Point3, Ray, Vec3};
+    use crate::texture::{Checker, SolidColour};
+    use crate::{Colour, Point3, Ray, Vec3, unit_vector};
     use rstest::rstest;
 
     fn almost_eq(a: f64, b: f64) -> bool {
@@ -78,6 +139,55 @@ mod tests {
         assert_eq!(s.radius, 5.0);
     }
 
+    #[rstest]
+    #[case(Point3::new(1.0, 0.0, 0.0), (0.5, 0.5))]
+    #[case(Point3::new(0.0, 1.0, 0.0), (0.5, 1.0))]
+    #[case(Point3::new(0.0, -1.0, 0.0), (0.5, 0.0))]
+    #[case(Point3::new(-1.0, 0.0, 0.0), (0.0, 0.5))]
+    #[case(Point3::new(0.0, 0.0, 1.0), (0.25, 0.5))]
+    #[case(Point3::new(0.0, 0.0, -1.0), (0.75, 0.5))]
+    fn test_uv(#[case] p: Point3, #[case] want: (f64, f64)) {
+        let (u, v) = Sphere::uv(&p);
+        assert!(almost_eq(u, want.0) && almost_eq(v, want.1), "{:?}", (u, v));
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_alpha_mask_lets_rays_through() {
+        let solid = |alpha| Arc::new(SolidColour::new(Colour::new(alpha, alpha, alpha)));
+        // Opaque where z > 0 only.
+        let half = Arc::new(Checker {
+            scale: 10.0,
+            even: solid(1.0),
+            odd: solid(0.0),
+        });
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -3.0), Vec3::new(0.0, 0.0, 1.0));
+        let s =
+            Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0).with_alpha(AlphaMask::cutout(half, 0.5));
+        let rec = s.hit(&ray, &(0.001..f64::INFINITY)).unwrap();
+        assert!(almost_eq(rec.t, 4.0) && !rec.front_face);
+        let s = Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0)
+            .with_alpha(AlphaMask::stochastic(solid(0.0)));
+        assert!(s.hit(&ray, &(0.001..f64::INFINITY)).is_none());
+    }
+
+    #[rstest]
+    #[case(Vec3::new(0.6, 0.0, 0.8))]
+    #[case(Vec3::new(-0.48, 0.6, -0.64))]
+    #[case(Vec3::new(0.0, -0.28, 0.96))]
+    fn test_tangents_follow_uv(#[case] n: Vec3) {
+        let radius = 2.0;
+        let (u, v) = Sphere::uv(&n);
+        let (dpdu, dpdv) = Sphere::tangents(&n, radius);
+        assert!(almost_eq(dot(&dpdu, &n), 0.0) && almost_eq(dot(&dpdv, &n), 0.0));
+        // Stepping along each tangent moves the surface coordinates along just that one.
+        let eps = 1e-6;
+        let (u1, v1) = Sphere::uv(&unit_vector(&(n + eps * dpdu / radius)));
+        assert!((u1 - u - eps).abs() < 1e-9 && (v1 - v).abs() < 1e-9);
+        let (u2, v2) = Sphere::uv(&unit_vector(&(n + eps * dpdv / radius)));
+        assert!((u2 - u).abs() < 1e-9 && (v2 - v - eps).abs() < 1e-9);
+    }
+
     #[rstest]
     #[case(
         // Ray hits center
@@ -0,0 +1,127 @@
+use std::fmt;
+use std::time::Duration;
+
+/// Counters and timings collected while rendering.
+///
+/// Each render thread counts into its own copy for the tile it's working on, and those are merged
+/// on the calling thread along with the tile's film, so counting costs a few integer adds per ray
+/// and no synchronisation.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RenderStats {
+    /// Rays leaving the camera, one per sample.
+    pub camera_rays: u64,
+    /// Every ray traced: camera rays and each bounce after them.
+    pub rays: u64,
+    /// Ray-object intersection tests. The scene is a flat list, so each ray tests every object.
+    pub primitive_tests: u64,
+    /// The most rays traced for a single camera sample.
+    pub max_path_depth: u64,
+    pub phases: PhaseTimes,
+}
+
+/// Wall clock time spent in each phase of a render.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PhaseTimes {
+    /// Rendering passes, from starting the threads to the last tile coming back.
+    pub render: Duration,
+    /// Merging tiles into the film. This happens while other tiles are still rendering, so it
+    /// overlaps `render`.
+    pub merge: Duration,
+    /// Deciding which pixels need more samples, and measuring noise against the budget.
+    pub analysis: Duration,
+    /// Running the caller's per-pass callback, e.g. to write images or checkpoints.
+    pub callbacks: Duration,
+}
+
+impl RenderStats {
+    /// Add the counts from `other`, e.g. another tile's, to these.
+    pub fn merge(&mut self, other: &RenderStats) {
+        self.camera_rays += other.camera_rays;
+        self.rays += other.rays;
+        self.primitive_tests += other.primitive_tests;
+        self.max_path_depth = self.max_path_depth.max(other.max_path_depth);
+        self.phases.render += other.phases.render;
+        self.phases.merge += other.phases.merge;
+        self.phases.analysis += other.phases.analysis;
+        self.phases.callbacks += other.phases.callbacks;
+    }
+
+    /// Rays traced per camera sample.
+    pub fn mean_path_depth(&self) -> f64 {
+        if self.camera_rays == 0 {
+            return 0.0;
+        }
+        self.rays as f64 / self.camera_rays as f64
+    }
+
+    /// Rays traced per second spent rendering.
+    pub fn rays_per_second(&self) -> f64 {
+        let seconds = self.phases.render.as_secs_f64();
+        if seconds == 0.0 {
+            return 0.0;
+        }
+        self.rays as f64 / seconds
+    }
+}
+
+impl fmt::Display for RenderStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Camera rays:      {}", self.camera_rays)?;
+        writeln!(
+            f,
+            "Total rays:       {} ({:.2} M/s)",
+            self.rays,
+            self.rays_per_second() / 1e6
+        )?;
+        writeln!(f, "Primitive tests:  {}", self.primitive_tests)?;
+        writeln!(
+            f,
+            "Path depth:       {:.2} mean, {} max",
+            self.mean_path_depth(),
+            self.max_path_depth
+        )?;
+        writeln!(f, "Render time:      {:.3?}", self.phases.render)?;
+        writeln!(f, "  merging tiles:  {:.3?}", self.phases.merge)?;
+        writeln!(f, "Analysis time:    {:.3?}", self.phases.analysis)?;
+        write!(f, "Callback time:    {:.3?}", self.phases.callbacks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_merge_adds_counts_and_keeps_max_depth() {
+        let mut stats = RenderStats {
+            camera_rays: 2,
+            rays: 5,
+            primitive_tests: 10,
+            max_path_depth: 3,
+            ..RenderStats::default()
+        };
+        stats.merge(&RenderStats {
+            camera_rays: 2,
+            rays: 3,
+            primitive_tests: 6,
+            max_path_depth: 2,
+            ..RenderStats::default()
+        });
+
+        assert_eq!(stats.camera_rays, 4);
+        assert_eq!(stats.rays, 8);
+        assert_eq!(stats.primitive_tests, 16);
+        assert_eq!(stats.max_path_depth, 3);
+        assert_eq!(stats.mean_path_depth(), 2.0);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_rates_are_zero_without_work() {
+        let stats = RenderStats::default();
+        assert_eq!(stats.mean_path_depth(), 0.0);
+        assert_eq!(stats.rays_per_second(), 0.0);
+    }
+}
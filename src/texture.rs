@@ -0,0 +1,115 @@
+//! Values that vary over a surface, for driving material parameters.
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use crate::hit::HitRecord;
+use crate::image::Image;
+use crate::{Colour, Point3};
+
+/// A colour at each point of a surface, looked up by the hit's (u, v) coordinates or position.
+pub trait Texture: Send + Sync + Debug {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Colour;
+
+    /// The value at a hit.
+    fn at(&self, rec: &HitRecord) -> Colour {
+        self.value(rec.u, rec.v, &rec.p)
+    }
+}
+
+/// The same colour everywhere.
+#[derive(Debug, Clone, Copy)]
+pub struct SolidColour {
+    pub colour: Colour,
+}
+
+impl SolidColour {
+    pub fn new(colour: Colour) -> Self {
+        Self { colour }
+    }
+}
+
+impl Texture for SolidColour {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Colour {
+        self.colour
+    }
+}
+
+/// A checkerboard in space, alternating between two textures in cubes `scale` across.
+#[derive(Debug, Clone)]
+pub struct Checker {
+    pub scale: f64,
+    pub even: Arc<dyn Texture>,
+    pub odd: Arc<dyn Texture>,
+}
+
+impl Texture for Checker {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Colour {
+        let cell = |x: f64| (x / self.scale).floor() as i64;
+        if (cell(p.x) + cell(p.y) + cell(p.z)) % 2 == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+/// An image wrapped around a surface by its (u, v) coordinates.
+#[derive(Debug, Clone)]
+pub struct ImageTexture {
+    pub image: Arc<Image>,
+}
+
+impl ImageTexture {
+    pub fn new(image: Arc<Image>) -> Self {
+        Self { image }
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Point3) -> Colour {
+        self.image.bilinear(u, v)
+    }
+}
+
+/// A number that's either fixed or read from the first channel of a texture.
+#[derive(Debug, Clone)]
+pub enum Scalar {
+    Value(f64),
+    Texture(Arc<dyn Texture>),
+}
+
+impl Scalar {
+    pub fn at(&self, rec: &HitRecord) -> f64 {
+        match self {
+            Scalar::Value(value) => *value,
+            Scalar::Texture(texture) => texture.at(rec).x,
+        }
+    }
+}
+
+impl From<f64> for Scalar {
+    fn from(value: f64) -> Self {
+        Scalar::Value(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_checker_alternates() {
+        let checker = Checker {
+            scale: 0.5,
+            even: Arc::new(SolidColour::new(Colour::new(1.0, 1.0, 1.0))),
+            odd: Arc::new(SolidColour::new(Colour::default())),
+        };
+        let at = |x, y, z| checker.value(0.0, 0.0, &Point3::new(x, y, z)).x;
+        assert_eq!(at(0.1, 0.1, 0.1), 1.0);
+        assert_eq!(at(0.6, 0.1, 0.1), 0.0);
+        assert_eq!(at(0.6, 0.6, 0.1), 1.0);
+        assert_eq!(at(-0.1, 0.1, 0.1), 0.0);
+    }
+}
@@ -0,0 +1,211 @@
+//! Interference in thin coatings, which gives soap bubbles and oil slicks their colours.
+use std::f64::consts::PI;
+use std::ops::{Add, Div, Mul, Sub};
+
+use crate::Colour;
+use crate::spectrum::{SampledWavelengths, rgb_to_spectrum};
+
+/// The wavelength, in nanometres, each RGB channel stands for when rendering in RGB.
+pub const RGB_WAVELENGTHS: [f64; 3] = [630.0, 532.0, 465.0];
+
+/// Just enough complex arithmetic for Fresnel amplitudes, which go complex past the critical
+/// angle and in conductors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+
+    fn norm_squared(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    /// The principal square root, with a non-negative real part.
+    fn sqrt(self) -> Self {
+        let modulus = self.norm_squared().sqrt();
+        let re = ((modulus + self.re) / 2.0).max(0.0).sqrt();
+        let im = ((modulus - self.re) / 2.0).max(0.0).sqrt();
+        Self::new(re, if self.im < 0.0 { -im } else { im })
+    }
+
+    /// e^(i self).
+    fn exp_i(self) -> Self {
+        let magnitude = (-self.im).exp();
+        Self::new(magnitude * self.re.cos(), magnitude * self.re.sin())
+    }
+}
+
+impl From<f64> for Complex {
+    fn from(re: f64) -> Self {
+        Self::new(re, 0.0)
+    }
+}
+
+impl Add for Complex {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Self;
+    fn div(self, rhs: Self) -> Self {
+        let denominator = rhs.norm_squared();
+        Self::new(
+            (self.re * rhs.re + self.im * rhs.im) / denominator,
+            (self.im * rhs.re - self.re * rhs.im) / denominator,
+        )
+    }
+}
+
+/// The cosine of the angle to the normal in a medium of index `n`, for light arriving from a
+/// medium of index `n_i` at `sin2_i`.
+fn cos_in(n_i: f64, n: Complex, sin2_i: f64) -> Complex {
+    let ratio = Complex::from(n_i) / n;
+    (Complex::from(1.0) - ratio * ratio * Complex::from(sin2_i)).sqrt()
+}
+
+/// Amplitude reflection coefficients (s and p polarised) of a boundary.
+fn amplitudes(n_i: Complex, cos_i: Complex, n_t: Complex, cos_t: Complex) -> (Complex, Complex) {
+    let s = (n_i * cos_i - n_t * cos_t) / (n_i * cos_i + n_t * cos_t);
+    let p = (n_t * cos_i - n_i * cos_t) / (n_t * cos_i + n_i * cos_t);
+    (s, p)
+}
+
+/// A thin transparent film on a surface. Light reflected off its top and bottom interferes,
+/// strengthening some wavelengths and cancelling others depending on the thickness and angle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThinFilm {
+    /// In nanometres; a few hundred gives the strongest colours.
+    pub thickness: f64,
+    pub ior: f64,
+}
+
+impl ThinFilm {
+    pub fn new(thickness: f64, ior: f64) -> Self {
+        Self { thickness, ior }
+    }
+
+    /// Reflectance, for unpolarised light of `lambda` nanometres arriving at `cos_i` to the
+    /// normal from a medium of index `n_i`, of the film over a substrate of complex index
+    /// `eta_t + i k_t`.
+    pub fn reflectance(&self, cos_i: f64, n_i: f64, eta_t: f64, k_t: f64, lambda: f64) -> f64 {
+        let cos_i = cos_i.clamp(0.0, 1.0);
+        let sin2_i = 1.0 - cos_i * cos_i;
+        let n_film = Complex::from(self.ior);
+        let n_t = Complex::new(eta_t, k_t);
+        let cos_film = cos_in(n_i, n_film, sin2_i);
+        let cos_t = cos_in(n_i, n_t, sin2_i);
+        let (top_s, top_p) = amplitudes(n_i.into(), cos_i.into(), n_film, cos_film);
+        let (bottom_s, bottom_p) = amplitudes(n_film, cos_film, n_t, cos_t);
+        // The phase the light reflected off the bottom gains crossing the film and back.
+        let phase = Complex::from(4.0 * PI * self.ior * self.thickness / lambda) * cos_film;
+        let shift = phase.exp_i();
+        // The Airy sum over every number of trips across the film.
+        let airy = |top: Complex, bottom: Complex| {
+            ((top + bottom * shift) / (Complex::from(1.0) + top * bottom * shift)).norm_squared()
+        };
+        ((airy(top_s, bottom_s) + airy(top_p, bottom_p)) / 2.0).clamp(0.0, 1.0)
+    }
+
+    /// Reflectance of each RGB channel, at its representative wavelength, over a substrate whose
+    /// complex index may differ by channel.
+    pub fn reflectance_rgb(&self, cos_i: f64, n_i: f64, eta_t: Colour, k_t: Colour) -> Colour {
+        let [r, g, b] = RGB_WAVELENGTHS;
+        Colour::new(
+            self.reflectance(cos_i, n_i, eta_t.x, k_t.x, r),
+            self.reflectance(cos_i, n_i, eta_t.y, k_t.y, g),
+            self.reflectance(cos_i, n_i, eta_t.z, k_t.z, b),
+        )
+    }
+
+    /// Reflectance at a spectral path's hero wavelength, with the substrate's RGB index
+    /// upsampled to it. The other wavelengths would reflect differently, so the caller must
+    /// treat the result as for the hero alone.
+    pub fn reflectance_hero(
+        &self,
+        cos_i: f64,
+        n_i: f64,
+        eta_t: Colour,
+        k_t: Colour,
+        wavelengths: &SampledWavelengths,
+    ) -> f64 {
+        let lambda = wavelengths.hero();
+        self.reflectance(
+            cos_i,
+            n_i,
+            rgb_to_spectrum(eta_t, lambda),
+            rgb_to_spectrum(k_t, lambda),
+            lambda,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::microfacet::{fresnel_complex, fresnel_dielectric};
+    use rstest::rstest;
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    #[case(1.0)]
+    #[case(0.6)]
+    #[case(0.1)]
+    fn test_vanishing_film_is_plain_fresnel(#[case] cos_i: f64) {
+        let film = ThinFilm::new(0.0, 1.33);
+        let glass = film.reflectance(cos_i, 1.0, 1.5, 0.0, 550.0);
+        assert!((glass - fresnel_dielectric(cos_i, 1.5)).abs() < 1e-9);
+        let gold = film.reflectance(cos_i, 1.0, 0.143, 3.98, 550.0);
+        assert!((gold - fresnel_complex(cos_i, 0.143, 3.98)).abs() < 1e-9);
+        // Including from inside glass, past the critical angle.
+        let inside = film.reflectance(cos_i, 1.5, 1.0, 0.0, 550.0);
+        assert!((inside - fresnel_dielectric(cos_i, 1.0 / 1.5)).abs() < 1e-9);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_quarter_wave_coating_cancels_reflection() {
+        let (lambda, substrate) = (550.0, 1.5_f64);
+        let ior = substrate.sqrt();
+        let film = ThinFilm::new(lambda / (4.0 * ior), ior);
+        assert!(film.reflectance(1.0, 1.0, substrate, 0.0, lambda) < 1e-12);
+        // But not at other wavelengths.
+        assert!(film.reflectance(1.0, 1.0, substrate, 0.0, 420.0) > 1e-3);
+    }
+
+    #[test_log::test(rstest)]
+    #[rstest]
+    fn test_soap_film_is_iridescent() {
+        let film = ThinFilm::new(350.0, 1.33);
+        let one = Colour::new(1.0, 1.0, 1.0);
+        let head_on = film.reflectance_rgb(1.0, 1.0, one, Colour::default());
+        let grazing = film.reflectance_rgb(0.5, 1.0, one, Colour::default());
+        let spread = |c: Colour| c.x.max(c.y).max(c.z) - c.x.min(c.y).min(c.z);
+        assert!(spread(head_on) > 0.05, "{head_on:?}");
+        assert!((head_on - grazing).length() > 0.05);
+    }
+}